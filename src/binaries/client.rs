@@ -1,10 +1,43 @@
 use potion::prelude::*;
 
-fn main() {
+/// Usage: `client [server address] [password] [--name=name] [--latency=ms --loss=0.1 ...]`
+///
+/// See [`LinkConditioner::from_args`] for the network flags.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let conditioner =
+        LinkConditioner::from_args(std::env::args().filter(|arg| !arg.starts_with("--name=")))?;
+    let mut args = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"));
+    let server_addr = args
+        .next()
+        .unwrap_or_else(|| format!("127.0.0.1:{}", potion::network::PORT))
+        .parse()?;
+    let password = args.next();
+
+    let mut identity = PlayerIdentity::default();
+    if let Some(name) =
+        std::env::args().find_map(|arg| arg.strip_prefix("--name=").map(str::to_owned))
+    {
+        identity.profile.name = name;
+    }
+
     let mut app = App::new();
-    //app.insert_resource(sabi::Client);
     app.add_plugins(PotionCellarPlugin);
     app.add_plugins(PlayerInputPlugin);
+    app.add_plugins(NetworkPlugin);
+    app.add_plugins(NetworkUiPlugin);
+    app.add_systems(Startup, potion::maps::showcase::setup);
+
+    let mut client = NetworkClient::new(UdpTransport::bind("0.0.0.0:0")?, server_addr)
+        .with_identity(identity.clone());
+    if let Some(password) = password {
+        client = client.with_password(password);
+    }
+    app.insert_resource(client);
+    app.insert_resource(identity);
+    app.insert_resource(conditioner);
 
     app.run();
+    Ok(())
 }
@@ -55,7 +55,11 @@ fn spawn_multibody(mut commands: Commands) {
 */
 
 fn spawn_local_player(mut spawn_player: EventWriter<PlayerEvent>, _asset_server: Res<AssetServer>) {
-    spawn_player.send(PlayerEvent::Spawn { id: 1 });
-    spawn_player.send(PlayerEvent::SetupLocal { id: 1 });
+    spawn_player.send(PlayerEvent::Spawn {
+        id: LOCAL_CLIENT_ID,
+    });
+    spawn_player.send(PlayerEvent::SetupLocal {
+        id: LOCAL_CLIENT_ID,
+    });
     info!("spawning new player");
 }
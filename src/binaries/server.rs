@@ -1,44 +1,133 @@
-use potion::prelude::*;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
+use bevy::log::LogPlugin;
+use potion::{headless::HeadlessPlugin, network::config::ServerConfig, prelude::*};
+use serde::Serialize;
+
+/// Set by the SIGINT handler.
+#[derive(Resource, Clone, Default)]
+struct Interrupted(Arc<AtomicBool>);
+
+#[derive(Serialize)]
+struct StateDump {
+    tick: u64,
+    entities: u32,
+    players: Vec<PlayerDump>,
+}
+
+#[derive(Serialize)]
+struct PlayerDump {
+    id: ClientId,
+    name: String,
+    addr: Option<SocketAddr>,
+    translation: [f32; 3],
+}
+
+/// Usage: `server [config.toml|config.ron] [--latency=ms --loss=0.1 ...]`
+///
+/// See [`LinkConditioner::from_args`] for the flags.
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let conditioner = LinkConditioner::from_args(std::env::args())?;
+    let config = match std::env::args().skip(1).find(|arg| !arg.starts_with("--")) {
+        Some(path) => ServerConfig::load(path)?,
+        None => ServerConfig::default(),
+    };
+
     let mut app = App::new();
-    app.add_plugins(PotionCellarPlugin);
-    app.insert_resource(LockToggle::default());
-
-    /*
-       app.add_system(
-           bevy_fly_camera::camera_movement_system
-               .run_if(window_focused)
-               .label("player_fly_movement"),
-       )
-       .add_system(
-           bevy_fly_camera::mouse_motion_system
-               .run_in_state(MouseState::Locked)
-               .run_if(window_focused)
-               .label("player_mouse_input"),
-       );
-    */
-    /*
-       app.add_system(
-           toggle_mouse_lock
-               .run_if(window_focused)
-       )
-       .add_system(mouse_lock.run_if(window_focused).label("toggle_mouse_lock"));
-    */
-
-    app.add_systems(Startup, potion::maps::showcase::setup);
-
-    /*
-       #[cfg(feature = "public")]
-       let ip = sabi::protocol::public_ip()?;
-       #[cfg(not(feature = "public"))]
-       let ip = sabi::protocol::localhost_ip();
-
-       let new_server: bevy_renet::renet::RenetServer =
-           sabi::protocol::new_renet_server(ip, None, sabi::protocol::PORT)
-               .expect("could not make new server");
-       app.insert_resource(new_server);
-    */
+    app.add_plugins(HeadlessPlugin {
+        wait: Some(config.tick_duration()),
+    });
+    app.add_plugins(LogPlugin::default());
+    app.insert_resource(Time::<Fixed>::from_duration(config.tick_duration()));
+
+    if !potion::maps::add_headless(&mut app, &config.map) {
+        return Err(format!("unknown map `{}`", config.map).into());
+    }
+
+    app.add_plugins(NetworkPlugin);
+    app.insert_resource(config.lobby_settings());
+    let mut server =
+        NetworkServer::new(UdpTransport::bind(config.addr())?).with_max_clients(config.max_players);
+    if let Some(password) = &config.password {
+        server = server.with_password(password.clone());
+    }
+    info!(
+        "listening on {} with map `{}`, {} players max, {} ticks/s",
+        server.local_addr(),
+        config.map,
+        config.max_players,
+        config.tick_rate
+    );
+    app.insert_resource(server);
+    if conditioner.enabled {
+        info!("simulating network conditions: {:?}", conditioner);
+    }
+    app.insert_resource(conditioner);
+
+    let interrupted = Interrupted::default();
+    let flag = interrupted.0.clone();
+    ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst))?;
+    app.insert_resource(interrupted);
+    app.insert_resource(config);
+    app.add_systems(Last, shutdown_on_interrupt);
+
     app.run();
     Ok(())
 }
+
+fn shutdown_on_interrupt(world: &mut World) {
+    if !world.resource::<Interrupted>().0.load(Ordering::SeqCst) {
+        return;
+    }
+
+    info!("shutting down");
+
+    let players = world
+        .resource::<Lobby>()
+        .players
+        .iter()
+        .map(|(id, entity)| (*id, *entity))
+        .collect::<Vec<_>>();
+    let dump = StateDump {
+        tick: world.resource::<NetworkTick>().0,
+        entities: world.entities().len(),
+        players: players
+            .into_iter()
+            .map(|(id, entity)| PlayerDump {
+                id,
+                name: world
+                    .resource::<Lobby>()
+                    .members
+                    .get(&id)
+                    .map(|member| member.profile.name.clone())
+                    .unwrap_or_default(),
+                addr: world.resource::<NetworkServer>().client_addr(id),
+                translation: world
+                    .get::<Transform>(entity)
+                    .map(|transform| transform.translation.to_array())
+                    .unwrap_or_default(),
+            })
+            .collect(),
+    };
+
+    match ron::ser::to_string_pretty(&dump, Default::default()) {
+        Ok(dump) => {
+            info!("final state:\n{}", dump);
+            if let Some(path) = &world.resource::<ServerConfig>().dump_path {
+                if let Err(err) = std::fs::write(path, &dump) {
+                    error!("failed to write state dump to {:?}: {}", path, err);
+                }
+            }
+        }
+        Err(err) => error!("failed to serialize state dump: {}", err),
+    }
+
+    world.resource_mut::<NetworkServer>().disconnect_all();
+    world.send_event(AppExit::Success);
+}
@@ -79,7 +79,7 @@ impl RetainedGizmos {
 pub struct RetainedGizmoPlugin;
 impl Plugin for RetainedGizmoPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(RetainedGizmos::default());
+        app.init_resource::<RetainedGizmos>();
         app.add_systems(PostUpdate, retained_gizmos);
     }
 }
@@ -69,7 +69,7 @@ impl Plugin for DepositPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Value>();
 
-        app.add_systems(FixedUpdate, deposit);
+        app.add_systems(FixedUpdate, deposit.in_set(GameplaySet));
     }
 }
 
@@ -0,0 +1,170 @@
+//! Run the game logic without a window or GPU, mostly for tests and CI.
+//!
+//! ```ignore
+//! let mut app = HeadlessApp::new();
+//! app.spawn_player(1).step(60);
+//! ```
+use std::time::Duration;
+
+use bevy::{
+    app::{Plugins, ScheduleRunnerPlugin},
+    input::InputPlugin,
+    scene::ScenePlugin,
+    time::TimeUpdateStrategy,
+};
+
+use crate::prelude::*;
+
+/// [`PotionCorePlugin`] and everything it expects, without a window or GPU.
+#[derive(Default)]
+pub struct HeadlessPlugin {
+    /// Minimum time between updates when run with [`App::run`], as fast as possible if `None`.
+    pub wait: Option<Duration>,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let runner = match self.wait {
+            Some(wait) => ScheduleRunnerPlugin::run_loop(wait),
+            None => ScheduleRunnerPlugin::default(),
+        };
+
+        app.add_plugins(MinimalPlugins.set(runner)).add_plugins((
+            TransformPlugin,
+            HierarchyPlugin,
+            InputPlugin,
+            AssetPlugin::default(),
+            ScenePlugin,
+        ));
+
+        // Normally registered by the render plugins, but spawning code still
+        // creates handles for these.
+        app.init_asset::<Mesh>().init_asset::<StandardMaterial>();
+
+        app.add_plugins(PotionCorePlugin);
+    }
+}
+
+/// [`App`] with [`HeadlessPlugin`] where every update advances exactly one
+/// fixed tick of [`crate::TICK_RATE`].
+pub struct HeadlessApp {
+    app: App,
+    /// Startup has run and the clock has been primed.
+    ready: bool,
+}
+
+impl Default for HeadlessApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeadlessApp {
+    pub fn new() -> Self {
+        let mut app = App::new();
+        app.add_plugins(HeadlessPlugin::default());
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(crate::TICK_RATE));
+
+        Self { app, ready: false }
+    }
+
+    pub fn add_plugins<M>(&mut self, plugins: impl Plugins<M>) -> &mut Self {
+        self.app.add_plugins(plugins);
+        self
+    }
+
+    pub fn add_systems<M>(
+        &mut self,
+        schedule: impl bevy::ecs::schedule::ScheduleLabel,
+        systems: impl IntoSystemConfigs<M>,
+    ) -> &mut Self {
+        self.app.add_systems(schedule, systems);
+        self
+    }
+
+    /// Queue a [`PlayerEvent::Spawn`], the player exists after the next tick.
+    pub fn spawn_player(&mut self, id: u64) -> &mut Self {
+        self.app.world_mut().send_event(PlayerEvent::Spawn { id });
+        self
+    }
+
+    /// Run a single fixed tick.
+    pub fn tick(&mut self) -> &mut Self {
+        if !self.ready {
+            self.app.finish();
+            self.app.cleanup();
+
+            // The first update only starts the clock, no fixed tick happens.
+            self.app.update();
+            self.ready = true;
+        }
+
+        self.app.update();
+        self
+    }
+
+    /// Run `ticks` fixed ticks.
+    pub fn step(&mut self, ticks: usize) -> &mut Self {
+        for _ in 0..ticks {
+            self.tick();
+        }
+        self
+    }
+
+    pub fn app(&self) -> &App {
+        &self.app
+    }
+
+    pub fn app_mut(&mut self) -> &mut App {
+        &mut self.app
+    }
+
+    pub fn world(&self) -> &World {
+        self.app.world()
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        self.app.world_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_player() {
+        let mut app = HeadlessApp::new();
+        app.spawn_player(1).step(2);
+
+        let world = app.world_mut();
+        let players = world
+            .query::<&Player>()
+            .iter(world)
+            .map(|player| player.id)
+            .collect::<Vec<_>>();
+        assert_eq!(players, vec![1]);
+
+        let hands = world.query_filtered::<(), With<Hand>>().iter(world).count();
+        assert_eq!(hands, 2);
+    }
+
+    #[test]
+    fn player_falls() {
+        let mut app = HeadlessApp::new();
+        app.spawn_player(1).step(2);
+
+        let height = |app: &mut HeadlessApp| {
+            let world = app.world_mut();
+            world
+                .query_filtered::<&GlobalTransform, With<Player>>()
+                .single(world)
+                .translation()
+                .y
+        };
+
+        let start = height(&mut app);
+        app.step(30);
+        assert!(height(&mut app) < start);
+    }
+}
@@ -5,7 +5,8 @@ pub mod attach;
 pub mod debug;
 pub mod deposit;
 pub mod egui;
-//pub mod network;
+pub mod headless;
+pub mod network;
 pub mod maps;
 pub mod objects;
 pub mod physics;
@@ -15,8 +16,9 @@ pub mod traversal;
 
 pub mod prelude {
     pub use super::{
-        attach::Attach, debug::prelude::*, physics::prelude::*, player::prelude::*,
-        traversal::prelude::*, FixedSet, PotionCellarPlugin,
+        attach::Attach, debug::prelude::*, network::prelude::*, physics::prelude::*,
+        player::prelude::*, traversal::prelude::*, FixedSet, GameplaySet, PotionCellarPlugin,
+        PotionCorePlugin, PotionPresentationPlugin,
     };
 
     pub use bevy::{
@@ -51,12 +53,70 @@ pub enum FixedSet {
     Last,
 }
 
+/// Game rules like brewing, fire and potion effects, as opposed to movement
+/// and physics. Skipped while [`Resimulating`] so replaying ticks after a
+/// rollback doesn't do any of it a second time.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GameplaySet;
+
 pub const RAW_TICK_RATE: std::time::Duration = std::time::Duration::from_millis(16);
 pub const TICK_RATE: std::time::Duration = std::time::Duration::from_millis(16);
 pub const SUBSTEPS: usize = 16;
 
+/// Full game: [`PotionPresentationPlugin`] on top of [`PotionCorePlugin`].
 pub struct PotionCellarPlugin;
 impl Plugin for PotionCellarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(PotionPresentationPlugin);
+        app.add_plugins(PotionCorePlugin);
+    }
+}
+
+/// Game logic only, no window/rendering requirements.
+///
+/// Expects `MinimalPlugins` (or `DefaultPlugins`) plus transform, hierarchy,
+/// input, asset and scene plugins to already be added, see [`headless::HeadlessPlugin`].
+pub struct PotionCorePlugin;
+impl Plugin for PotionCorePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_duration(crate::TICK_RATE));
+        app.init_resource::<RetainedGizmos>();
+
+        app.configure_sets(
+            FixedUpdate,
+            (FixedSet::First, FixedSet::Update, FixedSet::Last).chain(),
+        );
+        app.configure_sets(
+            FixedUpdate,
+            GameplaySet.run_if(not(resource_exists::<Resimulating>)),
+        );
+
+        app.add_plugins(PlayerPlugin)
+            .add_plugins(attach::AttachPlugin)
+            .add_plugins(StorePlugin)
+            .add_plugins(DepositPlugin)
+            .add_plugins(HierarchyTraversalPlugin)
+            .add_plugins(InverseKinematicsPlugin)
+            .add_plugins(crate::objects::potion::PotionPlugin)
+            .add_plugins(crate::objects::ingredient::IngredientPlugin)
+            .add_plugins(crate::objects::cauldron::CauldronPlugin)
+            .add_plugins(crate::objects::stirrer::StirrerPlugin)
+            .add_plugins(crate::objects::mortar::MortarPlugin)
+            .add_plugins(crate::objects::heat::HeatPlugin)
+            .add_plugins(crate::objects::fire::FirePlugin)
+            //.add_plugins(TreesPlugin)
+            .add_plugins(PhysicsPlugin)
+            .add_plugins(crate::objects::EffectPlugin);
+
+        app.add_event::<AssetEvent<Mesh>>();
+
+        app.add_systems(Update, (update_level_collision, decomp_load));
+    }
+}
+
+/// Window, rendering and debug visualization.
+pub struct PotionPresentationPlugin;
+impl Plugin for PotionPresentationPlugin {
     fn build(&self, app: &mut App) {
         //app.insert_resource(bevy::ecs::schedule::ReportExecutionOrderAmbiguities);
         let default_res = (1000.0, 600.0);
@@ -99,7 +159,6 @@ impl Plugin for PotionCellarPlugin {
             //limiter: bevy_framepace::Limiter::Manual(crate::TICK_RATE),
         });
         */
-        app.insert_resource(Time::<Fixed>::from_duration(crate::TICK_RATE));
         //app.add_plugins(bevy_mod_component_mirror::RapierMirrorsPlugins);
         //app.add_plugins(bevy_framepace::FramepacePlugin);
         app.insert_resource(bevy::pbr::DirectionalLightShadowMap { size: 2 << 10 });
@@ -126,24 +185,9 @@ impl Plugin for PotionCellarPlugin {
             */
         }
 
-        app.configure_sets(
-            FixedUpdate,
-            (FixedSet::First, FixedSet::Update, FixedSet::Last).chain(),
-        );
-
         //app.add_plugins(bevy_framepace::FramepacePlugin);
         app.insert_resource(ClearColor(Color::rgb(0.04, 0.04, 0.3)))
-                .add_plugins(PlayerPlugin)
-                .add_plugins(attach::AttachPlugin,)
-                .add_plugins(StorePlugin,)
-                .add_plugins(DepositPlugin,)
-                .add_plugins(HierarchyTraversalPlugin,)
-                .add_plugins(InverseKinematicsPlugin,)
-                .add_plugins(crate::objects::potion::PotionPlugin,)
-                .add_plugins(crate::debug::DebugPlugin,)
-                //.add_plugins(TreesPlugin,)
-                .add_plugins(PhysicsPlugin,)
-                .add_plugins(crate::objects::EffectPlugin,)
+            .add_plugins(crate::debug::DebugPlugin)
             .add_plugins(RapierDebugRenderPlugin {
                 enabled: true,
                 style: Default::default(),
@@ -151,13 +195,15 @@ impl Plugin for PotionCellarPlugin {
             })
             .add_plugins(bevy::diagnostic::FrameTimeDiagnosticsPlugin);
 
-        app.add_event::<AssetEvent<Mesh>>();
-
         app.add_systems(Startup, fallback_camera);
 
         app.add_systems(
             Update,
-            (update_level_collision, active_cameras, decomp_load),
+            (
+                active_cameras,
+                crate::player::grab::auto_aim_debug_lines,
+                crate::objects::vine::sunflower_effect,
+            ),
         );
     }
 }
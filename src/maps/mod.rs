@@ -3,3 +3,20 @@ pub mod colliders;
 pub mod effects;
 pub mod puzzle;
 pub mod showcase;
+
+use bevy::prelude::*;
+
+/// Add a map by name without anything that needs a window, for the dedicated server.
+///
+/// Returns `false` if there is no such map.
+pub fn add_headless(app: &mut App, name: &str) -> bool {
+    match name {
+        "showcase" => app.add_systems(Startup, showcase::setup),
+        "base_test" => app.add_plugins(base_test::SetupPlugin),
+        "puzzle" => app.add_plugins(puzzle::SetupPlugin),
+        "colliders" => app.add_plugins(colliders::SetupPlugin),
+        _ => return false,
+    };
+
+    true
+}
@@ -4,6 +4,7 @@ use crate::{
     attach::Attach,
     objects::{
         cauldron::Ingredient,
+        mortar::Crushable,
         store::{SecurityCheck, StoreItem},
     },
     physics::{
@@ -139,7 +140,10 @@ pub fn potions(
         ))
         .insert(Storeable)
         .insert(RigidBodyBundle::dynamic())
-        .insert(crate::objects::potion::PotionBundle::default())
+        .insert(crate::objects::potion::PotionBundle {
+            kind: crate::objects::PotionKind::new(crate::objects::PotionKind::FROST),
+            ..default()
+        })
         .insert(crate::objects::potion::PotionColliderBundle::default())
         .insert(ColliderBundle {
             collider: Collider::cuboid(0.5, 0.5, 0.5),
@@ -161,7 +165,10 @@ pub fn potions(
             Name::new("potion 2"),
         ))
         .insert(Storeable)
-        .insert(crate::objects::potion::PotionBundle::default())
+        .insert(crate::objects::potion::PotionBundle {
+            kind: crate::objects::PotionKind::new(crate::objects::PotionKind::EXPLODE),
+            ..default()
+        })
         .insert(crate::objects::potion::PotionColliderBundle::default())
         .insert(RigidBodyBundle::dynamic())
         .insert(ColliderBundle {
@@ -175,6 +182,7 @@ pub fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
     commands
         .spawn((
@@ -244,6 +252,41 @@ pub fn setup(
         &mut meshes,
     );
 
+    // The cauldron lands on it.
+    let _campfire = crate::objects::heat::spawn_campfire(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        Transform::from_xyz(-5.0, 0.1, 0.0),
+    );
+
+    // Something for explosion potions to blow open.
+    let brick = meshes.add(Cuboid::new(0.5, 0.5, 0.5));
+    for row in 0..4 {
+        for column in 0..6 {
+            commands
+                .spawn(PbrBundle {
+                    mesh: brick.clone(),
+                    transform: Transform::from_xyz(
+                        8.0,
+                        0.25 + row as f32 * 0.5,
+                        -1.25 + column as f32 * 0.5,
+                    ),
+                    ..default()
+                })
+                .insert(RigidBodyBundle::fixed())
+                .insert(ColliderBundle {
+                    collider: Collider::cuboid(0.25, 0.25, 0.25),
+                    collision_groups: crate::physics::TERRAIN_GROUPING,
+                    ..default()
+                })
+                .insert((
+                    Name::new("Brick"),
+                    crate::objects::explode::Destructible::default(),
+                ));
+        }
+    }
+
     crate::deposit::spawn_deposit_box(
         &mut commands,
         &*asset_server,
@@ -300,7 +343,8 @@ pub fn setup(
             ..default()
         })
         .insert((
-            Ingredient,
+            Ingredient::new("stone"),
+            Crushable::new("stone_dust"),
             crate::deposit::Value::new(1),
             StoreItem,
             Slottable::default(),
@@ -324,7 +368,8 @@ pub fn setup(
             ..default()
         })
         .insert((
-            Ingredient,
+            Ingredient::new("stone"),
+            Crushable::new("stone_dust"),
             crate::deposit::Value::new(1),
             StoreItem,
             Slottable::default(),
@@ -476,7 +521,7 @@ pub fn setup(
         .insert(RigidBodyBundle::dynamic())
         .insert(ColliderBundle::collider(Collider::ball(ball_radius)))
         .insert((
-            Ingredient,
+            Ingredient::new("ball"),
             crate::deposit::Value::new(5),
             Name::new("Ball"),
             Slottable::default(),
@@ -500,7 +545,7 @@ pub fn setup(
             0.025, 0.4, 0.1,
         )))
         .insert((
-            Ingredient,
+            Ingredient::new("donut"),
             crate::deposit::Value::new(5),
             Name::new("Donut 2"),
             Slottable::default(),
@@ -522,7 +567,7 @@ pub fn setup(
         .insert(RigidBodyBundle::dynamic())
         .insert(ColliderBundle::collider(Collider::cylinder(0.025, 0.4)))
         .insert((
-            Ingredient,
+            Ingredient::new("donut"),
             crate::deposit::Value::new(5),
             Name::new("Donut"),
             Slottable::default(),
@@ -542,7 +587,7 @@ pub fn setup(
         .insert(RigidBodyBundle::dynamic())
         .insert(ColliderBundle::collider(Collider::cuboid(0.3, 0.3, 0.3)))
         .insert((
-            Ingredient,
+            Ingredient::new("prallet"),
             crate::deposit::Value::new(1),
             Name::new("Prallet"),
             Slottable::default(),
@@ -562,7 +607,7 @@ pub fn setup(
         .insert(RigidBodyBundle::dynamic())
         .insert(ColliderBundle::collider(Collider::cuboid(0.3, 0.3, 0.3)))
         .insert((
-            Ingredient,
+            Ingredient::new("thorns"),
             crate::deposit::Value::new(1),
             StoreItem,
             Slottable::default(),
@@ -586,7 +631,8 @@ pub fn setup(
             ..default()
         })
         .insert((
-            Ingredient,
+            Ingredient::new("weltberry"),
+            Crushable::new("weltberry_mash"),
             Slottable::default(),
             crate::deposit::Value::new(1),
             Name::new("Weltberry"),
@@ -626,9 +672,6 @@ pub fn setup(
             }),
         ));
 
-    let level_collision_mesh3: Handle<Mesh> =
-        asset_server.load("models/cauldron_stirrer.glb#Mesh0/Primitive0");
-
     let _mock = commands
         .spawn(PbrBundle {
             mesh: meshes.add(Mesh::from(Sphere::new(0.0))),
@@ -640,61 +683,32 @@ pub fn setup(
         .insert(Name::new("Mock spring location"))
         .id();
 
-    let col_mesh_mortar: Handle<Mesh> = asset_server.load("models/mortar.gltf#Mesh0/Primitive0");
-
-    let _mortar = commands
-        .spawn((
-            SceneBundle {
-                scene: asset_server.load("models/mortar.gltf#Scene0"),
-                transform: Transform {
-                    // translation: Vec3::new(5., 10., -0.075),
-                    translation: Vec3::new(20.0, 5.0, -3.0),
-                    scale: Vec3::splat(2.),
-                    ..default()
-                },
-                ..default()
-            },
-            crate::ColliderLoad,
-            Name::new("Mortar & Pestle"),
-            col_mesh_mortar,
-        ))
-        .insert(RigidBodyBundle::dynamic())
-        .insert(ColliderBundle {
-            collision_groups: TERRAIN_GROUPING,
-            ..default()
-        })
-        .id();
+    let mortar_position = Transform {
+        translation: Vec3::new(20.0, 5.0, -3.0),
+        scale: Vec3::splat(2.),
+        ..default()
+    };
+    let _mortar = crate::objects::mortar::spawn_mortar(
+        &mut commands,
+        &*asset_server,
+        mortar_position,
+        &mut meshes,
+    );
+    let _pestle = crate::objects::mortar::spawn_pestle(
+        &mut commands,
+        Transform::from_translation(mortar_position.translation + Vec3::new(1.0, 0.5, 0.0)),
+        &mut meshes,
+    );
 
-    let _stirrer = commands
-        .spawn(SceneBundle {
-            scene: asset_server.load("models/cauldron_stirrer.glb#Scene0"),
-            transform: Transform {
-                // translation: Vec3::new(5., 10., -0.075),
-                translation: Vec3::new(-10.0, 10.0, -4.0),
-                scale: Vec3::splat(1.5),
-                ..default()
-            },
+    let _stirrer = crate::objects::stirrer::spawn_stirrer(
+        &mut commands,
+        &*asset_server,
+        Transform {
+            translation: Vec3::new(-10.0, 10.0, -4.0),
+            scale: Vec3::splat(1.5),
             ..default()
-        })
-        .insert(AutoAim(vec![AimPrimitive::Line {
-            start: Vec3::new(0.0, 0.3, 0.0),
-            end: Vec3::new(0.0, 1.2, 0.0),
-        }]))
-        .insert(RigidBodyBundle::dynamic())
-        .insert((Name::new("Stirrer"), level_collision_mesh3))
-        .with_children(|builder| {
-            builder
-                .spawn(TransformBundle {
-                    local: Transform {
-                        translation: Vec3::new(0.0, 1.0, 0.0),
-                        ..default()
-                    },
-                    ..default()
-                })
-                //.insert(DecompLoad("stirrer".to_owned()))
-                .insert(ColliderBundle::collider(Collider::cuboid(0.1, 0.5, 0.1)));
-        })
-        .id();
+        },
+    );
 
     let level_collision_mesh: Handle<Mesh> =
         asset_server.load("models/walls_shop1.glb#Mesh0/Primitive0");
@@ -0,0 +1,303 @@
+//! Who gets to simulate a grabbed body.
+//!
+//! The server decides which hands actually hold onto something. A body held
+//! by a single player is simulated by that player's client, which streams the
+//! result back. The first tick wins a contested grab; hands from different
+//! players grabbing on the same tick all hold on and fight over it with their
+//! joints, simulated by the server. Authority goes back to the server once
+//! the body is let go and no longer [`Thrown`].
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{objects::Thrown, prelude::*};
+
+use super::{
+    protocol::Channel, replicate::Replicate, server_read_client_messages, ClientId, ClientMessage,
+    ClientMessageEvent, Lobby, NetworkClient, NetworkServer, NetworkSet, NetworkTick, Owned,
+    ServerEntities, ServerMessage,
+};
+
+pub struct AuthorityPlugin;
+
+impl Plugin for AuthorityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GrabAuthority>();
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                enforce_denied_grabs.after(grab_collider).before(grab_joint),
+                server_arbitrate_grabs
+                    .after(enforce_denied_grabs)
+                    .before(grab_joint)
+                    .run_if(resource_exists::<NetworkServer>),
+                server_receive_body_states
+                    .in_set(NetworkSet::Process)
+                    .after(server_read_client_messages)
+                    .run_if(resource_exists::<NetworkServer>),
+                client_send_body_states
+                    .in_set(NetworkSet::Send)
+                    .run_if(resource_exists::<NetworkClient>),
+            ),
+        );
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Authority {
+    #[default]
+    Server,
+    Client(ClientId),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GrabClaim {
+    pub hand: Entity,
+    pub client_id: ClientId,
+    pub tick: u64,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct GrabbedState {
+    /// Hands holding on, in the order they grabbed.
+    pub claims: Vec<GrabClaim>,
+    pub authority: Authority,
+}
+
+/// Server bookkeeping of grabbed bodies.
+#[derive(Default, Debug, Resource)]
+pub struct GrabAuthority {
+    pub bodies: HashMap<Entity, GrabbedState>,
+}
+
+impl GrabAuthority {
+    pub fn authority(&self, entity: Entity) -> Authority {
+        self.bodies
+            .get(&entity)
+            .map(|state| state.authority)
+            .unwrap_or_default()
+    }
+}
+
+/// The server told this hand to let go, it can't grab the entity again until it stops trying.
+#[derive(Debug, Clone, Copy, Component)]
+pub struct DeniedGrab(pub Entity);
+
+/// Simulation state of a body the sending client has authority over.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BodyState {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub linvel: [f32; 3],
+    pub angvel: [f32; 3],
+}
+
+impl BodyState {
+    pub fn new(transform: &Transform, velocity: &Velocity) -> Self {
+        Self {
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+            linvel: velocity.linvel.to_array(),
+            angvel: velocity.angvel.to_array(),
+        }
+    }
+
+    pub fn apply(&self, transform: &mut Transform, velocity: &mut Velocity) {
+        transform.translation = Vec3::from_array(self.translation);
+        transform.rotation = Quat::from_array(self.rotation).normalize();
+        velocity.linvel = Vec3::from_array(self.linvel);
+        velocity.angvel = Vec3::from_array(self.angvel);
+    }
+}
+
+pub fn enforce_denied_grabs(
+    mut commands: Commands,
+    mut hands: Query<(Entity, &mut Grabbing, &DeniedGrab)>,
+) {
+    for (hand, mut grabbing, denied) in &mut hands {
+        if !grabbing.trying_grab {
+            commands.entity(hand).remove::<DeniedGrab>();
+            continue;
+        }
+
+        if grabbing
+            .grabbed
+            .is_some_and(|grabbed| grabbed.entity == denied.0)
+        {
+            grabbing.grabbed = None;
+        }
+    }
+}
+
+pub fn server_arbitrate_grabs(
+    mut commands: Commands,
+    tick: Res<NetworkTick>,
+    lobby: Res<Lobby>,
+    mut server: ResMut<NetworkServer>,
+    mut authority: ResMut<GrabAuthority>,
+    characters: Query<&CharacterEntities>,
+    mut hands: Query<(Entity, &mut Grabbing), With<Hand>>,
+    thrown: Query<(), With<Thrown>>,
+    bodies: Query<&RigidBody>,
+    replicated: Query<(), With<Replicate>>,
+) {
+    let mut hand_owners = HashMap::new();
+    for (client_id, player) in lobby.players.iter() {
+        let Ok(character) = characters.get(*player) else {
+            continue;
+        };
+        for entity in character.iter() {
+            hand_owners.insert(*entity, *client_id);
+        }
+    }
+
+    // Let go of claims from hands that aren't holding on anymore.
+    for (target, state) in authority.bodies.iter_mut() {
+        state.claims.retain(|claim| {
+            hands.get(claim.hand).is_ok_and(|(_, grabbing)| {
+                grabbing
+                    .grabbed
+                    .is_some_and(|grabbed| grabbed.entity == *target)
+            })
+        });
+    }
+
+    for (hand, mut grabbing) in &mut hands {
+        let Some(grabbed) = grabbing.grabbed else {
+            continue;
+        };
+        let Some(&client_id) = hand_owners.get(&hand) else {
+            continue;
+        };
+
+        let state = authority.bodies.entry(grabbed.entity).or_default();
+        if state.claims.iter().any(|claim| claim.hand == hand) {
+            continue;
+        }
+
+        let contested = state
+            .claims
+            .iter()
+            .any(|claim| claim.client_id != client_id && claim.tick < tick.0);
+        if contested {
+            info!(
+                "player {} lost the grab on {:?}, already held",
+                client_id, grabbed.entity
+            );
+            grabbing.grabbed = None;
+            commands.entity(hand).insert(DeniedGrab(grabbed.entity));
+
+            let message = bincode::serialize(&ServerMessage::GrabDenied {
+                entity: grabbed.entity.into(),
+            })
+            .unwrap();
+            server.send_message(client_id, Channel::Reliable, message);
+            continue;
+        }
+
+        // Whoever ends up simulating it, clients need to see it move.
+        let dynamic = bodies
+            .get(grabbed.entity)
+            .is_ok_and(|body| *body == RigidBody::Dynamic);
+        if dynamic && !replicated.contains(grabbed.entity) {
+            commands.entity(grabbed.entity).insert(Replicate);
+        }
+
+        state.claims.push(GrabClaim {
+            hand,
+            client_id,
+            tick: tick.0,
+        });
+    }
+
+    let mut released = Vec::new();
+    for (&target, state) in authority.bodies.iter_mut() {
+        let new_authority = match state.claims.first() {
+            Some(first)
+                if state
+                    .claims
+                    .iter()
+                    .all(|claim| claim.client_id == first.client_id) =>
+            {
+                Authority::Client(first.client_id)
+            }
+            // Tug-of-war, the server settles it.
+            Some(_) => Authority::Server,
+            // Still flying, whoever threw it stays in charge.
+            None if thrown.contains(target) => state.authority,
+            None => Authority::Server,
+        };
+
+        if new_authority != state.authority {
+            if let Authority::Client(previous) = state.authority {
+                let message = bincode::serialize(&ServerMessage::RevokeAuthority {
+                    entity: target.into(),
+                })
+                .unwrap();
+                server.send_message(previous, Channel::Reliable, message);
+            }
+
+            if let Authority::Client(next) = new_authority {
+                let message = bincode::serialize(&ServerMessage::GrantAuthority {
+                    entity: target.into(),
+                })
+                .unwrap();
+                server.send_message(next, Channel::Reliable, message);
+            }
+
+            info!("authority of {:?} -> {:?}", target, new_authority);
+            state.authority = new_authority;
+        }
+
+        if state.claims.is_empty() && state.authority == Authority::Server {
+            released.push(target);
+        }
+    }
+
+    for target in released {
+        authority.bodies.remove(&target);
+    }
+}
+
+pub fn server_receive_body_states(
+    authority: Res<GrabAuthority>,
+    mut messages: EventReader<ClientMessageEvent>,
+    mut bodies: Query<(&mut Transform, &mut Velocity)>,
+) {
+    for event in messages.read() {
+        let ClientMessage::BodyState { entity, state } = &event.message else {
+            continue;
+        };
+
+        let Some(entity) = entity.entity() else {
+            continue;
+        };
+        if authority.authority(entity) != Authority::Client(event.client_id) {
+            continue;
+        }
+
+        if let Ok((mut transform, mut velocity)) = bodies.get_mut(entity) {
+            state.apply(&mut transform, &mut velocity);
+        }
+    }
+}
+
+/// Stream bodies we have authority over, other than our own character.
+pub fn client_send_body_states(
+    server_entities: Res<ServerEntities>,
+    mut client: ResMut<NetworkClient>,
+    bodies: Query<(Entity, &Transform, &Velocity), (With<Owned>, Without<Player>)>,
+) {
+    for (entity, transform, velocity) in &bodies {
+        let Some(server_entity) = server_entities.server_entity(entity) else {
+            continue;
+        };
+
+        let message = bincode::serialize(&ClientMessage::BodyState {
+            entity: server_entity,
+            state: BodyState::new(transform, velocity),
+        })
+        .unwrap();
+        client.send_message(Channel::Unreliable, message);
+    }
+}
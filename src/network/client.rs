@@ -0,0 +1,197 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bevy::prelude::*;
+
+use super::{
+    conditioner::{ConditionedTransport, LinkConditioner},
+    lobby::PlayerIdentity,
+    protocol::{Channel, Connection, ConnectionStats, Packet, PROTOCOL_ID},
+    transport::Transport,
+    ClientId,
+};
+
+/// Resend the connect request this often until the server answers.
+pub const CONNECT_RETRY: Duration = Duration::from_millis(250);
+
+#[derive(Event, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientEvent {
+    Connected(ClientId),
+    Disconnected { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientState {
+    Connecting,
+    Connected(ClientId),
+    Disconnected(String),
+}
+
+#[derive(Resource)]
+pub struct NetworkClient {
+    transport: ConditionedTransport,
+    state: ClientState,
+    connection: Connection,
+    since_request: Duration,
+    password: Option<String>,
+    identity: PlayerIdentity,
+    events: Vec<ClientEvent>,
+}
+
+impl NetworkClient {
+    pub fn new(transport: impl Transport, server_addr: SocketAddr) -> Self {
+        Self {
+            transport: ConditionedTransport::new(transport),
+            state: ClientState::Connecting,
+            connection: Connection::new(server_addr),
+            since_request: CONNECT_RETRY,
+            password: None,
+            identity: PlayerIdentity::default(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Password to join servers that require one.
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Name, color and reconnection token to introduce ourselves with.
+    pub fn with_identity(mut self, identity: PlayerIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.transport.local_addr()
+    }
+
+    pub fn link_conditioner(&self) -> &LinkConditioner {
+        self.transport.conditioner()
+    }
+
+    /// Simulate a bad connection to the server.
+    pub fn set_link_conditioner(&mut self, conditioner: LinkConditioner) {
+        self.transport.set_conditioner(conditioner);
+    }
+
+    pub fn server_addr(&self) -> SocketAddr {
+        self.connection.addr()
+    }
+
+    pub fn state(&self) -> &ClientState {
+        &self.state
+    }
+
+    pub fn stats(&self) -> &ConnectionStats {
+        self.connection.stats()
+    }
+
+    pub fn client_id(&self) -> Option<ClientId> {
+        match self.state {
+            ClientState::Connected(client_id) => Some(client_id),
+            _ => None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, ClientState::Connected(_))
+    }
+
+    pub fn is_connecting(&self) -> bool {
+        matches!(self.state, ClientState::Connecting)
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self.state, ClientState::Disconnected(_))
+    }
+
+    /// Messages sent while still connecting go out once the server accepts us.
+    pub fn send_message(&mut self, channel: Channel, message: Vec<u8>) {
+        if !self.is_disconnected() {
+            self.connection.send_message(channel, message);
+        }
+    }
+
+    pub fn receive_message(&mut self, channel: Channel) -> Option<Vec<u8>> {
+        self.connection.receive_message(channel)
+    }
+
+    pub fn disconnect(&mut self) {
+        if self.is_disconnected() {
+            return;
+        }
+
+        self.connection
+            .send_packet(&mut self.transport, &Packet::Disconnect);
+        self.set_disconnected("disconnected by client".to_owned());
+    }
+
+    fn set_disconnected(&mut self, reason: String) {
+        info!("disconnected from {}: {}", self.server_addr(), reason);
+        self.state = ClientState::Disconnected(reason.clone());
+        self.events.push(ClientEvent::Disconnected { reason });
+    }
+
+    /// Read incoming packets and return connection changes since the last update.
+    pub fn update(&mut self) -> Vec<ClientEvent> {
+        for (addr, bytes) in self.transport.receive() {
+            if addr != self.server_addr() || self.is_disconnected() {
+                continue;
+            }
+
+            let Some(packet) = Packet::from_bytes(&bytes) else {
+                warn!("malformed packet from {}", addr);
+                continue;
+            };
+
+            match packet {
+                Packet::ConnectAccepted { client_id } => {
+                    if self.is_connecting() {
+                        info!("connected to {} as client {}", addr, client_id);
+                        self.state = ClientState::Connected(client_id);
+                        self.events.push(ClientEvent::Connected(client_id));
+                    }
+                    self.connection.process(Packet::Heartbeat);
+                }
+                Packet::ConnectDenied { reason } => self.set_disconnected(reason),
+                Packet::Disconnect => self.set_disconnected("disconnected by server".to_owned()),
+                packet => {
+                    if self.is_connected() {
+                        self.connection.process(packet);
+                    }
+                }
+            }
+        }
+
+        if !self.is_disconnected() && self.connection.timed_out() {
+            self.set_disconnected("connection timed out".to_owned());
+        }
+
+        std::mem::take(&mut self.events)
+    }
+
+    /// Flush queued messages, or keep asking to connect.
+    pub fn send_packets(&mut self, dt: Duration) {
+        self.transport.advance(dt);
+        match self.state {
+            ClientState::Connecting => {
+                self.connection.advance(dt);
+                self.since_request += dt;
+                if self.since_request >= CONNECT_RETRY {
+                    self.since_request = Duration::ZERO;
+                    self.connection.send_packet(
+                        &mut self.transport,
+                        &Packet::ConnectRequest {
+                            protocol: PROTOCOL_ID,
+                            password: self.password.clone(),
+                            identity: self.identity.clone(),
+                        },
+                    );
+                }
+            }
+            ClientState::Connected(_) => self.connection.flush(dt, &mut self.transport),
+            ClientState::Disconnected(_) => {}
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! Simulated bad connections for testing on localhost.
+//!
+//! Every [`super::NetworkServer`] and [`super::NetworkClient`] sends through a
+//! [`ConditionedTransport`], which does nothing until [`LinkConditioner`] is
+//! enabled. Randomness comes from [`LinkConditioner::seed`] and time only
+//! moves with fixed ticks, so a run can be reproduced exactly.
+use std::{io, net::SocketAddr, time::Duration};
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{client::NetworkClient, server::NetworkServer, transport::Transport};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Reflect)]
+pub struct LinkConditions {
+    pub latency: Duration,
+    /// Latency varies by up to this much either way.
+    pub jitter: Duration,
+    /// Chance a packet is dropped, from 0 to 1.
+    pub loss: f32,
+    /// Chance a packet arrives twice.
+    pub duplication: f32,
+    /// Chance a packet is held back long enough to arrive after later ones.
+    pub reordering: f32,
+}
+
+impl LinkConditions {
+    /// When a packet sent `now` arrives, `None` if it gets lost.
+    fn roll(&self, rng: &mut StdRng, now: Duration) -> Option<Duration> {
+        if rng.gen::<f32>() < self.loss {
+            return None;
+        }
+
+        let jitter = self.jitter.as_secs_f64();
+        let mut delay = self.latency.as_secs_f64() + rng.gen_range(-jitter..=jitter);
+        if rng.gen::<f32>() < self.reordering {
+            delay += self.latency.as_secs_f64().max(REORDER_DELAY.as_secs_f64());
+        }
+
+        Some(now + Duration::from_secs_f64(delay.max(0.0)))
+    }
+}
+
+/// Minimum extra delay for reordered packets.
+pub const REORDER_DELAY: Duration = Duration::from_millis(50);
+
+#[derive(Resource, Default, Debug, Clone, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct LinkConditioner {
+    pub enabled: bool,
+    pub seed: u64,
+    /// Packets we send.
+    pub outgoing: LinkConditions,
+    /// Packets we receive.
+    pub incoming: LinkConditions,
+}
+
+impl LinkConditioner {
+    /// Read flags like `--latency=100` (ms) or `--loss-in=0.1` from `args`,
+    /// skipping arguments that aren't flags.
+    ///
+    /// Flags apply to both directions unless suffixed with `-in` or `-out`, and
+    /// any of them enables the conditioner. Supported flags are `latency`,
+    /// `jitter`, `loss`, `duplication`, `reordering` and `seed`, anything else
+    /// is an error.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut conditioner = Self::default();
+
+        for arg in args {
+            let Some(flag) = arg.strip_prefix("--") else {
+                continue;
+            };
+            let Some((flag, value)) = flag.split_once('=') else {
+                return Err(format!("missing value for --{}", flag));
+            };
+
+            let (name, directions) = if let Some(name) = flag.strip_suffix("-in") {
+                (name, (true, false))
+            } else if let Some(name) = flag.strip_suffix("-out") {
+                (name, (false, true))
+            } else {
+                (flag, (true, true))
+            };
+
+            let parse_chance = || -> Result<f32, String> {
+                let chance = value
+                    .parse::<f32>()
+                    .map_err(|err| format!("invalid value for --{}: {}", flag, err))?;
+                if !(0.0..=1.0).contains(&chance) {
+                    return Err(format!("--{} has to be between 0 and 1", flag));
+                }
+                Ok(chance)
+            };
+            let parse_ms = || {
+                value
+                    .parse::<u64>()
+                    .map(Duration::from_millis)
+                    .map_err(|err| format!("invalid value for --{}: {}", flag, err))
+            };
+
+            let mut apply = |set: &dyn Fn(&mut LinkConditions)| {
+                if directions.0 {
+                    set(&mut conditioner.incoming);
+                }
+                if directions.1 {
+                    set(&mut conditioner.outgoing);
+                }
+            };
+
+            match name {
+                "latency" => {
+                    let latency = parse_ms()?;
+                    apply(&|conditions| conditions.latency = latency);
+                }
+                "jitter" => {
+                    let jitter = parse_ms()?;
+                    apply(&|conditions| conditions.jitter = jitter);
+                }
+                "loss" => {
+                    let loss = parse_chance()?;
+                    apply(&|conditions| conditions.loss = loss);
+                }
+                "duplication" => {
+                    let duplication = parse_chance()?;
+                    apply(&|conditions| conditions.duplication = duplication);
+                }
+                "reordering" => {
+                    let reordering = parse_chance()?;
+                    apply(&|conditions| conditions.reordering = reordering);
+                }
+                "seed" => {
+                    conditioner.seed = value
+                        .parse()
+                        .map_err(|err| format!("invalid value for --seed: {}", err))?;
+                }
+                _ => return Err(format!("unknown flag --{}", flag)),
+            }
+
+            conditioner.enabled = true;
+        }
+
+        Ok(conditioner)
+    }
+}
+
+/// Push [`LinkConditioner`] to the server and client whenever they differ.
+pub fn sync_link_conditioner(
+    conditioner: Res<LinkConditioner>,
+    server: Option<ResMut<NetworkServer>>,
+    client: Option<ResMut<NetworkClient>>,
+) {
+    if let Some(mut server) = server {
+        if server.link_conditioner() != &*conditioner {
+            server.set_link_conditioner(conditioner.clone());
+        }
+    }
+
+    if let Some(mut client) = client {
+        if client.link_conditioner() != &*conditioner {
+            client.set_link_conditioner(conditioner.clone());
+        }
+    }
+}
+
+struct Delayed {
+    at: Duration,
+    addr: SocketAddr,
+    packet: Vec<u8>,
+}
+
+/// Wraps another transport and applies a [`LinkConditioner`] to it.
+pub struct ConditionedTransport {
+    inner: Box<dyn Transport>,
+    conditioner: LinkConditioner,
+    rng: StdRng,
+    /// Time advanced by [`ConditionedTransport::advance`].
+    now: Duration,
+    outgoing: Vec<Delayed>,
+    incoming: Vec<Delayed>,
+}
+
+impl ConditionedTransport {
+    pub fn new(inner: impl Transport) -> Self {
+        Self {
+            inner: Box::new(inner),
+            conditioner: LinkConditioner::default(),
+            rng: StdRng::seed_from_u64(0),
+            now: Duration::ZERO,
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+        }
+    }
+
+    pub fn conditioner(&self) -> &LinkConditioner {
+        &self.conditioner
+    }
+
+    /// Packets already in flight keep their delays, the random sequence restarts from the seed.
+    pub fn set_conditioner(&mut self, conditioner: LinkConditioner) {
+        self.rng = StdRng::seed_from_u64(conditioner.seed);
+        self.conditioner = conditioner;
+    }
+
+    /// Move time forward and send whatever is due.
+    pub fn advance(&mut self, dt: Duration) {
+        self.now += dt;
+
+        for delayed in take_due(&mut self.outgoing, self.now) {
+            self.send_now(delayed.addr, &delayed.packet);
+        }
+    }
+
+    fn send_now(&mut self, to: SocketAddr, packet: &[u8]) {
+        if let Err(err) = self.inner.send(to, packet) {
+            warn!("failed to send delayed packet to {}: {:?}", to, err);
+        }
+    }
+
+    /// Queue copies of a packet according to `conditions`.
+    fn condition(
+        rng: &mut StdRng,
+        conditions: &LinkConditions,
+        now: Duration,
+        queue: &mut Vec<Delayed>,
+        addr: SocketAddr,
+        packet: &[u8],
+    ) {
+        let copies = if rng.gen::<f32>() < conditions.duplication {
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            if let Some(at) = conditions.roll(rng, now) {
+                queue.push(Delayed {
+                    at,
+                    addr,
+                    packet: packet.to_vec(),
+                });
+            }
+        }
+    }
+}
+
+/// Remove and return everything due by `now`, in the order it arrives.
+fn take_due(queue: &mut Vec<Delayed>, now: Duration) -> Vec<Delayed> {
+    let mut due = Vec::new();
+    let mut index = 0;
+    while index < queue.len() {
+        if queue[index].at <= now {
+            due.push(queue.remove(index));
+        } else {
+            index += 1;
+        }
+    }
+
+    due.sort_by_key(|delayed| delayed.at);
+    due
+}
+
+impl Transport for ConditionedTransport {
+    fn local_addr(&self) -> SocketAddr {
+        self.inner.local_addr()
+    }
+
+    fn send(&mut self, to: SocketAddr, packet: &[u8]) -> io::Result<()> {
+        if !self.conditioner.enabled {
+            return self.inner.send(to, packet);
+        }
+
+        Self::condition(
+            &mut self.rng,
+            &self.conditioner.outgoing,
+            self.now,
+            &mut self.outgoing,
+            to,
+            packet,
+        );
+
+        for delayed in take_due(&mut self.outgoing, self.now) {
+            self.send_now(delayed.addr, &delayed.packet);
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Vec<(SocketAddr, Vec<u8>)> {
+        let received = self.inner.receive();
+        if !self.conditioner.enabled && self.incoming.is_empty() {
+            return received;
+        }
+
+        for (from, packet) in received {
+            if self.conditioner.enabled {
+                Self::condition(
+                    &mut self.rng,
+                    &self.conditioner.incoming,
+                    self.now,
+                    &mut self.incoming,
+                    from,
+                    &packet,
+                );
+            } else {
+                self.incoming.push(Delayed {
+                    at: self.now,
+                    addr: from,
+                    packet,
+                });
+            }
+        }
+
+        take_due(&mut self.incoming, self.now)
+            .into_iter()
+            .map(|delayed| (delayed.addr, delayed.packet))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::transport::LoopbackNetwork, *};
+
+    fn run(seed: u64) -> Vec<Vec<u8>> {
+        let network = LoopbackNetwork::new();
+        let mut sender = ConditionedTransport::new(network.bind("127.0.0.1:2000".parse().unwrap()));
+        let mut receiver = network.bind("127.0.0.1:2001".parse().unwrap());
+
+        sender.set_conditioner(LinkConditioner {
+            enabled: true,
+            seed,
+            outgoing: LinkConditions {
+                latency: Duration::from_millis(100),
+                jitter: Duration::from_millis(30),
+                loss: 0.2,
+                duplication: 0.1,
+                reordering: 0.1,
+            },
+            ..default()
+        });
+
+        for index in 0..100u8 {
+            sender.send(receiver.local_addr(), &[index]).unwrap();
+        }
+        assert!(
+            receiver.receive().is_empty(),
+            "nothing arrives before the latency"
+        );
+
+        let mut received = Vec::new();
+        for _ in 0..20 {
+            sender.advance(Duration::from_millis(16));
+            received.extend(receiver.receive().into_iter().map(|(_, packet)| packet));
+        }
+        received
+    }
+
+    #[test]
+    fn same_seed_same_conditions() {
+        let first = run(7);
+        assert_eq!(first, run(7));
+        assert!(first.len() < 100, "some packets should be lost");
+        assert!(!first.is_empty());
+    }
+
+    #[test]
+    fn parses_flags() {
+        let conditioner = LinkConditioner::from_args(
+            [
+                "client",
+                "--latency=80",
+                "--loss-in=0.5",
+                "--seed=3",
+                "127.0.0.1:42069",
+            ]
+            .map(str::to_owned),
+        )
+        .unwrap();
+
+        assert!(conditioner.enabled);
+        assert_eq!(conditioner.seed, 3);
+        assert_eq!(conditioner.incoming.latency, Duration::from_millis(80));
+        assert_eq!(conditioner.outgoing.latency, Duration::from_millis(80));
+        assert_eq!(conditioner.incoming.loss, 0.5);
+        assert_eq!(conditioner.outgoing.loss, 0.0);
+    }
+
+    #[test]
+    fn rejects_bad_flags() {
+        let parse = |arg: &str| LinkConditioner::from_args([arg.to_owned()]);
+
+        assert!(parse("--lag=80").is_err());
+        assert!(parse("--latency").is_err());
+        assert!(parse("--latency=fast").is_err());
+        assert!(parse("--loss-out=2").is_err());
+        assert!(parse("--seed=-1").is_err());
+        assert!(parse("127.0.0.1:42069").is_ok());
+    }
+}
@@ -0,0 +1,186 @@
+//! Dedicated server settings, read from a TOML or RON file.
+//!
+//! ```toml
+//! bind_address = "0.0.0.0"
+//! port = 42069
+//! max_players = 8
+//! map = "showcase"
+//! tick_rate = 62.5
+//! password = "hunter2"
+//! reconnect_grace = 30.0
+//! ```
+use std::{
+    fmt, fs, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::lobby::LobbySettings;
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    pub max_players: usize,
+    /// See [`crate::maps::add_headless`].
+    pub map: String,
+    /// Fixed ticks per second, has to match [`crate::TICK_RATE`] since physics,
+    /// gameplay and clients all run at that rate.
+    pub tick_rate: f64,
+    /// Clients have to send this to join, if set.
+    pub password: Option<String>,
+    /// Seconds a dropped player's character waits for them to reconnect.
+    pub reconnect_grace: f64,
+    /// Write the shutdown state dump here as well as to the log.
+    pub dump_path: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: super::PORT,
+            max_players: 8,
+            map: "showcase".to_owned(),
+            tick_rate: 1.0 / crate::TICK_RATE.as_secs_f64(),
+            password: None,
+            reconnect_grace: 30.0,
+            dump_path: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Load from `path`, parsed as RON if it ends in `.ron` and TOML otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("ron") => Self::from_ron(&source),
+            _ => Self::from_toml(&source),
+        }
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, ConfigError> {
+        toml::from_str::<Self>(source)?.validate()
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, ConfigError> {
+        ron::from_str::<Self>(source)?.validate()
+    }
+
+    fn validate(self) -> Result<Self, ConfigError> {
+        if (self.tick_rate - ServerConfig::default().tick_rate).abs() > 1e-6 {
+            return Err(ConfigError::TickRate(self.tick_rate));
+        }
+        Ok(self)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_address, self.port)
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.tick_rate)
+    }
+
+    pub fn lobby_settings(&self) -> LobbySettings {
+        LobbySettings {
+            grace_period: Duration::from_secs_f64(self.reconnect_grace.max(0.0)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Ron(ron::error::SpannedError),
+    /// `tick_rate` isn't the one the game runs at.
+    TickRate(f64),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "failed to read config: {}", err),
+            ConfigError::Toml(err) => write!(f, "invalid TOML config: {}", err),
+            ConfigError::Ron(err) => write!(f, "invalid RON config: {}", err),
+            ConfigError::TickRate(rate) => write!(
+                f,
+                "tick_rate has to be {}, got {}",
+                1.0 / crate::TICK_RATE.as_secs_f64(),
+                rate
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Toml(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for ConfigError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        ConfigError::Ron(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_and_ron_agree() {
+        let toml = ServerConfig::from_toml(
+            r#"
+            port = 1234
+            max_players = 2
+            password = "hunter2"
+            "#,
+        )
+        .unwrap();
+        let ron =
+            ServerConfig::from_ron(r#"(port: 1234, max_players: 2, password: Some("hunter2"))"#)
+                .unwrap();
+
+        assert_eq!(toml, ron);
+        assert_eq!(toml.map, "showcase", "missing fields use defaults");
+        assert_eq!(toml.addr(), "0.0.0.0:1234".parse().unwrap());
+    }
+
+    #[test]
+    fn rejects_bad_tick_rate() {
+        for source in [
+            "tick_rate = 0.0",
+            "tick_rate = -30.0",
+            "tick_rate = nan",
+            "tick_rate = 30.0",
+        ] {
+            assert!(matches!(
+                ServerConfig::from_toml(source),
+                Err(ConfigError::TickRate(_))
+            ));
+        }
+        assert!(matches!(
+            ServerConfig::from_ron("(tick_rate: 0.0)"),
+            Err(ConfigError::TickRate(_))
+        ));
+        assert!(ServerConfig::from_toml("tick_rate = 62.5").is_ok());
+    }
+}
@@ -0,0 +1,268 @@
+//! Smooth out remote entities by showing them slightly in the past.
+//!
+//! Snapshots for entities we don't predict are buffered per entity and played
+//! back [`InterpolationSettings::delay_ticks`] behind the server, so there is
+//! usually a snapshot on either side to interpolate between. Remote players
+//! are [`KinematicProxy`]s, their arms hang off the interpolated body through
+//! their joints.
+use std::any::TypeId;
+use std::collections::VecDeque;
+
+use crate::prelude::*;
+
+use super::{
+    prediction::{PredictionSettings, Resimulating},
+    NetworkClient, NetworkTick, Owned,
+};
+
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<InterpolationSettings>()
+            .register_type::<KinematicProxy>();
+        app.init_resource::<InterpolationSettings>();
+
+        app.add_systems(
+            FixedUpdate,
+            interpolate_remote
+                .after(crate::FixedSet::Last)
+                .after(PhysicsSet::Writeback)
+                .run_if(resource_exists::<NetworkClient>)
+                .run_if(not(resource_exists::<Resimulating>)),
+        );
+    }
+}
+
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct InterpolationSettings {
+    /// How many ticks behind the server remote entities are shown.
+    pub delay_ticks: u64,
+    /// How far past the newest snapshot we keep moving an entity when packets are late.
+    pub max_extrapolation_ticks: u64,
+    /// Snapshots kept per entity.
+    pub buffer_len: usize,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        Self {
+            delay_ticks: 6,
+            max_extrapolation_ticks: 10,
+            buffer_len: 32,
+        }
+    }
+}
+
+/// Simulated by someone else, stays kinematic here whatever the server
+/// simulates it as so only the snapshots move it.
+#[derive(Default, Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct KinematicProxy;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterpolationSample {
+    pub tick: u64,
+    pub transform: Transform,
+    pub velocity: Velocity,
+}
+
+/// Recent server states of a remote entity, oldest first.
+#[derive(Default, Debug, Clone, Component)]
+pub struct InterpolationBuffer {
+    pub samples: VecDeque<InterpolationSample>,
+}
+
+impl InterpolationBuffer {
+    /// Keep the value if it is interpolated, returns `false` otherwise.
+    pub fn capture(&mut self, tick: u64, type_id: TypeId, value: &dyn Reflect) -> bool {
+        if type_id == TypeId::of::<Transform>() {
+            let Some(transform) = Transform::from_reflect(value) else {
+                return false;
+            };
+            if let Some(sample) = self.sample_mut(tick) {
+                sample.transform = transform;
+            }
+        } else if type_id == TypeId::of::<Velocity>() {
+            let Some(velocity) = Velocity::from_reflect(value) else {
+                return false;
+            };
+            if let Some(sample) = self.sample_mut(tick) {
+                sample.velocity = velocity;
+            }
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    /// Sample for `tick`, starting from the previous one since deltas leave out
+    /// whatever didn't change.
+    ///
+    /// `None` if we already have something newer.
+    fn sample_mut(&mut self, tick: u64) -> Option<&mut InterpolationSample> {
+        let previous = self.samples.back().copied();
+        match previous {
+            Some(previous) if previous.tick > tick => return None,
+            Some(previous) if previous.tick == tick => {}
+            _ => self.samples.push_back(InterpolationSample {
+                tick,
+                transform: previous.map(|sample| sample.transform).unwrap_or_default(),
+                velocity: previous.map(|sample| sample.velocity).unwrap_or_default(),
+            }),
+        }
+
+        self.samples.back_mut()
+    }
+
+    /// Drop samples we can't need anymore, keeping one at or before `tick`.
+    pub fn prune(&mut self, tick: u64, max_len: usize) {
+        while self.samples.len() > max_len
+            || self.samples.get(1).is_some_and(|next| next.tick <= tick)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Where the entity was at `tick`, extrapolating at most `max_extrapolation` ticks.
+    pub fn sample(&self, tick: u64, max_extrapolation: u64) -> Option<(Transform, Velocity)> {
+        let after = self.samples.iter().position(|sample| sample.tick > tick);
+        match after {
+            Some(0) => {
+                let first = self.samples.front()?;
+                Some((first.transform, first.velocity))
+            }
+            Some(after) => {
+                let from = &self.samples[after - 1];
+                let to = &self.samples[after];
+                let t = (tick - from.tick) as f32 / (to.tick - from.tick) as f32;
+                Some(hermite(from, to, t))
+            }
+            None => {
+                let last = self.samples.back()?;
+                let ticks = (tick - last.tick).min(max_extrapolation);
+                Some(extrapolate(last, ticks))
+            }
+        }
+    }
+}
+
+/// Cubic hermite between two samples using their velocities as tangents.
+pub fn hermite(
+    from: &InterpolationSample,
+    to: &InterpolationSample,
+    t: f32,
+) -> (Transform, Velocity) {
+    let dt = (to.tick - from.tick) as f32 * crate::TICK_RATE.as_secs_f32();
+
+    let t2 = t * t;
+    let t3 = t2 * t;
+    let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+    let h10 = t3 - 2.0 * t2 + t;
+    let h01 = -2.0 * t3 + 3.0 * t2;
+    let h11 = t3 - t2;
+
+    let translation = h00 * from.transform.translation
+        + h10 * dt * from.velocity.linvel
+        + h01 * to.transform.translation
+        + h11 * dt * to.velocity.linvel;
+
+    let transform = Transform {
+        translation,
+        rotation: from.transform.rotation.slerp(to.transform.rotation, t),
+        scale: from.transform.scale.lerp(to.transform.scale, t),
+    };
+    let velocity = Velocity {
+        linvel: from.velocity.linvel.lerp(to.velocity.linvel, t),
+        angvel: from.velocity.angvel.lerp(to.velocity.angvel, t),
+    };
+
+    (transform, velocity)
+}
+
+/// Keep moving along the last known velocity for `ticks`.
+pub fn extrapolate(last: &InterpolationSample, ticks: u64) -> (Transform, Velocity) {
+    let dt = ticks as f32 * crate::TICK_RATE.as_secs_f32();
+
+    let mut transform = last.transform;
+    transform.translation += last.velocity.linvel * dt;
+    transform.rotation =
+        (Quat::from_scaled_axis(last.velocity.angvel * dt) * transform.rotation).normalize();
+
+    (transform, last.velocity)
+}
+
+pub fn interpolate_remote(
+    tick: Res<NetworkTick>,
+    prediction: Res<PredictionSettings>,
+    settings: Res<InterpolationSettings>,
+    mut remote: Query<
+        (
+            &mut InterpolationBuffer,
+            &mut Transform,
+            Option<&mut Velocity>,
+        ),
+        Without<Owned>,
+    >,
+) {
+    // Our tick runs ahead of the server by `lead_ticks`.
+    let render_tick = tick
+        .0
+        .saturating_sub(prediction.lead_ticks + settings.delay_ticks);
+
+    for (mut buffer, mut transform, velocity) in &mut remote {
+        buffer.prune(render_tick, settings.buffer_len);
+
+        let Some((sampled_transform, sampled_velocity)) =
+            buffer.sample(render_tick, settings.max_extrapolation_ticks)
+        else {
+            continue;
+        };
+
+        *transform = sampled_transform;
+        if let Some(mut velocity) = velocity {
+            *velocity = sampled_velocity;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(tick: u64, x: f32, linvel: f32) -> InterpolationSample {
+        InterpolationSample {
+            tick,
+            transform: Transform::from_xyz(x, 0.0, 0.0),
+            velocity: Velocity::linear(Vec3::X * linvel),
+        }
+    }
+
+    #[test]
+    fn interpolates_between_samples() {
+        let mut buffer = InterpolationBuffer::default();
+        buffer.samples.push_back(sample(10, 0.0, 0.0));
+        buffer.samples.push_back(sample(20, 1.0, 0.0));
+
+        let (transform, _) = buffer.sample(15, 0).unwrap();
+        assert!((transform.translation.x - 0.5).abs() < 1e-4);
+
+        let (transform, _) = buffer.sample(5, 0).unwrap();
+        assert_eq!(transform.translation.x, 0.0, "holds the oldest sample");
+    }
+
+    #[test]
+    fn extrapolation_is_capped() {
+        let mut buffer = InterpolationBuffer::default();
+        buffer.samples.push_back(sample(10, 0.0, 1.0));
+
+        let tick_secs = crate::TICK_RATE.as_secs_f32();
+        let (transform, _) = buffer.sample(12, 5).unwrap();
+        assert!((transform.translation.x - 2.0 * tick_secs).abs() < 1e-4);
+
+        let (transform, _) = buffer.sample(100, 5).unwrap();
+        assert!((transform.translation.x - 5.0 * tick_secs).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,308 @@
+//! Who is playing, what they go by and whether they are ready.
+//!
+//! Clients introduce themselves with a [`PlayerIdentity`] when connecting.
+//! The server keeps the character of a player whose connection drops for
+//! [`LobbySettings::grace_period`], and gives it back, [`Inventory`] and all,
+//! if a client with the same token shows up in time.
+use std::time::Duration;
+
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+use super::{
+    client_sync_players, prediction::InputHistory, protocol::Channel, replicate::Replicate,
+    server_read_client_messages, server_update_system, ClientId, ClientMessage, ClientMessageEvent,
+    NetworkClient, NetworkServer, NetworkSet, ServerMessage,
+};
+
+/// Client id used for the player of a game without networking.
+pub const LOCAL_CLIENT_ID: ClientId = 0;
+
+/// Longest display name, in characters.
+pub const MAX_NAME_LEN: usize = 24;
+
+/// Colors handed out to players that don't pick one.
+pub const PLAYER_COLORS: [[f32; 3]; 8] = [
+    [0.90, 0.30, 0.25],
+    [0.25, 0.55, 0.90],
+    [0.35, 0.80, 0.35],
+    [0.95, 0.75, 0.20],
+    [0.70, 0.40, 0.85],
+    [0.20, 0.80, 0.80],
+    [0.95, 0.55, 0.20],
+    [0.90, 0.45, 0.70],
+];
+
+pub struct LobbyPlugin;
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LobbyEvent>();
+        app.init_resource::<Lobby>();
+        app.init_resource::<LobbySettings>();
+        app.init_resource::<PlayerIdentity>();
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                (server_set_ready, server_expire_away)
+                    .in_set(NetworkSet::Process)
+                    .after(server_read_client_messages)
+                    .after(server_update_system)
+                    .run_if(resource_exists::<NetworkServer>),
+                client_send_ready
+                    .in_set(NetworkSet::Send)
+                    .after(client_sync_players)
+                    .run_if(resource_exists::<NetworkClient>),
+            ),
+        );
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct LobbySettings {
+    /// How long a dropped player's character waits for them to reconnect.
+    pub grace_period: Duration,
+}
+
+impl Default for LobbySettings {
+    fn default() -> Self {
+        Self {
+            grace_period: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What a player shows up as to everyone else.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    pub name: String,
+    /// Linear RGB.
+    pub color: [f32; 3],
+}
+
+impl PlayerProfile {
+    pub fn color(&self) -> Color {
+        let [r, g, b] = self.color;
+        Color::linear_rgb(r, g, b)
+    }
+
+    /// Trim the name to something displayable, falling back to `Player {id}`.
+    pub fn sanitized(mut self, id: ClientId) -> Self {
+        self.name = self
+            .name
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(MAX_NAME_LEN)
+            .collect::<String>()
+            .trim()
+            .to_owned();
+        if self.name.is_empty() {
+            self.name = format!("Player {}", id);
+        }
+
+        for channel in &mut self.color {
+            *channel = channel.clamp(0.0, 1.0);
+        }
+        self
+    }
+}
+
+/// Sent when connecting, the token lets a server recognize us after a dropped connection.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerIdentity {
+    pub profile: PlayerProfile,
+    pub token: u64,
+}
+
+impl Default for PlayerIdentity {
+    fn default() -> Self {
+        let token = rand::random::<u64>();
+        Self {
+            profile: PlayerProfile {
+                name: String::new(),
+                color: PLAYER_COLORS[token as usize % PLAYER_COLORS.len()],
+            },
+            token,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LobbyMember {
+    pub profile: PlayerProfile,
+    pub ready: bool,
+}
+
+/// A character left behind by a dropped connection.
+#[derive(Debug, Clone)]
+pub struct AwayPlayer {
+    /// Client id they had before dropping.
+    pub id: ClientId,
+    pub entity: Entity,
+    pub member: LobbyMember,
+    /// [`Time::elapsed`] when they dropped.
+    pub since: Duration,
+}
+
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum LobbyEvent {
+    Joined {
+        id: ClientId,
+    },
+    /// Came back within the grace period and got their old character.
+    Rejoined {
+        id: ClientId,
+        previous: ClientId,
+    },
+    Left {
+        id: ClientId,
+    },
+    Ready {
+        id: ClientId,
+        ready: bool,
+    },
+}
+
+#[derive(Default, Debug, Resource)]
+pub struct Lobby {
+    /// Player entities by the client that controls them.
+    pub players: HashMap<ClientId, Entity>,
+    pub members: HashMap<ClientId, LobbyMember>,
+    /// Server only, reconnection tokens of connected clients.
+    pub tokens: HashMap<ClientId, u64>,
+    /// Server only, characters waiting for their player by token.
+    pub away: HashMap<u64, AwayPlayer>,
+}
+
+impl Lobby {
+    /// Members sorted by id, for displaying.
+    pub fn list(&self) -> Vec<(ClientId, &LobbyMember)> {
+        let mut list = self
+            .members
+            .iter()
+            .map(|(id, member)| (*id, member))
+            .collect::<Vec<_>>();
+        list.sort_by_key(|(id, _)| *id);
+        list
+    }
+
+    pub fn all_ready(&self) -> bool {
+        !self.members.is_empty() && self.members.values().all(|member| member.ready)
+    }
+
+    pub fn clear(&mut self) {
+        self.players.clear();
+        self.members.clear();
+        self.tokens.clear();
+        self.away.clear();
+    }
+}
+
+/// The local player wants to change their ready state, sent to the server by [`client_send_ready`].
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct RequestReady(pub bool);
+
+pub fn client_send_ready(
+    mut commands: Commands,
+    request: Option<Res<RequestReady>>,
+    mut client: ResMut<NetworkClient>,
+) {
+    let Some(request) = request else {
+        return;
+    };
+
+    if client.is_connected() {
+        let message = bincode::serialize(&ClientMessage::SetReady { ready: request.0 }).unwrap();
+        client.send_message(Channel::Reliable, message);
+        commands.remove_resource::<RequestReady>();
+    }
+}
+
+pub fn server_set_ready(
+    mut lobby: ResMut<Lobby>,
+    mut server: ResMut<NetworkServer>,
+    mut messages: EventReader<ClientMessageEvent>,
+    mut lobby_events: EventWriter<LobbyEvent>,
+) {
+    for event in messages.read() {
+        let ClientMessage::SetReady { ready } = event.message else {
+            continue;
+        };
+        let id = event.client_id;
+
+        let Some(member) = lobby.members.get_mut(&id) else {
+            continue;
+        };
+        if member.ready == ready {
+            continue;
+        }
+        member.ready = ready;
+
+        let message = bincode::serialize(&ServerMessage::PlayerReady { id, ready }).unwrap();
+        server.broadcast_message(Channel::Reliable, message);
+        lobby_events.send(LobbyEvent::Ready { id, ready });
+    }
+}
+
+/// Despawn characters whose players didn't make it back in time.
+pub fn server_expire_away(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<LobbySettings>,
+    mut lobby: ResMut<Lobby>,
+    characters: Query<(&CharacterEntities, Option<&PlayerNeck>)>,
+) {
+    let now = time.elapsed();
+    lobby.away.retain(|_, away| {
+        if now.saturating_sub(away.since) < settings.grace_period {
+            return true;
+        }
+
+        info!(
+            "player {} ({}) didn't come back, despawning their character",
+            away.id, away.member.profile.name
+        );
+        despawn_player(&mut commands, away.entity, &characters);
+        false
+    });
+}
+
+/// Keep the character of a dropped player around, returns false if there is no grace period.
+pub fn park_player(
+    commands: &mut Commands,
+    lobby: &mut Lobby,
+    settings: &LobbySettings,
+    now: Duration,
+    id: ClientId,
+    entity: Entity,
+    member: LobbyMember,
+) -> bool {
+    let Some(token) = lobby.tokens.remove(&id) else {
+        return false;
+    };
+    if settings.grace_period.is_zero() {
+        return false;
+    }
+
+    // Clients forget about it until the player returns, it just stands there meanwhile.
+    commands
+        .entity(entity)
+        .remove::<Replicate>()
+        .insert((PlayerInput::default(), InputHistory::default()));
+    lobby.away.insert(
+        token,
+        AwayPlayer {
+            id,
+            entity,
+            member: LobbyMember {
+                ready: false,
+                ..member
+            },
+            since: now,
+        },
+    );
+    true
+}
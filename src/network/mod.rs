@@ -1,120 +1,1125 @@
-use bevy::{ecs::entity::Entities, prelude::*};
-use bevy_editor_pls::AddEditorWindow;
-use bevy_renet::renet::{RenetClient, RenetServer, ServerEvent};
-use iyes_loopless::prelude::*;
-
-//use renet_visualizer::RenetServerVisualizer;
-use sabi::{
+use bevy::{
+    ecs::entity::{Entities, EntityHashSet},
     prelude::*,
-    protocol::{client_connected, input::QueuedInputs, ServerChannel},
-    stage::NetworkSimulationInfo,
+    utils::HashMap,
 };
+use serde::{Deserialize, Serialize};
 
+use crate::objects::{PotionEffects, PotionImpact, PotionKind};
 use crate::prelude::*;
 
+pub mod authority;
+pub mod client;
+pub mod conditioner;
+pub mod config;
+pub mod interpolate;
+pub mod lobby;
+pub mod prediction;
+pub mod protocol;
+pub mod replicate;
+pub mod server;
+pub mod transport;
 pub mod ui;
 
+pub mod prelude {
+    pub use super::{
+        authority::{Authority, DeniedGrab, GrabAuthority},
+        client::{ClientEvent, ClientState, NetworkClient},
+        conditioner::{LinkConditioner, LinkConditions},
+        config::ServerConfig,
+        interpolate::{InterpolationBuffer, InterpolationSettings, KinematicProxy},
+        lobby::{
+            Lobby, LobbyEvent, LobbyMember, LobbySettings, PlayerIdentity, PlayerProfile,
+            RequestReady, LOCAL_CLIENT_ID,
+        },
+        prediction::{InputHistory, Predicted, PredictionSettings, Resimulating},
+        protocol::Channel,
+        replicate::{Replicate, ReplicationRegistry, StableId},
+        server::{NetworkServer, ServerEvent},
+        transport::{LoopbackNetwork, Transport, UdpTransport},
+        ui::NetworkUiPlugin,
+        ClientId, ClientMessage, ClientMessageEvent, NetworkPlugin, NetworkSet, NetworkTick, Owned,
+        ServerEntities, ServerEntity, ServerMessage,
+    };
+}
+
+use self::{
+    authority::{AuthorityPlugin, BodyState, DeniedGrab},
+    client::{ClientEvent, NetworkClient},
+    conditioner::{sync_link_conditioner, LinkConditioner, LinkConditions},
+    interpolate::InterpolationBuffer,
+    interpolate::InterpolationPlugin,
+    lobby::{park_player, Lobby, LobbyEvent, LobbyMember, LobbyPlugin, LobbySettings},
+    prediction::{ConfirmedState, InputHistory, Predicted, PredictionHistory, PredictionPlugin},
+    protocol::Channel,
+    replicate::{Replicate, ReplicationPlugin, Snapshot, SnapshotBuffer},
+    server::{NetworkServer, ServerEvent},
+};
+
 pub const PORT: u16 = 42069;
 
+pub type ClientId = u64;
+
+/// Fixed ticks since the network started.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Resource)]
+pub struct NetworkTick(pub u64);
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum NetworkSet {
+    /// Read packets off the transport.
+    Receive,
+    /// React to connection changes and messages.
+    Process,
+    /// Flush outgoing messages, after the rest of the tick.
+    Send,
+}
+
 pub struct NetworkPlugin;
 
 impl Plugin for NetworkPlugin {
     fn build(&self, app: &mut App) {
-        use sabi::stage::NetworkSimulationAppExt;
+        app.register_type::<Owned>();
+        app.register_type::<LinkConditions>();
+        app.register_type::<LinkConditioner>();
+
+        app.add_event::<ServerEvent>();
+        app.add_event::<ClientEvent>();
+        app.add_event::<ClientMessageEvent>();
+        app.init_resource::<ServerEntities>();
+        app.init_resource::<NetworkTick>();
+        app.init_resource::<LinkConditioner>();
+
+        app.add_plugins(LobbyPlugin);
+        app.add_plugins(ReplicationPlugin);
+        app.add_plugins(PredictionPlugin);
+        app.add_plugins(InterpolationPlugin);
+        app.add_plugins(AuthorityPlugin);
 
-        app.add_system(
-            server_update_system
-                .run_if_resource_exists::<RenetServer>()
-                .label("server_update_system"),
+        app.configure_sets(
+            FixedUpdate,
+            (NetworkSet::Receive, NetworkSet::Process)
+                .chain()
+                .before(crate::FixedSet::First),
         );
+        app.configure_sets(FixedUpdate, NetworkSet::Send.after(crate::FixedSet::Last));
 
-        app.add_plugins(SabiPlugin::<PlayerInput> {
-            tick_rate: crate::TICK_RATE,
-            ..Default::default()
-        });
-
-        app.add_editor_window::<ui::NetworkWindow>();
-
-        app.insert_resource(QueuedInputs::<PlayerInput>::new());
-        let info = NetworkSimulationInfo::new(crate::TICK_RATE);
-        //info.slowdown = 3.0;
-        app.insert_resource(info);
-        app.add_system(
-            client_sync_players
-                //.run_if_resource_exists::<RenetClient>()
-                //.run_if(client_connected),
+        app.add_systems(
+            FixedUpdate,
+            (advance_tick, sync_link_conditioner)
+                .in_set(NetworkSet::Receive)
+                .before(server_receive)
+                .before(client_receive),
+        );
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                server_receive
+                    .in_set(NetworkSet::Receive)
+                    .run_if(resource_exists::<NetworkServer>),
+                (server_update_system, server_read_client_messages)
+                    .chain()
+                    .in_set(NetworkSet::Process)
+                    .run_if(resource_exists::<NetworkServer>),
+                (server_announce_players, server_send)
+                    .chain()
+                    .in_set(NetworkSet::Send)
+                    .run_if(resource_exists::<NetworkServer>),
+            ),
+        );
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                client_receive
+                    .in_set(NetworkSet::Receive)
+                    .run_if(resource_exists::<NetworkClient>),
+                client_sync_players
+                    .in_set(NetworkSet::Process)
+                    .run_if(resource_exists::<NetworkClient>),
+                client_send
+                    .in_set(NetworkSet::Send)
+                    .run_if(resource_exists::<NetworkClient>),
+            ),
         );
     }
 }
 
-fn server_update_system(
+/// This client is in control of the entity.
+#[derive(Default, Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Owned;
+
+/// An entity id as the server knows it.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ServerEntity(u64);
+
+impl From<Entity> for ServerEntity {
+    fn from(entity: Entity) -> Self {
+        Self(entity.to_bits())
+    }
+}
+
+impl ServerEntity {
+    /// The entity on the server, `None` if the bits can't be one.
+    ///
+    /// Clients send these, so they can't be trusted to be valid.
+    pub fn entity(self) -> Option<Entity> {
+        Entity::try_from_bits(self.0).ok()
+    }
+}
+
+/// Maps server entities to their local counterparts on the client.
+#[derive(Default, Debug, Resource)]
+pub struct ServerEntities {
+    entities: HashMap<ServerEntity, Entity>,
+    /// Entities we spawned ourselves, see [`ServerEntities::bind`].
+    bound: EntityHashSet,
+}
+
+impl ServerEntities {
+    pub fn get(&self, server_entity: ServerEntity) -> Option<Entity> {
+        self.entities.get(&server_entity).copied()
+    }
+
+    pub fn spawn_or_get(&mut self, commands: &mut Commands, server_entity: ServerEntity) -> Entity {
+        *self
+            .entities
+            .entry(server_entity)
+            .or_insert_with(|| commands.spawn_empty().id())
+    }
+
+    pub fn spawn_or_get_in(&mut self, world: &mut World, server_entity: ServerEntity) -> Entity {
+        *self
+            .entities
+            .entry(server_entity)
+            .or_insert_with(|| world.spawn_empty().id())
+    }
+
+    /// Map `server_entity` onto an entity we spawned ourselves, it outlives the connection.
+    pub fn bind(&mut self, server_entity: ServerEntity, entity: Entity) {
+        self.entities.insert(server_entity, entity);
+        self.bound.insert(entity);
+    }
+
+    /// Reverse lookup, only meant for the handful of entities we own.
+    pub fn server_entity(&self, entity: Entity) -> Option<ServerEntity> {
+        self.entities
+            .iter()
+            .find(|(_, local)| **local == entity)
+            .map(|(server_entity, _)| *server_entity)
+    }
+
+    pub fn remove(&mut self, server_entity: ServerEntity) -> Option<Entity> {
+        let entity = self.entities.remove(&server_entity)?;
+        self.bound.remove(&entity);
+        Some(entity)
+    }
+
+    /// Forget and despawn everything the server told us about, other than our own copies.
+    pub fn disconnect(&mut self, commands: &mut Commands, entities: &Entities) {
+        for (_, entity) in self.entities.drain() {
+            if !self.bound.remove(&entity) && entities.contains(entity) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ServerEntity, &Entity)> {
+        self.entities.iter()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    PlayerConnected {
+        id: ClientId,
+        entity: ServerEntity,
+        member: LobbyMember,
+    },
+    PlayerDisconnected {
+        id: ClientId,
+    },
+    SetPlayer {
+        id: ClientId,
+    },
+    PlayerReady {
+        id: ClientId,
+        ready: bool,
+    },
+    AssignOwnership {
+        entity: ServerEntity,
+    },
+    /// Simulate this body locally and send us the result.
+    GrantAuthority {
+        entity: ServerEntity,
+    },
+    RevokeAuthority {
+        entity: ServerEntity,
+    },
+    /// Someone else got to it first, let go.
+    GrabDenied {
+        entity: ServerEntity,
+    },
+    Snapshot(Snapshot),
+    /// A potion cracked, spawn its effect if it's one clients run too.
+    PotionCracked {
+        kind: String,
+        point: [f32; 3],
+        force: [f32; 3],
+        linvel: [f32; 3],
+        angvel: [f32; 3],
+        strength: f32,
+    },
+}
+
+/// A [`ClientMessage`] the server received.
+#[derive(Event, Debug, Clone)]
+pub struct ClientMessageEvent {
+    pub client_id: ClientId,
+    pub message: ClientMessage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// Inputs for `tick` followed by the ones for the ticks before it.
+    Input {
+        tick: u64,
+        inputs: Vec<PlayerInput>,
+    },
+    SetReady {
+        ready: bool,
+    },
+    /// State of a body we were granted authority over.
+    BodyState {
+        entity: ServerEntity,
+        state: BodyState,
+    },
+}
+
+pub fn advance_tick(mut tick: ResMut<NetworkTick>) {
+    tick.0 += 1;
+}
+
+pub fn server_receive(mut server: ResMut<NetworkServer>, mut events: EventWriter<ServerEvent>) {
+    events.send_batch(server.update());
+}
+
+pub fn server_send(time: Res<Time>, mut server: ResMut<NetworkServer>) {
+    server.send_packets(time.delta());
+}
+
+pub fn server_update_system(
     mut server_events: EventReader<ServerEvent>,
     mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<LobbySettings>,
     mut lobby: ResMut<Lobby>,
-    mut server: ResMut<RenetServer>,
+    mut server: ResMut<NetworkServer>,
     mut player_events: EventWriter<PlayerEvent>,
+    mut lobby_events: EventWriter<LobbyEvent>,
+    characters: Query<(&CharacterEntities, Option<&PlayerNeck>)>,
+    mut players: Query<&mut Player>,
 ) {
-    for event in server_events.iter() {
+    for event in server_events.read() {
         match event {
-            &ServerEvent::ClientConnected(id, _) => {
-                info!("player {} connected.", id);
-                player_events.send(PlayerEvent::Spawn { id });
+            &ServerEvent::ClientConnected(id) => {
+                let identity = server.client_identity(id).cloned().unwrap_or_default();
+                let member = LobbyMember {
+                    profile: identity.profile.sanitized(id),
+                    ready: false,
+                };
+                info!(
+                    "player {} ({}) joined from {:?} ({} connected).",
+                    id,
+                    member.profile.name,
+                    server.client_addr(id),
+                    server.clients_id().len()
+                );
+
+                lobby.tokens.insert(id, identity.token);
+                lobby.members.insert(id, member);
+
+                if let Some(away) = lobby.away.remove(&identity.token) {
+                    info!("player {} is back as player {}", away.id, id);
+                    if let Ok(mut player) = players.get_mut(away.entity) {
+                        player.id = id;
+                    }
+                    // Inputs from the old connection's ticks don't apply anymore.
+                    commands
+                        .entity(away.entity)
+                        .insert((Replicate, InputHistory::default()));
+                    announce_player(&mut lobby, &mut server, id, away.entity);
+                    lobby_events.send(LobbyEvent::Rejoined {
+                        id,
+                        previous: away.id,
+                    });
+                } else {
+                    player_events.send(PlayerEvent::Spawn { id });
+                    lobby_events.send(LobbyEvent::Joined { id });
+                }
             }
             &ServerEvent::ClientDisconnected(id) => {
-                info!("player {} disconnected.", id);
+                info!(
+                    "player {} left ({} connected).",
+                    id,
+                    server.clients_id().len()
+                );
+                let member = lobby.members.remove(&id).unwrap_or_default();
                 if let Some(player_entity) = lobby.players.remove(&id) {
-                    commands.entity(player_entity).despawn();
+                    let parked = park_player(
+                        &mut commands,
+                        &mut lobby,
+                        &settings,
+                        time.elapsed(),
+                        id,
+                        player_entity,
+                        member,
+                    );
+                    if !parked {
+                        despawn_player(&mut commands, player_entity, &characters);
+                    }
                 }
+                lobby.tokens.remove(&id);
+                lobby_events.send(LobbyEvent::Left { id });
 
                 let message =
                     bincode::serialize(&ServerMessage::PlayerDisconnected { id: id }).unwrap();
-                server.broadcast_message(ServerChannel::Message.id(), message);
+                server.broadcast_message(Channel::Reliable, message);
             }
         }
     }
 }
 
+pub fn server_read_client_messages(
+    mut server: ResMut<NetworkServer>,
+    mut messages: EventWriter<ClientMessageEvent>,
+) {
+    for client_id in server.clients_id() {
+        for channel in [Channel::Reliable, Channel::Unreliable] {
+            while let Some(message) = server.receive_message(client_id, channel) {
+                let Ok(message) = bincode::deserialize(&message) else {
+                    warn!("malformed message from client {}", client_id);
+                    continue;
+                };
+
+                messages.send(ClientMessageEvent { client_id, message });
+            }
+        }
+    }
+}
+
+/// Tell clients about players as they get spawned.
+pub fn server_announce_players(
+    mut commands: Commands,
+    mut lobby: ResMut<Lobby>,
+    mut server: ResMut<NetworkServer>,
+    players: Query<(Entity, &Player), Added<Player>>,
+) {
+    for (player_entity, player) in &players {
+        commands
+            .entity(player_entity)
+            .insert((Replicate, InputHistory::default()));
+        announce_player(&mut lobby, &mut server, player.id, player_entity);
+    }
+}
+
+/// Add a player to the lobby and tell everyone, including them, about it.
+pub fn announce_player(
+    lobby: &mut Lobby,
+    server: &mut NetworkServer,
+    id: ClientId,
+    player_entity: Entity,
+) {
+    // We could send an InitState with all the players id and positions for the client
+    // but this is easier to do.
+    for (existing_id, existing_entity) in lobby.players.iter() {
+        let message = bincode::serialize(&ServerMessage::PlayerConnected {
+            id: *existing_id,
+            entity: (*existing_entity).into(),
+            member: lobby.members.get(existing_id).cloned().unwrap_or_default(),
+        })
+        .unwrap();
+
+        server.send_message(id, Channel::Reliable, message);
+    }
+
+    lobby.players.insert(id, player_entity);
+
+    let message = bincode::serialize(&ServerMessage::PlayerConnected {
+        id: id,
+        entity: player_entity.into(),
+        member: lobby.members.get(&id).cloned().unwrap_or_default(),
+    })
+    .unwrap();
+    server.broadcast_message(Channel::Reliable, message);
+
+    let message = bincode::serialize(&ServerMessage::AssignOwnership {
+        entity: player_entity.into(),
+    })
+    .unwrap();
+    server.send_message(id, Channel::Reliable, message);
+
+    let message = bincode::serialize(&ServerMessage::SetPlayer { id }).unwrap();
+    server.send_message(id, Channel::Reliable, message);
+}
+
+pub fn client_receive(mut client: ResMut<NetworkClient>, mut events: EventWriter<ClientEvent>) {
+    events.send_batch(client.update());
+}
+
+pub fn client_send(time: Res<Time>, mut client: ResMut<NetworkClient>) {
+    client.send_packets(time.delta());
+}
+
 pub fn client_sync_players(
     mut commands: Commands,
     entities: &Entities,
     mut server_entities: ResMut<ServerEntities>,
-    mut client: ResMut<RenetClient>,
+    mut client: ResMut<NetworkClient>,
+    mut client_events: EventReader<ClientEvent>,
     mut lobby: ResMut<Lobby>,
     mut player_events: EventWriter<PlayerEvent>,
+    mut lobby_events: EventWriter<LobbyEvent>,
+    mut snapshots: ResMut<SnapshotBuffer>,
+    mut hands: Query<(Entity, &mut Grabbing, Option<&Children>), With<Hand>>,
+    grab_joints: Query<&ImpulseJoint, With<GrabJoint>>,
+    owned_players: Query<&CharacterEntities, (With<Player>, With<Owned>)>,
+    effects: Res<PotionEffects>,
 ) {
-    while let Some(message) = client.receive_message(ServerChannel::Message.id()) {
-        let server_message = bincode::deserialize(&message).unwrap();
+    for event in client_events.read() {
+        if let ClientEvent::Disconnected { .. } = event {
+            lobby.clear();
+            server_entities.disconnect(&mut commands, entities);
+        }
+    }
+
+    while let Some(message) = client.receive_message(Channel::Reliable) {
+        let Ok(server_message) = bincode::deserialize(&message) else {
+            warn!("malformed server message");
+            continue;
+        };
+
         match server_message {
             ServerMessage::PlayerConnected {
                 id,
                 entity: server_entity,
+                member,
             } => {
-                info!("player {} connected.", id);
+                info!("player {} ({}) connected.", id, member.profile.name);
                 let entity = server_entities.spawn_or_get(&mut commands, server_entity);
                 lobby.players.insert(id, entity);
+                lobby.members.insert(id, member);
+                player_events.send(PlayerEvent::Replicated {
+                    id,
+                    entity,
+                    local: client.client_id() == Some(id),
+                });
+                lobby_events.send(LobbyEvent::Joined { id });
             }
             ServerMessage::PlayerDisconnected { id } => {
                 info!("player {} disconnected.", id);
-                if let Some(player) = lobby.players.get(&id) {
-                    if entities.contains(*player) {
-                        commands.entity(*player).despawn_recursive();
+                lobby.members.remove(&id);
+                lobby_events.send(LobbyEvent::Left { id });
+                if let Some(player) = lobby.players.remove(&id) {
+                    if entities.contains(player) {
+                        commands.entity(player).despawn_recursive();
                     }
                 }
             }
             ServerMessage::SetPlayer { id } => {
                 info!("set up local player: {:?}.", id);
-                player_events.send(PlayerEvent::SetupLocal { id })
+                player_events.send(PlayerEvent::SetupLocal { id });
+            }
+            ServerMessage::PlayerReady { id, ready } => {
+                if let Some(member) = lobby.members.get_mut(&id) {
+                    member.ready = ready;
+                    lobby_events.send(LobbyEvent::Ready { id, ready });
+                }
             }
             ServerMessage::AssignOwnership {
                 entity: server_entity,
             } => {
                 let entity = server_entities.spawn_or_get(&mut commands, server_entity);
-                commands.entity(entity).insert(Owned);
-                println!(
-                    "Ownership assigned for entity {:?} (server id {:?})",
+                commands.entity(entity).insert((
+                    Owned,
+                    Predicted,
+                    PlayerInput::default(),
+                    InputHistory::default(),
+                    PredictionHistory::default(),
+                    ConfirmedState::default(),
+                ));
+                info!(
+                    "ownership assigned for entity {:?} (server id {:?})",
                     entity, server_entity
                 );
             }
+            ServerMessage::GrantAuthority {
+                entity: server_entity,
+            } => {
+                let entity = server_entities.spawn_or_get(&mut commands, server_entity);
+                commands.entity(entity).insert(Owned);
+            }
+            ServerMessage::RevokeAuthority {
+                entity: server_entity,
+            } => {
+                if let Some(entity) = server_entities.get(server_entity) {
+                    // Start interpolating again from fresh snapshots.
+                    commands
+                        .entity(entity)
+                        .remove::<Owned>()
+                        .insert(InterpolationBuffer::default());
+                }
+            }
+            ServerMessage::GrabDenied {
+                entity: server_entity,
+            } => {
+                let Some(entity) = server_entities.get(server_entity) else {
+                    continue;
+                };
+
+                for (hand, mut grabbing, children) in &mut hands {
+                    // Remote players' hands might be the ones that won the grab.
+                    if !owned_players
+                        .iter()
+                        .any(|character| character.contains(&hand))
+                    {
+                        continue;
+                    }
+
+                    let holding = grabbing
+                        .grabbed
+                        .is_some_and(|grabbed| grabbed.entity == entity);
+                    let joints = children
+                        .into_iter()
+                        .flatten()
+                        .filter(|child| {
+                            grab_joints
+                                .get(**child)
+                                .is_ok_and(|joint| joint.parent == entity)
+                        })
+                        .copied()
+                        .collect::<Vec<_>>();
+                    if !holding && joints.is_empty() {
+                        continue;
+                    }
+
+                    // Let go right away, the joint would keep pulling on the body otherwise.
+                    if holding {
+                        grabbing.grabbed = None;
+                    }
+                    for joint in joints {
+                        commands.entity(joint).despawn_recursive();
+                    }
+                    commands.entity(hand).insert(DeniedGrab(entity));
+                }
+            }
+            ServerMessage::Snapshot(snapshot) => snapshots.0.push_back(snapshot),
+            ServerMessage::PotionCracked {
+                kind,
+                point,
+                force,
+                linvel,
+                angvel,
+                strength,
+            } => {
+                let impact = PotionImpact {
+                    // Already gone on the server.
+                    potion: Entity::PLACEHOLDER,
+                    point: point.into(),
+                    force: force.into(),
+                    velocity: Velocity {
+                        linvel: linvel.into(),
+                        angvel: angvel.into(),
+                    },
+                    strength,
+                };
+                effects.spawn_on_client(&PotionKind::new(kind), &mut commands, &impact);
+            }
+        }
+    }
+
+    // Component state comes in unreliably and possibly out of order.
+    while let Some(message) = client.receive_message(Channel::Unreliable) {
+        match bincode::deserialize(&message) {
+            Ok(ServerMessage::Snapshot(snapshot)) => snapshots.0.push_back(snapshot),
+            Ok(_) => warn!("unexpected unreliable server message"),
+            Err(_) => warn!("malformed server message"),
+        }
+    }
+    snapshots
+        .0
+        .make_contiguous()
+        .sort_by_key(|snapshot| snapshot.tick);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use super::prelude::*;
+    use crate::{headless::HeadlessApp, prelude::*};
+
+    const SERVER_ADDR: &str = "127.0.0.1:1000";
+
+    fn serve() -> (LoopbackNetwork, HeadlessApp) {
+        let network = LoopbackNetwork::new();
+        let mut server = HeadlessApp::new();
+        server.add_plugins(NetworkPlugin);
+        server.app_mut().insert_resource(NetworkServer::new(
+            network.bind(SERVER_ADDR.parse().unwrap()),
+        ));
+
+        (network, server)
+    }
+
+    fn join(network: &LoopbackNetwork, server: &mut HeadlessApp, addr: &str) -> HeadlessApp {
+        let server_addr: SocketAddr = SERVER_ADDR.parse().unwrap();
+        let mut client = HeadlessApp::new();
+        client.add_plugins(NetworkPlugin);
+        client.app_mut().insert_resource(NetworkClient::new(
+            network.bind(addr.parse().unwrap()),
+            server_addr,
+        ));
+
+        for _ in 0..10 {
+            server.tick();
+            client.tick();
+        }
+
+        client
+    }
+
+    fn connect() -> (HeadlessApp, HeadlessApp) {
+        let (network, mut server) = serve();
+        let client = join(&network, &mut server, "127.0.0.1:1001");
+        (server, client)
+    }
+
+    fn player_ids(app: &mut HeadlessApp) -> Vec<ClientId> {
+        let world = app.world_mut();
+        world
+            .query::<&Player>()
+            .iter(world)
+            .map(|player| player.id)
+            .collect()
+    }
+
+    #[test]
+    fn connect_spawns_player() {
+        let (mut server, client) = connect();
+
+        let id = client
+            .world()
+            .resource::<NetworkClient>()
+            .client_id()
+            .expect("client should be connected");
+        assert_eq!(player_ids(&mut server), vec![id]);
+
+        let server_player = server.world().resource::<Lobby>().players[&id];
+        let client_player = client.world().resource::<Lobby>().players[&id];
+        assert!(client.world().get::<Owned>(client_player).is_some());
+        assert!(
+            client.world().get::<Transform>(client_player).is_some(),
+            "player should be replicated"
+        );
+        assert_eq!(
+            client
+                .world()
+                .resource::<ServerEntities>()
+                .get(server_player.into()),
+            Some(client_player)
+        );
+    }
+
+    #[test]
+    fn inputs_reach_server() {
+        let (mut server, mut client) = connect();
+
+        let id = client
+            .world()
+            .resource::<NetworkClient>()
+            .client_id()
+            .unwrap();
+        let client_player = client.world().resource::<Lobby>().players[&id];
+        client
+            .world_mut()
+            .get_mut::<PlayerInput>(client_player)
+            .expect("owned player should take input")
+            .set_forward(true);
+
+        for _ in 0..20 {
+            server.tick();
+            client.tick();
+        }
+
+        let server_player = server.world().resource::<Lobby>().players[&id];
+        assert!(server
+            .world()
+            .get::<PlayerInput>(server_player)
+            .unwrap()
+            .forward());
+    }
+
+    #[test]
+    fn remote_players_are_kinematic_proxies() {
+        let (network, mut server) = serve();
+        let mut first = join(&network, &mut server, "127.0.0.1:1001");
+        let mut second = join(&network, &mut server, "127.0.0.1:1002");
+        for _ in 0..10 {
+            server.tick();
+            first.tick();
+            second.tick();
+        }
+
+        let client_id =
+            |app: &HeadlessApp| app.world().resource::<NetworkClient>().client_id().unwrap();
+        let players = &first.world().resource::<Lobby>().players;
+        let local = players[&client_id(&first)];
+        let remote = players[&client_id(&second)];
+
+        assert!(first.world().get::<PlayerCamera>(local).is_some());
+        assert!(first
+            .world()
+            .get::<bevy_mod_wanderlust::Controller>(local)
+            .is_some());
+
+        assert!(first.world().get::<PlayerCamera>(remote).is_none());
+        assert!(first
+            .world()
+            .get::<bevy_mod_wanderlust::Controller>(remote)
+            .is_none());
+        assert!(first.world().get::<KinematicProxy>(remote).is_some());
+        assert_eq!(
+            first.world().get::<RigidBody>(remote),
+            Some(&RigidBody::KinematicPositionBased),
+            "snapshots shouldn't make the proxy dynamic again"
+        );
+    }
+
+    #[test]
+    fn props_bind_to_local_copies() {
+        let spawn_prop = |app: &mut HeadlessApp| {
+            app.world_mut()
+                .spawn((
+                    TransformBundle::from_transform(Transform::from_xyz(3.0, 1.0, 0.0)),
+                    RigidBody::Dynamic,
+                    Collider::ball(0.2),
+                    Name::new("prop"),
+                ))
+                .id()
+        };
+
+        let (mut server, mut client) = connect();
+        let server_prop = spawn_prop(&mut server);
+        let client_prop = spawn_prop(&mut client);
+        for _ in 0..10 {
+            server.tick();
+            client.tick();
+        }
+
+        assert!(server.world().get::<Replicate>(server_prop).is_some());
+        assert_eq!(
+            client
+                .world()
+                .resource::<ServerEntities>()
+                .get(server_prop.into()),
+            Some(client_prop)
+        );
+        assert!(client
+            .world()
+            .get::<InterpolationBuffer>(client_prop)
+            .is_some());
+
+        let world = client.world_mut();
+        let props = world
+            .query::<&Name>()
+            .iter(world)
+            .filter(|name| name.as_str() == "prop")
+            .count();
+        assert_eq!(props, 1, "the server's copy shouldn't spawn another");
+    }
+
+    #[test]
+    fn contested_grab_is_denied() {
+        let (network, mut server) = serve();
+        let mut clients = [
+            join(&network, &mut server, "127.0.0.1:1001"),
+            join(&network, &mut server, "127.0.0.1:1002"),
+        ];
+
+        let spawn_prop = |app: &mut HeadlessApp| {
+            app.world_mut()
+                .spawn((
+                    TransformBundle::from_transform(Transform::from_xyz(20.0, 1.0, 20.0)),
+                    RigidBody::Dynamic,
+                    Collider::ball(0.2),
+                    Name::new("prop"),
+                ))
+                .id()
+        };
+        let prop = spawn_prop(&mut server);
+        let client_props = clients.each_mut().map(spawn_prop);
+
+        let players = |app: &HeadlessApp| {
+            let id = app.world().resource::<NetworkClient>().client_id().unwrap();
+            (id, app.world().resource::<Lobby>().players[&id])
+        };
+        let [(first_id, _), (second_id, second_player)] = clients.each_ref().map(players);
+
+        // Both players reach out with their first arm.
+        let tick = |server: &mut HeadlessApp, clients: &mut [HeadlessApp; 2]| {
+            server.tick();
+            for client in clients.iter_mut() {
+                let (_, player) = players(&*client);
+                client
+                    .world_mut()
+                    .get_mut::<PlayerInput>(player)
+                    .unwrap()
+                    .set_extend_arm(0, true);
+                client.tick();
+            }
+        };
+        for _ in 0..20 {
+            tick(&mut server, &mut clients);
         }
+
+        let hand = |app: &mut HeadlessApp, player: Entity| {
+            let character = app
+                .world()
+                .get::<CharacterEntities>(player)
+                .unwrap()
+                .iter()
+                .copied()
+                .collect::<Vec<_>>();
+            let world = app.world_mut();
+            world
+                .query_filtered::<(Entity, &ArmId), With<Hand>>()
+                .iter(world)
+                .find(|(entity, arm)| arm.0 == 0 && character.contains(entity))
+                .map(|(entity, _)| entity)
+                .unwrap()
+        };
+        let grab = |app: &mut HeadlessApp, hand: Entity, entity: Entity| {
+            app.world_mut().get_mut::<Grabbing>(hand).unwrap().grabbed = Some(Grabbed {
+                entity,
+                local_grab_point: Vec3::ZERO,
+                global_grab_point: Vec3::ZERO,
+                teleport_entity: false,
+            });
+        };
+
+        // The second client grabs it locally, the server saw the first player grab it first.
+        let second_hand = hand(&mut clients[1], second_player);
+        grab(&mut clients[1], second_hand, client_props[1]);
+        let lobby = server.world().resource::<Lobby>();
+        let (first_server, second_server) = (lobby.players[&first_id], lobby.players[&second_id]);
+        let first_server_hand = hand(&mut server, first_server);
+        let second_server_hand = hand(&mut server, second_server);
+
+        grab(&mut server, first_server_hand, prop);
+        tick(&mut server, &mut clients);
+        grab(&mut server, second_server_hand, prop);
+        for _ in 0..10 {
+            tick(&mut server, &mut clients);
+        }
+
+        assert_eq!(
+            server.world().resource::<GrabAuthority>().authority(prop),
+            Authority::Client(first_id)
+        );
+        assert!(server
+            .world()
+            .get::<Grabbing>(second_server_hand)
+            .unwrap()
+            .grabbed
+            .is_none());
+        assert!(clients[0].world().get::<Owned>(client_props[0]).is_some());
+
+        let second = &mut clients[1];
+        assert!(second.world().get::<Owned>(client_props[1]).is_none());
+        assert!(second
+            .world()
+            .get::<Grabbing>(second_hand)
+            .unwrap()
+            .grabbed
+            .is_none());
+        let world = second.world_mut();
+        let joints = world
+            .query_filtered::<&ImpulseJoint, With<GrabJoint>>()
+            .iter(world)
+            .filter(|joint| joint.parent == client_props[1])
+            .count();
+        assert_eq!(joints, 0, "the denied client should let go");
+    }
+
+    #[test]
+    fn late_joiners_get_unchanged_components() {
+        let (network, mut server) = serve();
+        let prop = server
+            .world_mut()
+            .spawn((Name::new("still"), Replicate))
+            .id();
+        server.step(5);
+
+        let client = join(&network, &mut server, "127.0.0.1:1001");
+        let entity = client
+            .world()
+            .resource::<ServerEntities>()
+            .get(prop.into())
+            .expect("keyframe should bring the prop");
+        assert_eq!(
+            client.world().get::<Name>(entity).map(Name::as_str),
+            Some("still")
+        );
+    }
+
+    #[test]
+    fn bogus_body_state_is_dropped() {
+        let (mut server, mut client) = connect();
+
+        let message = bincode::serialize(&ClientMessage::BodyState {
+            entity: ServerEntity(0),
+            state: super::BodyState::new(&Transform::default(), &Velocity::default()),
+        })
+        .unwrap();
+        client
+            .world_mut()
+            .resource_mut::<NetworkClient>()
+            .send_message(Channel::Unreliable, message);
+
+        for _ in 0..10 {
+            client.tick();
+            server.tick();
+        }
+        assert_eq!(
+            player_ids(&mut server).len(),
+            1,
+            "server should keep running"
+        );
+    }
+
+    #[test]
+    fn wrong_password_is_denied() {
+        let network = LoopbackNetwork::new();
+        let server_addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+
+        let mut server = HeadlessApp::new();
+        server.add_plugins(NetworkPlugin);
+        server
+            .app_mut()
+            .insert_resource(NetworkServer::new(network.bind(server_addr)).with_password("potion"));
+
+        let mut client = HeadlessApp::new();
+        client.add_plugins(NetworkPlugin);
+        client.app_mut().insert_resource(
+            NetworkClient::new(network.bind("127.0.0.1:1001".parse().unwrap()), server_addr)
+                .with_password("cellar"),
+        );
+
+        for _ in 0..10 {
+            server.tick();
+            client.tick();
+        }
+
+        assert_eq!(
+            client.world().resource::<NetworkClient>().state(),
+            &ClientState::Disconnected("wrong password".to_owned())
+        );
+        assert!(player_ids(&mut server).is_empty());
+    }
+
+    #[test]
+    fn disconnect_despawns_player() {
+        let (mut server, mut client) = connect();
+        server
+            .world_mut()
+            .resource_mut::<LobbySettings>()
+            .grace_period = Duration::ZERO;
+
+        client
+            .world_mut()
+            .resource_mut::<NetworkClient>()
+            .disconnect();
+        server.step(2);
+
+        assert!(server.world().resource::<Lobby>().players.is_empty());
+        assert!(player_ids(&mut server).is_empty());
+        assert!(server
+            .world()
+            .resource::<NetworkServer>()
+            .clients_id()
+            .is_empty());
+    }
+
+    #[test]
+    fn reconnect_reattaches_character() {
+        let network = LoopbackNetwork::new();
+        let server_addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let identity = PlayerIdentity {
+            profile: PlayerProfile {
+                name: "Mortar".to_owned(),
+                ..default()
+            },
+            token: 42,
+        };
+
+        let mut server = HeadlessApp::new();
+        server.add_plugins(NetworkPlugin);
+        server
+            .app_mut()
+            .insert_resource(NetworkServer::new(network.bind(server_addr)));
+
+        let join = |server: &mut HeadlessApp, addr: &str| {
+            let mut client = HeadlessApp::new();
+            client.add_plugins(NetworkPlugin);
+            client.app_mut().insert_resource(
+                NetworkClient::new(network.bind(addr.parse().unwrap()), server_addr)
+                    .with_identity(identity.clone()),
+            );
+            for _ in 0..10 {
+                server.tick();
+                client.tick();
+            }
+            let id = client
+                .world()
+                .resource::<NetworkClient>()
+                .client_id()
+                .unwrap();
+            (client, id)
+        };
+
+        let (mut client, first_id) = join(&mut server, "127.0.0.1:1001");
+        let character = server.world().resource::<Lobby>().players[&first_id];
+        assert_eq!(
+            server.world().resource::<Lobby>().members[&first_id]
+                .profile
+                .name,
+            "Mortar"
+        );
+
+        client
+            .world_mut()
+            .resource_mut::<NetworkClient>()
+            .disconnect();
+        server.step(2);
+        assert!(server.world().get::<Inventory>(character).is_some());
+        assert_eq!(server.world().resource::<Lobby>().away.len(), 1);
+
+        let (client, second_id) = join(&mut server, "127.0.0.1:1002");
+        assert_ne!(first_id, second_id);
+
+        let lobby = server.world().resource::<Lobby>();
+        assert_eq!(lobby.players[&second_id], character);
+        assert!(lobby.away.is_empty());
+        assert_eq!(
+            server.world().get::<Player>(character).unwrap().id,
+            second_id
+        );
+        assert!(client
+            .world()
+            .resource::<Lobby>()
+            .players
+            .contains_key(&second_id));
     }
 }
@@ -0,0 +1,483 @@
+//! Client side prediction of the local player.
+//!
+//! The client simulates its own character immediately and streams its inputs
+//! to the server, which simulates every player from those inputs and stays
+//! authoritative. When a snapshot disagrees with what we predicted for that
+//! tick, the character is reset to the server state and the inputs since then
+//! are replayed.
+use std::any::TypeId;
+
+use bevy::ecs::entity::EntityHashSet;
+
+use crate::prelude::*;
+
+use super::{
+    client_sync_players, protocol::Channel, replicate::client_apply_snapshots,
+    replicate::SnapshotBuffer, server_read_client_messages, ClientMessage, ClientMessageEvent,
+    Lobby, NetworkClient, NetworkServer, NetworkSet, NetworkTick, Owned,
+};
+
+/// Ticks of input and prediction history kept around.
+pub const HISTORY_LEN: usize = 128;
+/// Inputs resent with every input message in case some get lost.
+pub const REDUNDANT_INPUTS: u64 = 4;
+
+pub struct PredictionPlugin;
+
+impl Plugin for PredictionPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<PredictionSettings>();
+        app.init_resource::<PredictionSettings>();
+
+        // Nothing goes over the wire while replaying old ticks.
+        app.configure_sets(
+            FixedUpdate,
+            (NetworkSet::Receive, NetworkSet::Process, NetworkSet::Send)
+                .run_if(not(resource_exists::<Resimulating>)),
+        );
+
+        app.add_systems(
+            FixedPreUpdate,
+            client_reconcile.run_if(resource_exists::<NetworkClient>),
+        );
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                server_receive_inputs
+                    .in_set(NetworkSet::Process)
+                    .after(server_read_client_messages)
+                    .run_if(resource_exists::<NetworkServer>),
+                server_apply_inputs
+                    .in_set(crate::FixedSet::First)
+                    .run_if(resource_exists::<NetworkServer>),
+                client_align_tick
+                    .in_set(NetworkSet::Process)
+                    .after(client_sync_players)
+                    .before(client_apply_snapshots)
+                    .run_if(resource_exists::<NetworkClient>),
+                client_remove_remote_inputs
+                    .after(crate::FixedSet::First)
+                    .before(crate::FixedSet::Update)
+                    .run_if(resource_exists::<NetworkClient>),
+                client_send_inputs
+                    .in_set(NetworkSet::Process)
+                    .after(client_sync_players)
+                    .run_if(resource_exists::<NetworkClient>),
+                record_prediction
+                    .after(crate::FixedSet::Last)
+                    .after(PhysicsSet::Writeback)
+                    .run_if(resource_exists::<NetworkClient>),
+            ),
+        );
+    }
+}
+
+#[derive(Resource, Debug, Clone, Reflect)]
+#[reflect(Resource)]
+pub struct PredictionSettings {
+    /// How far the predicted position can drift from the server's before rolling back.
+    pub rollback_threshold: f32,
+    /// How many ticks the client runs ahead of the latest snapshot.
+    pub lead_ticks: u64,
+}
+
+impl Default for PredictionSettings {
+    fn default() -> Self {
+        Self {
+            rollback_threshold: 0.25,
+            lead_ticks: 8,
+        }
+    }
+}
+
+/// Present while old ticks are being replayed after a rollback.
+#[derive(Resource, Default, Debug)]
+pub struct Resimulating;
+
+/// Fixed size history keyed by tick, old entries get overwritten.
+#[derive(Debug, Clone)]
+pub struct TickBuffer<T> {
+    entries: Vec<Option<(u64, T)>>,
+}
+
+impl<T> Default for TickBuffer<T> {
+    fn default() -> Self {
+        Self {
+            entries: (0..HISTORY_LEN).map(|_| None).collect(),
+        }
+    }
+}
+
+impl<T> TickBuffer<T> {
+    fn index(&self, tick: u64) -> usize {
+        (tick % self.entries.len() as u64) as usize
+    }
+
+    pub fn insert(&mut self, tick: u64, value: T) {
+        let index = self.index(tick);
+        self.entries[index] = Some((tick, value));
+    }
+
+    pub fn get(&self, tick: u64) -> Option<&T> {
+        match &self.entries[self.index(tick)] {
+            Some((entry_tick, value)) if *entry_tick == tick => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Most recent entry at or before `tick`.
+    pub fn latest(&self, tick: u64) -> Option<(u64, &T)> {
+        self.entries
+            .iter()
+            .flatten()
+            .filter(|(entry_tick, _)| *entry_tick <= tick)
+            .max_by_key(|(entry_tick, _)| *entry_tick)
+            .map(|(entry_tick, value)| (*entry_tick, value))
+    }
+}
+
+/// Inputs by tick, sent by the owning client.
+#[derive(Default, Debug, Clone, Component, Deref, DerefMut)]
+pub struct InputHistory(pub TickBuffer<PlayerInput>);
+
+/// What we predicted the character looked like at the end of each tick.
+#[derive(Default, Debug, Clone, Component, Deref, DerefMut)]
+pub struct PredictionHistory(pub TickBuffer<(Transform, Velocity)>);
+
+/// Locally simulated ahead of the server, see [`client_reconcile`].
+#[derive(Default, Debug, Clone, Copy, Component)]
+pub struct Predicted;
+
+/// Latest authoritative state of a [`Predicted`] entity.
+///
+/// Snapshots write here instead of overwriting the prediction.
+#[derive(Default, Debug, Clone, Component)]
+pub struct ConfirmedState {
+    pub tick: u64,
+    pub transform: Option<Transform>,
+    pub velocity: Option<Velocity>,
+    /// Not compared against the prediction yet.
+    pub pending: bool,
+}
+
+impl ConfirmedState {
+    /// Keep the value if it is part of the predicted state, returns `false` otherwise.
+    pub fn capture(&mut self, tick: u64, type_id: TypeId, value: &dyn Reflect) -> bool {
+        if type_id == TypeId::of::<Transform>() {
+            self.transform = Transform::from_reflect(value);
+        } else if type_id == TypeId::of::<Velocity>() {
+            self.velocity = Velocity::from_reflect(value);
+        } else {
+            return false;
+        }
+
+        self.tick = tick;
+        self.pending = true;
+        true
+    }
+}
+
+pub fn client_align_tick(
+    snapshots: Res<SnapshotBuffer>,
+    settings: Res<PredictionSettings>,
+    mut tick: ResMut<NetworkTick>,
+    mut aligned: Local<bool>,
+) {
+    let Some(latest) = snapshots.0.back() else {
+        return;
+    };
+
+    let target = latest.tick + settings.lead_ticks;
+    if !*aligned || tick.0.abs_diff(target) > settings.lead_ticks {
+        info!("aligning client tick {} -> {}", tick.0, target);
+        tick.0 = target;
+        *aligned = true;
+    }
+}
+
+/// Other players are driven by snapshots, not by simulating their inputs locally.
+pub fn client_remove_remote_inputs(
+    mut commands: Commands,
+    remote: Query<Entity, (With<Player>, With<PlayerInput>, Without<Owned>)>,
+) {
+    for entity in &remote {
+        commands.entity(entity).remove::<PlayerInput>();
+    }
+}
+
+pub fn client_send_inputs(
+    tick: Res<NetworkTick>,
+    mut client: ResMut<NetworkClient>,
+    mut players: Query<(&PlayerInput, &mut InputHistory), With<Owned>>,
+) {
+    let Ok((input, mut history)) = players.get_single_mut() else {
+        return;
+    };
+
+    history.insert(tick.0, *input);
+
+    let inputs = (0..REDUNDANT_INPUTS)
+        .map_while(|offset| tick.0.checked_sub(offset))
+        .map_while(|tick| history.get(tick).copied())
+        .collect();
+    let message = bincode::serialize(&ClientMessage::Input {
+        tick: tick.0,
+        inputs,
+    })
+    .unwrap();
+    client.send_message(Channel::Unreliable, message);
+}
+
+pub fn server_receive_inputs(
+    tick: Res<NetworkTick>,
+    lobby: Res<Lobby>,
+    mut messages: EventReader<ClientMessageEvent>,
+    mut histories: Query<&mut InputHistory>,
+) {
+    for event in messages.read() {
+        let ClientMessage::Input {
+            tick: input_tick,
+            inputs,
+        } = &event.message
+        else {
+            continue;
+        };
+
+        let Some(mut history) = lobby
+            .players
+            .get(&event.client_id)
+            .and_then(|player| histories.get_mut(*player).ok())
+        else {
+            continue;
+        };
+
+        for (offset, input) in inputs.iter().enumerate() {
+            let Some(input_tick) = input_tick.checked_sub(offset as u64) else {
+                break;
+            };
+
+            // Too late to matter.
+            if input_tick < tick.0 {
+                break;
+            }
+
+            history.insert(input_tick, *input);
+        }
+    }
+}
+
+pub fn server_apply_inputs(
+    tick: Res<NetworkTick>,
+    mut players: Query<(&mut PlayerInput, &InputHistory)>,
+) {
+    for (mut input, history) in &mut players {
+        let Some((input_tick, latest)) = history.latest(tick.0) else {
+            continue;
+        };
+
+        *input = *latest;
+        if input_tick != tick.0 {
+            // Missing input, assume the same keys are held but don't repeat one-off actions.
+            input.inventory_swap = None;
+        }
+    }
+}
+
+pub fn record_prediction(
+    tick: Res<NetworkTick>,
+    mut predicted: Query<(&Transform, &Velocity, &mut PredictionHistory), With<Predicted>>,
+) {
+    for (transform, velocity, mut history) in &mut predicted {
+        history.insert(tick.0, (*transform, *velocity));
+    }
+}
+
+/// Roll back and replay the local character if the server disagrees with our prediction.
+pub fn client_reconcile(world: &mut World) {
+    let settings = world.resource::<PredictionSettings>().clone();
+    let current = world.resource::<NetworkTick>().0;
+
+    let mut predicted = world
+        .query_filtered::<(Entity, &mut ConfirmedState, &PredictionHistory), With<Predicted>>();
+
+    let mut rollback = None;
+    for (entity, mut confirmed, history) in predicted.iter_mut(world) {
+        if !confirmed.pending {
+            continue;
+        }
+        confirmed.pending = false;
+
+        let Some((predicted_transform, predicted_velocity)) = history.get(confirmed.tick) else {
+            continue;
+        };
+        let Some(transform) = confirmed.transform else {
+            continue;
+        };
+
+        let error = predicted_transform
+            .translation
+            .distance(transform.translation);
+        if error > settings.rollback_threshold {
+            let velocity = confirmed.velocity.unwrap_or(*predicted_velocity);
+            rollback = Some((entity, confirmed.tick, transform, velocity, error));
+        }
+    }
+
+    let Some((entity, from_tick, transform, velocity, error)) = rollback else {
+        return;
+    };
+
+    info!(
+        "rolling back {} ticks, prediction was off by {:.3}",
+        current.saturating_sub(from_tick),
+        error
+    );
+
+    // Move the arms along with the body, keeping their current offsets.
+    let mut character = world
+        .get::<CharacterEntities>(entity)
+        .map(|character| character.iter().copied().collect::<EntityHashSet>())
+        .unwrap_or_default();
+    character.insert(entity);
+
+    let Some(root) = world.get::<Transform>(entity).copied() else {
+        return;
+    };
+    for &part in &character {
+        if let Some(mut part_transform) = world.get_mut::<Transform>(part) {
+            let offset = part_transform.translation - root.translation;
+            part_transform.translation = transform.translation + offset;
+        }
+        if let Some(mut part_velocity) = world.get_mut::<Velocity>(part) {
+            *part_velocity = velocity;
+        }
+    }
+    if let Some(mut root_transform) = world.get_mut::<Transform>(entity) {
+        *root_transform = transform;
+    }
+
+    if current <= from_tick || current - from_tick >= HISTORY_LEN as u64 {
+        // Nothing to replay, or too far behind to bother.
+        return;
+    }
+
+    // Everything else holds still while we replay.
+    let mut bodies = world.query_filtered::<(Entity, &Transform, &Velocity), With<RigidBody>>();
+    let frozen = bodies
+        .iter(world)
+        .filter(|(body, ..)| !character.contains(body))
+        .map(|(body, transform, velocity)| (body, *transform, *velocity))
+        .collect::<Vec<_>>();
+
+    let live_input = world.get::<PlayerInput>(entity).copied();
+
+    world.insert_resource(Resimulating);
+    for tick in from_tick + 1..=current {
+        world.resource_mut::<NetworkTick>().0 = tick;
+
+        let input = world
+            .get::<InputHistory>(entity)
+            .and_then(|history| history.get(tick).copied());
+        if let (Some(input), Some(mut player_input)) = (input, world.get_mut::<PlayerInput>(entity))
+        {
+            *player_input = input;
+        }
+
+        world.run_schedule(FixedUpdate);
+
+        for (body, transform, velocity) in &frozen {
+            if let Some(mut body_transform) = world.get_mut::<Transform>(*body) {
+                *body_transform = *transform;
+            }
+            if let Some(mut body_velocity) = world.get_mut::<Velocity>(*body) {
+                *body_velocity = *velocity;
+            }
+        }
+    }
+    world.remove_resource::<Resimulating>();
+
+    world.resource_mut::<NetworkTick>().0 = current;
+    if let (Some(input), Some(mut player_input)) =
+        (live_input, world.get_mut::<PlayerInput>(entity))
+    {
+        *player_input = input;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::{headless::HeadlessApp, network::NetworkPlugin};
+
+    #[derive(Resource, Default)]
+    struct Ran {
+        ticks: usize,
+        gameplay: usize,
+    }
+
+    #[test]
+    fn rollback_skips_gameplay() {
+        let network = LoopbackNetwork::new();
+        let server_addr: SocketAddr = "127.0.0.1:1000".parse().unwrap();
+        let client_addr: SocketAddr = "127.0.0.1:1001".parse().unwrap();
+
+        let mut app = HeadlessApp::new();
+        app.add_plugins(NetworkPlugin);
+        app.app_mut()
+            .insert_resource(NetworkClient::new(network.bind(client_addr), server_addr))
+            .init_resource::<Ran>();
+        app.add_systems(
+            FixedUpdate,
+            (
+                (|mut ran: ResMut<Ran>| ran.ticks += 1),
+                (|mut ran: ResMut<Ran>| ran.gameplay += 1).in_set(GameplaySet),
+            ),
+        );
+        app.step(10);
+
+        // The server saw us somewhere else three ticks ago.
+        let tick = app.world().resource::<NetworkTick>().0;
+        let mut history = PredictionHistory::default();
+        history.insert(tick - 3, (Transform::IDENTITY, Velocity::zero()));
+        app.world_mut().spawn((
+            Predicted,
+            TransformBundle::default(),
+            Velocity::zero(),
+            history,
+            ConfirmedState {
+                tick: tick - 3,
+                transform: Some(Transform::from_xyz(5.0, 0.0, 0.0)),
+                velocity: Some(Velocity::zero()),
+                pending: true,
+            },
+        ));
+
+        *app.world_mut().resource_mut::<Ran>() = Ran::default();
+        app.tick();
+
+        let ran = app.world().resource::<Ran>();
+        assert_eq!(ran.ticks, 4, "three replayed ticks and the live one");
+        assert_eq!(ran.gameplay, 1, "gameplay only runs for the live tick");
+    }
+
+    #[test]
+    fn tick_buffer_wraps() {
+        let mut buffer = TickBuffer::default();
+        for tick in 0..HISTORY_LEN as u64 + 10 {
+            buffer.insert(tick, tick * 2);
+        }
+
+        assert_eq!(buffer.get(5), None, "overwritten by a later tick");
+        assert_eq!(
+            buffer.get(HISTORY_LEN as u64 + 5),
+            Some(&((HISTORY_LEN as u64 + 5) * 2))
+        );
+        assert_eq!(
+            buffer.latest(u64::MAX).map(|(tick, _)| tick),
+            Some(HISTORY_LEN as u64 + 9)
+        );
+    }
+}
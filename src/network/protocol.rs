@@ -0,0 +1,357 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    lobby::PlayerIdentity,
+    transport::{Transport, MAX_PACKET_SIZE},
+    ClientId,
+};
+
+/// Bumped whenever [`Packet`] or the message enums change shape.
+pub const PROTOCOL_ID: u64 = 5;
+
+/// Send a heartbeat if nothing else has gone out for this long.
+pub const HEARTBEAT: Duration = Duration::from_millis(100);
+/// Resend unacknowledged reliable messages after this long.
+pub const RESEND_AFTER: Duration = Duration::from_millis(200);
+/// Drop the connection if we haven't heard from the peer for this long.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+/// Bytes [`Packet::Reliable`] adds around a payload: variant, sequence and length.
+pub const PACKET_OVERHEAD: usize = 4 + 8 + 8;
+/// Largest payload that still fits in a single datagram.
+pub const MAX_MESSAGE_SIZE: usize = MAX_PACKET_SIZE - PACKET_OVERHEAD;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Channel {
+    /// Resent until acknowledged, delivered in order.
+    Reliable,
+    /// Fire and forget, may arrive out of order or not at all.
+    Unreliable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Packet {
+    ConnectRequest {
+        protocol: u64,
+        password: Option<String>,
+        identity: PlayerIdentity,
+    },
+    ConnectAccepted {
+        client_id: ClientId,
+    },
+    ConnectDenied {
+        reason: String,
+    },
+    Disconnect,
+    Heartbeat,
+    Reliable {
+        sequence: u64,
+        payload: Vec<u8>,
+    },
+    Unreliable {
+        payload: Vec<u8>,
+    },
+    Ack {
+        sequences: Vec<u64>,
+    },
+}
+
+impl Packet {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize(bytes).ok()
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ChannelStats {
+    pub sent_packets: u64,
+    pub sent_bytes: u64,
+    pub received_packets: u64,
+    pub received_bytes: u64,
+}
+
+/// Running totals for a [`Connection`].
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ConnectionStats {
+    /// Smoothed round trip time of acknowledged reliable messages.
+    pub rtt: Duration,
+    /// Reliable messages sent again because the ack didn't come back in time.
+    pub resent: u64,
+    /// Messages dropped for being larger than [`MAX_MESSAGE_SIZE`].
+    pub oversized: u64,
+    pub reliable: ChannelStats,
+    pub unreliable: ChannelStats,
+}
+
+impl ConnectionStats {
+    pub fn channel(&self, channel: Channel) -> &ChannelStats {
+        match channel {
+            Channel::Reliable => &self.reliable,
+            Channel::Unreliable => &self.unreliable,
+        }
+    }
+
+    fn channel_mut(&mut self, channel: Channel) -> &mut ChannelStats {
+        match channel {
+            Channel::Reliable => &mut self.reliable,
+            Channel::Unreliable => &mut self.unreliable,
+        }
+    }
+
+    fn add_rtt_sample(&mut self, sample: Duration) {
+        self.rtt = if self.rtt.is_zero() {
+            sample
+        } else {
+            self.rtt.mul_f32(0.9) + sample.mul_f32(0.1)
+        };
+    }
+}
+
+#[derive(Debug)]
+struct Unacked {
+    /// `None` until it went out the first time.
+    since_sent: Option<Duration>,
+    /// Time since it first went out.
+    age: Duration,
+    resent: bool,
+    payload: Vec<u8>,
+}
+
+/// Message channels to a single peer on top of an unreliable [`Transport`].
+#[derive(Debug)]
+pub struct Connection {
+    addr: SocketAddr,
+
+    since_received: Duration,
+    since_sent: Duration,
+
+    next_sequence: u64,
+    /// Reliable messages the peer hasn't acknowledged yet.
+    unacked: BTreeMap<u64, Unacked>,
+    /// Next reliable sequence to deliver.
+    expected_sequence: u64,
+    /// Reliable messages that arrived ahead of `expected_sequence`.
+    out_of_order: BTreeMap<u64, Vec<u8>>,
+    pending_acks: Vec<u64>,
+
+    outgoing: Vec<Packet>,
+    reliable_received: VecDeque<Vec<u8>>,
+    unreliable_received: VecDeque<Vec<u8>>,
+
+    stats: ConnectionStats,
+}
+
+impl Connection {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            since_received: Duration::ZERO,
+            since_sent: Duration::ZERO,
+            next_sequence: 0,
+            unacked: BTreeMap::new(),
+            expected_sequence: 0,
+            out_of_order: BTreeMap::new(),
+            pending_acks: Vec::new(),
+            outgoing: Vec::new(),
+            reliable_received: VecDeque::new(),
+            unreliable_received: VecDeque::new(),
+            stats: ConnectionStats::default(),
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn stats(&self) -> &ConnectionStats {
+        &self.stats
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.since_received >= TIMEOUT
+    }
+
+    /// Queue a message, messages over [`MAX_MESSAGE_SIZE`] are dropped since
+    /// they would never make it through in one datagram.
+    pub fn send_message(&mut self, channel: Channel, payload: Vec<u8>) {
+        if payload.len() > MAX_MESSAGE_SIZE {
+            warn!(
+                "dropping {} byte message to {}, more than the {} bytes that fit in a packet",
+                payload.len(),
+                self.addr,
+                MAX_MESSAGE_SIZE
+            );
+            self.stats.oversized += 1;
+            return;
+        }
+
+        match channel {
+            Channel::Reliable => {
+                let sequence = self.next_sequence;
+                self.next_sequence += 1;
+                self.unacked.insert(
+                    sequence,
+                    Unacked {
+                        since_sent: None,
+                        age: Duration::ZERO,
+                        resent: false,
+                        payload,
+                    },
+                );
+            }
+            Channel::Unreliable => self.outgoing.push(Packet::Unreliable { payload }),
+        }
+    }
+
+    pub fn receive_message(&mut self, channel: Channel) -> Option<Vec<u8>> {
+        match channel {
+            Channel::Reliable => self.reliable_received.pop_front(),
+            Channel::Unreliable => self.unreliable_received.pop_front(),
+        }
+    }
+
+    /// Handle a packet that arrived from the peer.
+    pub fn process(&mut self, packet: Packet) {
+        self.since_received = Duration::ZERO;
+
+        match packet {
+            Packet::Reliable { sequence, payload } => {
+                self.stats.reliable.received_packets += 1;
+                self.stats.reliable.received_bytes += payload.len() as u64;
+
+                // Always ack, our previous ack might have been lost.
+                self.pending_acks.push(sequence);
+                if sequence >= self.expected_sequence {
+                    self.out_of_order.insert(sequence, payload);
+                }
+
+                while let Some(payload) = self.out_of_order.remove(&self.expected_sequence) {
+                    self.reliable_received.push_back(payload);
+                    self.expected_sequence += 1;
+                }
+            }
+            Packet::Unreliable { payload } => {
+                self.stats.unreliable.received_packets += 1;
+                self.stats.unreliable.received_bytes += payload.len() as u64;
+                self.unreliable_received.push_back(payload);
+            }
+            Packet::Ack { sequences } => {
+                for sequence in sequences {
+                    let Some(unacked) = self.unacked.remove(&sequence) else {
+                        continue;
+                    };
+
+                    // Can't tell which send a resent message's ack belongs to.
+                    if !unacked.resent {
+                        self.stats.add_rtt_sample(unacked.age);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn advance(&mut self, dt: Duration) {
+        self.since_received += dt;
+        self.since_sent += dt;
+    }
+
+    /// Advance timers by `dt` and write everything queued to the transport.
+    pub fn flush(&mut self, dt: Duration, transport: &mut dyn Transport) {
+        self.advance(dt);
+
+        let mut packets = std::mem::take(&mut self.outgoing);
+        if !self.pending_acks.is_empty() {
+            packets.push(Packet::Ack {
+                sequences: std::mem::take(&mut self.pending_acks),
+            });
+        }
+
+        for (sequence, unacked) in self.unacked.iter_mut() {
+            let send = match &mut unacked.since_sent {
+                None => true,
+                Some(since_sent) => {
+                    *since_sent += dt;
+                    unacked.age += dt;
+                    if *since_sent >= RESEND_AFTER {
+                        unacked.resent = true;
+                        self.stats.resent += 1;
+                        true
+                    } else {
+                        false
+                    }
+                }
+            };
+
+            if send {
+                unacked.since_sent = Some(Duration::ZERO);
+                packets.push(Packet::Reliable {
+                    sequence: *sequence,
+                    payload: unacked.payload.clone(),
+                });
+            }
+        }
+
+        if packets.is_empty() && self.since_sent >= HEARTBEAT {
+            packets.push(Packet::Heartbeat);
+        }
+
+        for packet in packets {
+            self.send_packet(transport, &packet);
+        }
+    }
+
+    /// Send a packet immediately, bypassing the channels.
+    pub fn send_packet(&mut self, transport: &mut dyn Transport, packet: &Packet) {
+        self.since_sent = Duration::ZERO;
+
+        let sent = match packet {
+            Packet::Reliable { payload, .. } => Some((Channel::Reliable, payload.len())),
+            Packet::Unreliable { payload } => Some((Channel::Unreliable, payload.len())),
+            _ => None,
+        };
+        if let Some((channel, bytes)) = sent {
+            let stats = self.stats.channel_mut(channel);
+            stats.sent_packets += 1;
+            stats.sent_bytes += bytes as u64;
+        }
+
+        if let Err(err) = transport.send(self.addr, &packet.to_bytes()) {
+            warn!("failed to send packet to {}: {:?}", self.addr, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::transport::LoopbackNetwork;
+
+    #[test]
+    fn oversized_messages_are_dropped() {
+        let network = LoopbackNetwork::new();
+        let mut sender = network.bind("127.0.0.1:1".parse().unwrap());
+        let mut receiver = network.bind("127.0.0.1:2".parse().unwrap());
+        let mut connection = Connection::new(receiver.local_addr());
+
+        connection.send_message(Channel::Reliable, vec![0; MAX_MESSAGE_SIZE + 1]);
+        connection.send_message(Channel::Unreliable, vec![0; MAX_MESSAGE_SIZE + 1]);
+        connection.send_message(Channel::Reliable, vec![0; MAX_MESSAGE_SIZE]);
+        connection.flush(Duration::ZERO, &mut sender);
+
+        assert_eq!(connection.stats().oversized, 2);
+        let received = receiver.receive();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].1.len() <= MAX_PACKET_SIZE);
+    }
+}
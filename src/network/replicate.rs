@@ -0,0 +1,788 @@
+//! Component replication keyed by the stable ids in `types.toml`.
+//!
+//! Every tick the server serializes the replicated components that changed
+//! since the last tick into [`Snapshot`]s small enough for a single packet and
+//! sends them unreliably. Every [`KEYFRAME_INTERVAL`] ticks, and right after a
+//! client connects, they carry every replicated component instead, so whatever
+//! a lost snapshot had doesn't stay missing for long. Removed components and
+//! despawned entities only show up once, so those go out reliably on their own.
+//!
+//! Map props are spawned by both sides on their own, they get a [`StableId`]
+//! from where they started so the client can bind the server's copy to its own.
+use std::{any::TypeId, collections::VecDeque, fmt};
+
+use bevy::{
+    ecs::{
+        component::Tick,
+        entity::{EntityHashMap, EntityHashSet},
+        reflect::ReflectMapEntities,
+    },
+    reflect::{
+        serde::{TypedReflectDeserializer, TypedReflectSerializer},
+        TypeRegistry,
+    },
+    utils::{HashMap, HashSet},
+};
+use bincode::Options;
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
+
+use crate::prelude::*;
+
+use super::{
+    client_sync_players,
+    interpolate::{InterpolationBuffer, KinematicProxy},
+    prediction::ConfirmedState,
+    protocol::{Channel, MAX_MESSAGE_SIZE},
+    server_announce_players, server_send, NetworkClient, NetworkServer, NetworkSet, NetworkTick,
+    Owned, ServerEntities, ServerEntity, ServerEvent, ServerMessage,
+};
+
+pub type ReplicationId = u16;
+
+pub const TYPES_TOML: &str = include_str!("../../types.toml");
+
+/// Ticks between snapshots that carry every replicated component.
+pub const KEYFRAME_INTERVAL: u64 = 60;
+
+pub struct ReplicationPlugin;
+
+impl Plugin for ReplicationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Replicate>()
+            .register_type::<StableId>()
+            .register_type::<CollisionGroups>()
+            .register_type::<SolverGroups>()
+            .register_type::<ColliderScale>();
+
+        app.init_resource::<ReplicationState>();
+        app.init_resource::<SnapshotBuffer>();
+        app.init_resource::<LatestSnapshotTick>();
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                replication_track_clients
+                    .in_set(NetworkSet::Process)
+                    .run_if(resource_exists::<NetworkServer>),
+                assign_stable_ids
+                    .in_set(NetworkSet::Process)
+                    .before(client_apply_snapshots),
+                server_replicate_stable
+                    .in_set(NetworkSet::Send)
+                    .before(server_send_snapshots)
+                    .run_if(resource_exists::<NetworkServer>),
+                server_send_snapshots
+                    .in_set(NetworkSet::Send)
+                    .after(server_announce_players)
+                    .before(server_send)
+                    .run_if(resource_exists::<NetworkServer>),
+                client_apply_snapshots
+                    .in_set(NetworkSet::Process)
+                    .after(client_sync_players)
+                    .run_if(resource_exists::<NetworkClient>),
+            ),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        // Everything is registered by now.
+        let replication = {
+            let registry = app.world().resource::<AppTypeRegistry>().read();
+            match ReplicationRegistry::from_toml(TYPES_TOML, &registry) {
+                Ok(replication) => replication,
+                Err(errors) => {
+                    for error in &errors {
+                        error!("types.toml: {}", error);
+                    }
+                    panic!("invalid replication table in types.toml");
+                }
+            }
+        };
+
+        app.insert_resource(replication);
+    }
+}
+
+/// Replicate this entity's components to clients.
+#[derive(Default, Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Replicate;
+
+/// Identifies a body both sides spawned on their own, from its name and where it started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Component, Reflect)]
+#[reflect(Component)]
+pub struct StableId(pub u64);
+
+impl StableId {
+    pub fn new(name: &str, transform: &Transform) -> Self {
+        // FNV-1a over explicit bytes, so every build on every platform agrees.
+        let mut hash = FNV_OFFSET;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= u64::from(*byte);
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        };
+        write(name.as_bytes());
+        // In millimeters so float noise doesn't change the id.
+        for axis in transform.translation.to_array() {
+            write(&((axis * 1000.0).round() as i64).to_le_bytes());
+        }
+        Self(hash)
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationError {
+    Parse(String),
+    UnknownType(String),
+    NotComponent(String),
+    DuplicateId {
+        id: ReplicationId,
+        first: String,
+        second: String,
+    },
+    RetiredId {
+        id: ReplicationId,
+        type_path: String,
+        retired: String,
+    },
+}
+
+impl fmt::Display for ReplicationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(err) => write!(f, "failed to parse: {}", err),
+            Self::UnknownType(path) => write!(f, "`{}` is not in the type registry", path),
+            Self::NotComponent(path) => write!(f, "`{}` is not a reflected component", path),
+            Self::DuplicateId { id, first, second } => {
+                write!(f, "id {} used by both `{}` and `{}`", id, first, second)
+            }
+            Self::RetiredId {
+                id,
+                type_path,
+                retired,
+            } => write!(
+                f,
+                "id {} of `{}` was retired with `{}`",
+                id, type_path, retired
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplicationError {}
+
+#[derive(Deserialize)]
+struct TypesFile {
+    replicate: HashMap<String, ReplicationId>,
+    /// Ids of types that aren't replicated anymore, these can't be reused.
+    #[serde(default)]
+    retired: HashMap<String, ReplicationId>,
+}
+
+#[derive(Clone)]
+pub struct ReplicatedType {
+    pub id: ReplicationId,
+    pub type_path: String,
+    pub type_id: TypeId,
+    pub component: ReflectComponent,
+    pub map_entities: Option<ReflectMapEntities>,
+}
+
+#[derive(Default, Clone, Resource)]
+pub struct ReplicationRegistry {
+    types: HashMap<ReplicationId, ReplicatedType>,
+    ids: HashMap<TypeId, ReplicationId>,
+}
+
+impl ReplicationRegistry {
+    /// Parse the `[replicate]` table, every path has to be a registered reflected component
+    /// and no id can be one listed in `[retired]`.
+    pub fn from_toml(source: &str, registry: &TypeRegistry) -> Result<Self, Vec<ReplicationError>> {
+        let file: TypesFile =
+            toml::from_str(source).map_err(|err| vec![ReplicationError::Parse(err.to_string())])?;
+
+        let mut replication = Self::default();
+        let mut errors = Vec::new();
+
+        let retired = file
+            .retired
+            .into_iter()
+            .map(|(type_path, id)| (id, type_path))
+            .collect::<HashMap<_, _>>();
+
+        let mut entries = file.replicate.into_iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(_, id)| *id);
+        for (type_path, id) in entries {
+            if let Some(retired) = retired.get(&id) {
+                errors.push(ReplicationError::RetiredId {
+                    id,
+                    type_path,
+                    retired: retired.clone(),
+                });
+                continue;
+            }
+
+            let Some(registration) = registry.get_with_type_path(&type_path) else {
+                errors.push(ReplicationError::UnknownType(type_path));
+                continue;
+            };
+
+            let Some(component) = registration.data::<ReflectComponent>() else {
+                errors.push(ReplicationError::NotComponent(type_path));
+                continue;
+            };
+
+            if let Some(existing) = replication.types.get(&id) {
+                errors.push(ReplicationError::DuplicateId {
+                    id,
+                    first: existing.type_path.clone(),
+                    second: type_path,
+                });
+                continue;
+            }
+
+            replication.ids.insert(registration.type_id(), id);
+            replication.types.insert(
+                id,
+                ReplicatedType {
+                    id,
+                    type_path,
+                    type_id: registration.type_id(),
+                    component: component.clone(),
+                    map_entities: registration.data::<ReflectMapEntities>().cloned(),
+                },
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(replication)
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn id(&self, type_id: TypeId) -> Option<ReplicationId> {
+        self.ids.get(&type_id).copied()
+    }
+
+    pub fn get(&self, id: ReplicationId) -> Option<&ReplicatedType> {
+        self.types.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ReplicatedType> {
+        self.types.values()
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub entity: ServerEntity,
+    /// `changed` has every replicated component, not just the ones that changed.
+    pub full: bool,
+    pub changed: Vec<(ReplicationId, Vec<u8>)>,
+    pub removed: Vec<ReplicationId>,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub entities: Vec<EntitySnapshot>,
+    pub despawned: Vec<ServerEntity>,
+}
+
+impl Snapshot {
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty() && self.despawned.is_empty()
+    }
+
+    /// Split into snapshots of the same tick that serialize to at most
+    /// `max_size` bytes as a [`ServerMessage::Snapshot`].
+    ///
+    /// Entities are never split up, one that doesn't fit on its own is dropped.
+    pub fn split(self, max_size: usize) -> Vec<Snapshot> {
+        let empty = Snapshot {
+            tick: self.tick,
+            ..default()
+        };
+        let header = message_size(&ServerMessage::Snapshot(empty.clone()));
+
+        let mut snapshots = Vec::new();
+        let mut current = empty.clone();
+        let mut size = header;
+        for entity in self.entities {
+            let entity_size = message_size(&entity);
+            if header + entity_size > max_size {
+                warn!(
+                    "{:?} doesn't fit in a snapshot with {} bytes",
+                    entity.entity, entity_size
+                );
+                continue;
+            }
+
+            if size + entity_size > max_size {
+                snapshots.push(std::mem::replace(&mut current, empty.clone()));
+                size = header;
+            }
+            size += entity_size;
+            current.entities.push(entity);
+        }
+
+        for despawned in self.despawned {
+            let despawned_size = message_size(&despawned);
+            if size + despawned_size > max_size {
+                snapshots.push(std::mem::replace(&mut current, empty.clone()));
+                size = header;
+            }
+            size += despawned_size;
+            current.despawned.push(despawned);
+        }
+
+        if !current.is_empty() {
+            snapshots.push(current);
+        }
+        snapshots
+    }
+}
+
+fn message_size(value: &impl Serialize) -> usize {
+    bincode::serialized_size(value).unwrap_or(u64::MAX) as usize
+}
+
+/// Server side bookkeeping for building deltas.
+#[derive(Default, Resource)]
+pub struct ReplicationState {
+    last_run: Option<Tick>,
+    /// Component ids last sent for each entity, to notice removals.
+    sent: EntityHashMap<HashSet<ReplicationId>>,
+    /// A client connected, the next snapshot has to be a keyframe.
+    keyframe: bool,
+}
+
+/// Snapshots received by the client that haven't been applied yet.
+#[derive(Default, Resource)]
+pub struct SnapshotBuffer(pub VecDeque<Snapshot>);
+
+/// Server tick of the newest snapshot the client applied, component values
+/// from older snapshots that arrive late are ignored.
+#[derive(Default, Debug, Resource)]
+pub struct LatestSnapshotTick(pub Option<u64>);
+
+fn serialize_component(
+    value: &dyn Reflect,
+    registry: &TypeRegistry,
+) -> Result<Vec<u8>, bincode::Error> {
+    bincode::DefaultOptions::new().serialize(&TypedReflectSerializer::new(value, registry))
+}
+
+fn deserialize_component(
+    bytes: &[u8],
+    type_id: TypeId,
+    registry: &TypeRegistry,
+) -> Option<Box<dyn Reflect>> {
+    let registration = registry.get(type_id)?;
+    bincode::DefaultOptions::new()
+        .deserialize_seed(TypedReflectDeserializer::new(registration, registry), bytes)
+        .ok()
+}
+
+/// Tag named dynamic bodies that aren't part of a character before they move.
+pub fn assign_stable_ids(
+    mut commands: Commands,
+    bodies: Query<
+        (Entity, &Name, &Transform, &RigidBody),
+        (
+            Without<StableId>,
+            Without<CharacterEntities>,
+            Without<Player>,
+            // Already told about by the server.
+            Without<InterpolationBuffer>,
+            Without<ConfirmedState>,
+        ),
+    >,
+) {
+    for (entity, name, transform, body) in &bodies {
+        if *body == RigidBody::Dynamic {
+            commands
+                .entity(entity)
+                .insert(StableId::new(name.as_str(), transform));
+        }
+    }
+}
+
+/// Props are grabbable, so clients need to see them move.
+pub fn server_replicate_stable(
+    mut commands: Commands,
+    bodies: Query<Entity, (With<StableId>, Without<Replicate>)>,
+) {
+    for entity in &bodies {
+        commands.entity(entity).insert(Replicate);
+    }
+}
+
+pub fn replication_track_clients(
+    mut server_events: EventReader<ServerEvent>,
+    mut state: ResMut<ReplicationState>,
+) {
+    for event in server_events.read() {
+        if let ServerEvent::ClientConnected(_) = event {
+            state.keyframe = true;
+        }
+    }
+}
+
+pub fn server_send_snapshots(world: &mut World) {
+    let replicated = world
+        .query_filtered::<Entity, With<Replicate>>()
+        .iter(world)
+        .collect::<Vec<_>>();
+
+    world.resource_scope(|world, mut state: Mut<ReplicationState>| {
+        let this_run = world.read_change_tick();
+        let tick = world.resource::<NetworkTick>().0;
+        let keyframe = state.keyframe || tick % KEYFRAME_INTERVAL == 0;
+
+        let replication = world.resource::<ReplicationRegistry>();
+        let registry = world.resource::<AppTypeRegistry>().read();
+
+        let mut delta = Snapshot { tick, ..default() };
+        let mut removals = Snapshot { tick, ..default() };
+        let mut sent = EntityHashMap::default();
+
+        for &entity in &replicated {
+            let entity_ref = world.entity(entity);
+            let previous = state.sent.get(&entity);
+
+            let mut present = HashSet::new();
+            let mut delta_entity = EntitySnapshot {
+                entity: entity.into(),
+                // New entities go out whole, clients need all of it to spawn them.
+                full: keyframe || previous.is_none() || state.last_run.is_none(),
+                ..default()
+            };
+
+            for ty in replication.iter() {
+                let Some(component_id) = world.components().get_id(ty.type_id) else {
+                    continue;
+                };
+                let Some(ticks) = entity_ref.get_change_ticks_by_id(component_id) else {
+                    continue;
+                };
+                let Some(value) = ty.component.reflect(entity_ref) else {
+                    continue;
+                };
+
+                present.insert(ty.id);
+
+                let changed = match (state.last_run, previous) {
+                    (Some(last_run), Some(previous)) => {
+                        !previous.contains(&ty.id) || ticks.is_changed(last_run, this_run)
+                    }
+                    _ => true,
+                };
+                if !changed && !delta_entity.full {
+                    continue;
+                }
+
+                match serialize_component(value, &registry) {
+                    Ok(bytes) => delta_entity.changed.push((ty.id, bytes)),
+                    Err(err) => warn!("failed to serialize `{}`: {:?}", ty.type_path, err),
+                }
+            }
+
+            if let Some(previous) = previous {
+                let removed = previous.difference(&present).copied().collect::<Vec<_>>();
+                if !removed.is_empty() {
+                    removals.entities.push(EntitySnapshot {
+                        entity: entity.into(),
+                        removed,
+                        ..default()
+                    });
+                }
+            }
+
+            if !delta_entity.changed.is_empty() {
+                delta.entities.push(delta_entity);
+            }
+            sent.insert(entity, present);
+        }
+
+        let alive = replicated.iter().copied().collect::<EntityHashSet>();
+        removals.despawned = state
+            .sent
+            .keys()
+            .filter(|entity| !alive.contains(*entity))
+            .map(|entity| (*entity).into())
+            .collect();
+
+        state.sent = sent;
+        state.last_run = Some(this_run);
+        state.keyframe = false;
+        drop(registry);
+
+        let mut server = world.resource_mut::<NetworkServer>();
+        for (channel, snapshot) in [(Channel::Unreliable, delta), (Channel::Reliable, removals)] {
+            for snapshot in snapshot.split(MAX_MESSAGE_SIZE) {
+                let message = bincode::serialize(&ServerMessage::Snapshot(snapshot)).unwrap();
+                server.broadcast_message(channel, message);
+            }
+        }
+    });
+}
+
+pub fn client_apply_snapshots(world: &mut World) {
+    let snapshots = std::mem::take(&mut world.resource_mut::<SnapshotBuffer>().0);
+    if snapshots.is_empty() {
+        return;
+    }
+
+    let mut latest = world.resource::<LatestSnapshotTick>().0;
+    let replication = world.resource::<ReplicationRegistry>().clone();
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    let stable_id = replication.id(TypeId::of::<StableId>());
+    let mut stable = world
+        .query::<(Entity, &StableId)>()
+        .iter(world)
+        .map(|(entity, id)| (*id, entity))
+        .collect::<HashMap<_, _>>();
+
+    world.resource_scope(|world, mut server_entities: Mut<ServerEntities>| {
+        let bound = server_entities
+            .iter()
+            .map(|(_, entity)| *entity)
+            .collect::<EntityHashSet>();
+        stable.retain(|_, entity| !bound.contains(entity));
+
+        for snapshot in snapshots {
+            let stale = latest.is_some_and(|latest| snapshot.tick < latest);
+            latest = latest.max(Some(snapshot.tick));
+
+            for server_entity in snapshot.despawned {
+                if let Some(entity) = server_entities.remove(server_entity) {
+                    if let Some(entity) = world.get_entity_mut(entity) {
+                        entity.despawn_recursive();
+                    }
+                }
+            }
+
+            let mut mapped = Vec::new();
+            for entity_snapshot in snapshot.entities {
+                // Late snapshots could bring back entities that were despawned since,
+                // and a delta doesn't have enough to spawn one.
+                let entity = if stale || !entity_snapshot.full {
+                    match server_entities.get(entity_snapshot.entity) {
+                        Some(entity) if world.get_entity(entity).is_some() => entity,
+                        _ => continue,
+                    }
+                } else {
+                    if server_entities.get(entity_snapshot.entity).is_none() {
+                        // Our own copy of something we both spawned.
+                        let local = stable_id
+                            .and_then(|stable_id| {
+                                entity_snapshot
+                                    .changed
+                                    .iter()
+                                    .find(|(id, _)| *id == stable_id)
+                            })
+                            .and_then(|(_, bytes)| {
+                                deserialize_component(bytes, TypeId::of::<StableId>(), &registry)
+                            })
+                            .and_then(|value| StableId::from_reflect(&*value))
+                            .and_then(|id| stable.remove(&id));
+                        if let Some(local) = local {
+                            server_entities.bind(entity_snapshot.entity, local);
+                        }
+                    }
+
+                    server_entities.spawn_or_get_in(world, entity_snapshot.entity)
+                };
+
+                if !world.entity(entity).contains::<ConfirmedState>()
+                    && !world.entity(entity).contains::<InterpolationBuffer>()
+                {
+                    world
+                        .entity_mut(entity)
+                        .insert(InterpolationBuffer::default());
+                }
+
+                for id in entity_snapshot.removed {
+                    if let Some(ty) = replication.get(id) {
+                        ty.component.remove(&mut world.entity_mut(entity));
+                    }
+                }
+
+                if stale {
+                    continue;
+                }
+
+                for (id, bytes) in entity_snapshot.changed {
+                    let Some(ty) = replication.get(id) else {
+                        warn!("unknown replication id {}", id);
+                        continue;
+                    };
+
+                    let Some(value) = deserialize_component(&bytes, ty.type_id, &registry) else {
+                        warn!("failed to deserialize `{}`", ty.type_path);
+                        continue;
+                    };
+
+                    // Predicted state is reconciled instead of overwritten.
+                    if let Some(mut confirmed) = world.get_mut::<ConfirmedState>(entity) {
+                        if confirmed.capture(snapshot.tick, ty.type_id, &*value) {
+                            continue;
+                        }
+                    }
+
+                    if ty.type_id == TypeId::of::<RigidBody>()
+                        && world.entity(entity).contains::<KinematicProxy>()
+                    {
+                        continue;
+                    }
+
+                    // We simulate bodies we own ourselves.
+                    let physics_state = ty.type_id == TypeId::of::<Transform>()
+                        || ty.type_id == TypeId::of::<Velocity>();
+                    if physics_state
+                        && world.entity(entity).contains::<Owned>()
+                        && !world.entity(entity).contains::<ConfirmedState>()
+                        && ty.component.contains(world.entity(entity))
+                    {
+                        continue;
+                    }
+
+                    // Remote entities are played back with a delay, only insert them here.
+                    if let Some(mut buffer) = world.get_mut::<InterpolationBuffer>(entity) {
+                        if buffer.capture(snapshot.tick, ty.type_id, &*value)
+                            && ty.component.contains(world.entity(entity))
+                        {
+                            continue;
+                        }
+                    }
+
+                    ty.component
+                        .apply_or_insert(&mut world.entity_mut(entity), &*value, &registry);
+                    if let Some(map_entities) = &ty.map_entities {
+                        mapped.push((map_entities.clone(), entity));
+                    }
+                }
+            }
+
+            // Entity references inside components still point at server entities.
+            let mut entity_map = server_entities
+                .iter()
+                .filter_map(|(server_entity, entity)| Some((server_entity.entity()?, *entity)))
+                .collect::<EntityHashMap<Entity>>();
+            for (map_entities, entity) in mapped {
+                map_entities.map_entities(world, &mut entity_map, &[entity]);
+            }
+        }
+    });
+
+    world.resource_mut::<LatestSnapshotTick>().0 = latest;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::HeadlessApp;
+
+    #[test]
+    fn types_toml_is_valid() {
+        let mut app = HeadlessApp::new();
+        app.add_plugins(crate::network::NetworkPlugin);
+        app.tick();
+
+        let replication = app.world().resource::<ReplicationRegistry>();
+        assert_eq!(
+            replication.id(TypeId::of::<Transform>()),
+            Some(5),
+            "transform should keep its wire id"
+        );
+    }
+
+    #[test]
+    fn stable_ids_are_fixed() {
+        // Has to stay the same across builds, peers compare these.
+        assert_eq!(
+            StableId::new("prop", &Transform::from_xyz(1.0, 2.0, 3.0)),
+            StableId(0xbe20_c5a4_4c29_832d)
+        );
+        assert_ne!(
+            StableId::new("prop", &Transform::from_xyz(1.0, 0.0, 0.0)),
+            StableId::new("prop", &Transform::from_xyz(0.0, 1.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn retired_ids_stay_retired() {
+        let registry = TypeRegistry::new();
+        let errors = ReplicationRegistry::from_toml(
+            r#"
+            [replicate]
+            "bevy_transform::components::transform::Transform" = 47
+
+            [retired]
+            "potion::slot::SlotDeposit" = 47
+            "#,
+            &registry,
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            errors[..],
+            [ReplicationError::RetiredId { id: 47, .. }]
+        ));
+    }
+
+    #[test]
+    fn snapshots_split_to_fit() {
+        let entities = (0..40)
+            .map(|index| EntitySnapshot {
+                entity: Entity::from_raw(index).into(),
+                full: true,
+                changed: vec![(5, vec![0; 100])],
+                removed: Vec::new(),
+            })
+            .collect();
+        let snapshot = Snapshot {
+            tick: 7,
+            entities,
+            despawned: vec![Entity::from_raw(100).into()],
+        };
+
+        let snapshots = snapshot.split(1000);
+        assert!(snapshots.len() > 1);
+        for snapshot in &snapshots {
+            assert_eq!(snapshot.tick, 7);
+            assert!(message_size(&ServerMessage::Snapshot(snapshot.clone())) <= 1000);
+        }
+
+        let entities = snapshots.iter().map(|snapshot| snapshot.entities.len());
+        assert_eq!(entities.sum::<usize>(), 40);
+        let despawned = snapshots.iter().map(|snapshot| snapshot.despawned.len());
+        assert_eq!(despawned.sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn rejects_unknown_paths() {
+        let registry = TypeRegistry::new();
+        let errors = ReplicationRegistry::from_toml(
+            "[replicate]\n\"bevy_rapier2d::dynamics::rigid_body::Velocity\" = 1\n",
+            &registry,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            errors,
+            vec![ReplicationError::UnknownType(
+                "bevy_rapier2d::dynamics::rigid_body::Velocity".to_owned()
+            )]
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{
+    conditioner::{ConditionedTransport, LinkConditioner},
+    lobby::PlayerIdentity,
+    protocol::{Channel, Connection, ConnectionStats, Packet, PROTOCOL_ID},
+    transport::Transport,
+    ClientId,
+};
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServerEvent {
+    ClientConnected(ClientId),
+    ClientDisconnected(ClientId),
+}
+
+#[derive(Resource)]
+pub struct NetworkServer {
+    transport: ConditionedTransport,
+    connections: HashMap<ClientId, Connection>,
+    addresses: HashMap<SocketAddr, ClientId>,
+    identities: HashMap<ClientId, PlayerIdentity>,
+    next_client_id: ClientId,
+    max_clients: Option<usize>,
+    password: Option<String>,
+    /// Events that happened outside of [`NetworkServer::update`].
+    events: Vec<ServerEvent>,
+}
+
+impl NetworkServer {
+    pub fn new(transport: impl Transport) -> Self {
+        Self {
+            transport: ConditionedTransport::new(transport),
+            connections: HashMap::new(),
+            addresses: HashMap::new(),
+            identities: HashMap::new(),
+            next_client_id: 1,
+            max_clients: None,
+            password: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// Turn away clients once this many are connected.
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = Some(max_clients);
+        self
+    }
+
+    /// Only accept clients that send this password.
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.transport.local_addr()
+    }
+
+    pub fn link_conditioner(&self) -> &LinkConditioner {
+        self.transport.conditioner()
+    }
+
+    /// Simulate a bad connection to every client.
+    pub fn set_link_conditioner(&mut self, conditioner: LinkConditioner) {
+        self.transport.set_conditioner(conditioner);
+    }
+
+    pub fn clients_id(&self) -> Vec<ClientId> {
+        self.connections.keys().copied().collect()
+    }
+
+    pub fn is_connected(&self, client_id: ClientId) -> bool {
+        self.connections.contains_key(&client_id)
+    }
+
+    pub fn client_addr(&self, client_id: ClientId) -> Option<SocketAddr> {
+        self.connections
+            .get(&client_id)
+            .map(|connection| connection.addr())
+    }
+
+    /// Who the client said they are when connecting.
+    pub fn client_identity(&self, client_id: ClientId) -> Option<&PlayerIdentity> {
+        self.identities.get(&client_id)
+    }
+
+    pub fn client_stats(&self, client_id: ClientId) -> Option<&ConnectionStats> {
+        self.connections
+            .get(&client_id)
+            .map(|connection| connection.stats())
+    }
+
+    pub fn send_message(&mut self, client_id: ClientId, channel: Channel, message: Vec<u8>) {
+        if let Some(connection) = self.connections.get_mut(&client_id) {
+            connection.send_message(channel, message);
+        }
+    }
+
+    pub fn broadcast_message(&mut self, channel: Channel, message: Vec<u8>) {
+        for connection in self.connections.values_mut() {
+            connection.send_message(channel, message.clone());
+        }
+    }
+
+    pub fn broadcast_message_except(
+        &mut self,
+        except: ClientId,
+        channel: Channel,
+        message: Vec<u8>,
+    ) {
+        for (client_id, connection) in self.connections.iter_mut() {
+            if *client_id != except {
+                connection.send_message(channel, message.clone());
+            }
+        }
+    }
+
+    pub fn receive_message(&mut self, client_id: ClientId, channel: Channel) -> Option<Vec<u8>> {
+        self.connections
+            .get_mut(&client_id)
+            .and_then(|connection| connection.receive_message(channel))
+    }
+
+    pub fn disconnect(&mut self, client_id: ClientId) {
+        if let Some(mut connection) = self.connections.remove(&client_id) {
+            connection.send_packet(&mut self.transport, &Packet::Disconnect);
+            self.addresses.remove(&connection.addr());
+            self.identities.remove(&client_id);
+            self.events.push(ServerEvent::ClientDisconnected(client_id));
+        }
+    }
+
+    pub fn disconnect_all(&mut self) {
+        for client_id in self.clients_id() {
+            self.disconnect(client_id);
+        }
+    }
+
+    /// Read incoming packets and return connection changes since the last update.
+    pub fn update(&mut self) -> Vec<ServerEvent> {
+        for (addr, bytes) in self.transport.receive() {
+            let Some(packet) = Packet::from_bytes(&bytes) else {
+                warn!("malformed packet from {}", addr);
+                continue;
+            };
+
+            let Some(&client_id) = self.addresses.get(&addr) else {
+                self.handle_new(addr, packet);
+                continue;
+            };
+
+            match packet {
+                Packet::ConnectRequest { .. } => {
+                    // Our accept got lost, say it again.
+                    if let Some(connection) = self.connections.get_mut(&client_id) {
+                        connection.send_packet(
+                            &mut self.transport,
+                            &Packet::ConnectAccepted { client_id },
+                        );
+                    }
+                }
+                Packet::Disconnect => {
+                    self.connections.remove(&client_id);
+                    self.addresses.remove(&addr);
+                    self.identities.remove(&client_id);
+                    self.events.push(ServerEvent::ClientDisconnected(client_id));
+                }
+                packet => {
+                    if let Some(connection) = self.connections.get_mut(&client_id) {
+                        connection.process(packet);
+                    }
+                }
+            }
+        }
+
+        let timed_out = self
+            .connections
+            .iter()
+            .filter(|(_, connection)| connection.timed_out())
+            .map(|(client_id, _)| *client_id)
+            .collect::<Vec<_>>();
+        for client_id in timed_out {
+            info!("client {} timed out", client_id);
+            self.disconnect(client_id);
+        }
+
+        std::mem::take(&mut self.events)
+    }
+
+    fn handle_new(&mut self, addr: SocketAddr, packet: Packet) {
+        let Packet::ConnectRequest {
+            protocol,
+            password,
+            identity,
+        } = packet
+        else {
+            return;
+        };
+
+        let denied = if protocol != PROTOCOL_ID {
+            Some(format!(
+                "protocol mismatch: server {}, client {}",
+                PROTOCOL_ID, protocol
+            ))
+        } else if self.password.is_some() && password != self.password {
+            Some("wrong password".to_owned())
+        } else if self
+            .max_clients
+            .is_some_and(|max_clients| self.connections.len() >= max_clients)
+        {
+            Some("server is full".to_owned())
+        } else {
+            None
+        };
+
+        let mut connection = Connection::new(addr);
+        if let Some(reason) = denied {
+            info!("denied connection from {}: {}", addr, reason);
+            connection.send_packet(&mut self.transport, &Packet::ConnectDenied { reason });
+            return;
+        }
+
+        let client_id = self.next_client_id;
+        self.next_client_id += 1;
+
+        connection.send_packet(&mut self.transport, &Packet::ConnectAccepted { client_id });
+        self.connections.insert(client_id, connection);
+        self.addresses.insert(addr, client_id);
+        self.identities.insert(client_id, identity);
+        self.events.push(ServerEvent::ClientConnected(client_id));
+    }
+
+    /// Flush every connection to the transport.
+    pub fn send_packets(&mut self, dt: Duration) {
+        self.transport.advance(dt);
+        for connection in self.connections.values_mut() {
+            connection.flush(dt, &mut self.transport);
+        }
+    }
+}
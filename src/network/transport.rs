@@ -0,0 +1,133 @@
+use std::{
+    collections::VecDeque,
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+
+/// Largest datagram we send or will try to read.
+pub const MAX_PACKET_SIZE: usize = 1200 * 8;
+
+/// Raw datagram transport used by [`super::NetworkServer`] and [`super::NetworkClient`].
+///
+/// No guarantees are made about delivery or ordering, that is handled by
+/// [`super::protocol::Connection`].
+pub trait Transport: Send + Sync + 'static {
+    fn local_addr(&self) -> SocketAddr;
+
+    fn send(&mut self, to: SocketAddr, packet: &[u8]) -> io::Result<()>;
+
+    /// Every datagram that arrived since the last call.
+    fn receive(&mut self) -> Vec<(SocketAddr, Vec<u8>)>;
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+impl UdpTransport {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            buffer: vec![0; MAX_PACKET_SIZE],
+        })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn local_addr(&self) -> SocketAddr {
+        self.socket
+            .local_addr()
+            .expect("udp socket should be bound")
+    }
+
+    fn send(&mut self, to: SocketAddr, packet: &[u8]) -> io::Result<()> {
+        // The other end would only read a truncated datagram.
+        if packet.len() > MAX_PACKET_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} byte packet is larger than {}",
+                    packet.len(),
+                    MAX_PACKET_SIZE
+                ),
+            ));
+        }
+
+        self.socket.send_to(packet, to).map(|_| ())
+    }
+
+    fn receive(&mut self) -> Vec<(SocketAddr, Vec<u8>)> {
+        let mut packets = Vec::new();
+        loop {
+            match self.socket.recv_from(&mut self.buffer) {
+                Ok((length, from)) => packets.push((from, self.buffer[..length].to_vec())),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                // Windows reports ICMP port unreachable as a receive error, the
+                // connection timeout takes care of it.
+                Err(err) if err.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(err) => {
+                    warn!("udp receive error: {:?}", err);
+                    break;
+                }
+            }
+        }
+
+        packets
+    }
+}
+
+/// In-memory "network" that [`LoopbackTransport`]s can bind to, for tests and
+/// running a client and server in the same process.
+#[derive(Default, Clone)]
+pub struct LoopbackNetwork {
+    queues: Arc<Mutex<HashMap<SocketAddr, VecDeque<(SocketAddr, Vec<u8>)>>>>,
+}
+
+impl LoopbackNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind(&self, addr: SocketAddr) -> LoopbackTransport {
+        self.queues.lock().unwrap().entry(addr).or_default();
+        LoopbackTransport {
+            addr,
+            network: self.clone(),
+        }
+    }
+}
+
+pub struct LoopbackTransport {
+    addr: SocketAddr,
+    network: LoopbackNetwork,
+}
+
+impl Transport for LoopbackTransport {
+    fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn send(&mut self, to: SocketAddr, packet: &[u8]) -> io::Result<()> {
+        // Like UDP, sending to nobody silently goes nowhere.
+        if let Some(queue) = self.network.queues.lock().unwrap().get_mut(&to) {
+            queue.push_back((self.addr, packet.to_vec()));
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Vec<(SocketAddr, Vec<u8>)> {
+        self.network
+            .queues
+            .lock()
+            .unwrap()
+            .get_mut(&self.addr)
+            .map(|queue| queue.drain(..).collect())
+            .unwrap_or_default()
+    }
+}
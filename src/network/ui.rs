@@ -1,22 +1,42 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{ecs::entity::Entities, utils::HashMap};
+use bevy_egui::{EguiContexts, EguiPlugin};
 
 use crate::prelude::*;
-use bevy::{
-    ecs::{
-        entity::Entities,
-        system::{SystemParam, SystemState},
-    },
+
+use super::{
+    conditioner::{LinkConditioner, LinkConditions},
+    lobby::{PlayerIdentity, RequestReady},
+    protocol::{Channel, ConnectionStats},
+    replicate::LatestSnapshotTick,
+    ClientId, Lobby, NetworkClient, NetworkServer, NetworkTick, ServerEntities,
 };
-use bevy_editor_pls::editor_window::{EditorWindow, EditorWindowContext};
-use bevy_renet::renet::{RenetClient, RenetServer};
-//use renet_visualizer::{RenetClientVisualizer, RenetServerVisualizer, RenetVisualizerStyle};
-use sabi::{prelude::ServerEntities, protocol::update::UpdateMessages, tick::NetworkTick};
 
 pub const DATA_POINTS: usize = 100;
+/// Time between samples, so the graphs cover `DATA_POINTS * SAMPLE_INTERVAL`.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct NetworkUiPlugin;
+
+impl Plugin for NetworkUiPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+
+        app.init_resource::<NetworkWindow>();
+        app.init_resource::<NetworkDiagnostics>();
 
-#[derive(Debug, Clone)]
+        app.add_systems(Update, (update_network_diagnostics, network_window).chain());
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
 pub struct NetworkWindow {
     pub client_ip: String,
     pub client_port: u16,
+    pub client_password: String,
     pub client_error: Option<String>,
 
     pub server_ip: String,
@@ -27,249 +47,404 @@ pub struct NetworkWindow {
 impl Default for NetworkWindow {
     fn default() -> Self {
         Self {
-            #[cfg(not(feature = "public"))]
             client_ip: "127.0.0.1".to_owned(),
-            #[cfg(feature = "public")]
-            client_ip: "spite.aceeri.com".to_owned(),
-
-            client_port: sabi::protocol::PORT,
+            client_port: super::PORT,
+            client_password: String::new(),
             client_error: None,
 
-            #[cfg(not(feature = "public"))]
-            server_ip: "127.0.0.1".to_owned(),
-            #[cfg(feature = "public")]
-            server_ip: sabi::protocol::public_ip().expect("public ip to bind to"),
-            server_port: sabi::protocol::PORT,
+            server_ip: "0.0.0.0".to_owned(),
+            server_port: super::PORT,
             server_error: None,
         }
     }
 }
 
-impl EditorWindow for NetworkWindow {
-    type State = (
-        Self,
-        Option<SystemState<NetworkInfoQuery<'static, 'static>>>,
-    );
-    const NAME: &'static str = "Network Info";
-
-    fn ui(world: &mut World, mut cx: EditorWindowContext, ui: &mut egui::Ui) {
-        let (ref mut state, ref mut system_state) = cx.state_mut::<Self>().unwrap();
-        if let None = system_state {
-            *system_state = Some(SystemState::from_world(world));
-        }
-
-        let system_state = system_state.as_mut().unwrap();
-        {
-            let query = system_state.get_mut(world);
-            network_info(ui, state, query);
-        }
-        system_state.apply(world);
+fn push_sample(samples: &mut VecDeque<f32>, value: f32) {
+    samples.push_back(value);
+    while samples.len() > DATA_POINTS {
+        samples.pop_front();
     }
 }
 
-/*
-pub fn update_network_stats(
-    mut commands: Commands,
-    client: Option<Res<RenetClient>>,
-    client_visualizer: Option<ResMut<RenetClientVisualizer<DATA_POINTS>>>,
+/// Graphs for one connection, built from the difference between [`ConnectionStats`] samples.
+#[derive(Default, Debug, Clone)]
+pub struct ConnectionMetrics {
+    previous: Option<ConnectionStats>,
+    /// Milliseconds.
+    pub rtt: VecDeque<f32>,
+    /// Percentage of reliable packets that had to be resent.
+    pub packet_loss: VecDeque<f32>,
+    /// Kilobytes per second sent and received, by channel.
+    pub sent: HashMap<Channel, VecDeque<f32>>,
+    pub received: HashMap<Channel, VecDeque<f32>>,
+}
 
-    server: Option<Res<RenetServer>>,
-    server_visualizer: Option<ResMut<RenetServerVisualizer<DATA_POINTS>>>,
-) {
-    if let Some(ref client) = client {
-        match client_visualizer {
-            Some(mut visualizer) => {
-                visualizer.add_network_info(client.network_info());
-            }
-            None => {
-                commands.insert_resource(RenetClientVisualizer::<DATA_POINTS>::new(
-                    RenetVisualizerStyle::default(),
-                ));
-            }
+impl ConnectionMetrics {
+    pub fn sample(&mut self, stats: &ConnectionStats, elapsed: Duration) {
+        let previous = self.previous.replace(*stats).unwrap_or_default();
+
+        push_sample(&mut self.rtt, stats.rtt.as_secs_f32() * 1000.0);
+
+        // Counters start over with a new connection.
+        let sent = stats
+            .reliable
+            .sent_packets
+            .saturating_sub(previous.reliable.sent_packets);
+        let resent = stats.resent.saturating_sub(previous.resent);
+        let loss = if sent > 0 {
+            resent as f32 / sent as f32 * 100.0
+        } else {
+            0.0
         };
+        push_sample(&mut self.packet_loss, loss);
+
+        let per_second = |bytes: u64| bytes as f32 / 1024.0 / elapsed.as_secs_f32();
+        for channel in [Channel::Reliable, Channel::Unreliable] {
+            let (now, before) = (stats.channel(channel), previous.channel(channel));
+            push_sample(
+                self.sent.entry(channel).or_default(),
+                per_second(now.sent_bytes.saturating_sub(before.sent_bytes)),
+            );
+            push_sample(
+                self.received.entry(channel).or_default(),
+                per_second(now.received_bytes.saturating_sub(before.received_bytes)),
+            );
+        }
     }
 
-    if let Some(ref server) = server {
-        match server_visualizer {
-            Some(mut visualizer) => {
-                visualizer.update(&**server);
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        graph(ui, "RTT", "ms", &self.rtt);
+        graph(ui, "Packet loss", "%", &self.packet_loss);
+        for channel in [Channel::Reliable, Channel::Unreliable] {
+            if let Some(sent) = self.sent.get(&channel) {
+                graph(ui, &format!("{:?} sent", channel), "KB/s", sent);
             }
-            None => {
-                commands.insert_resource(RenetServerVisualizer::<DATA_POINTS>::new(
-                    RenetVisualizerStyle::default(),
-                ));
+            if let Some(received) = self.received.get(&channel) {
+                graph(ui, &format!("{:?} received", channel), "KB/s", received);
             }
-        };
+        }
     }
 }
 
-pub fn update_connected_clients(
-    mut events: EventReader<ServerEvent>,
-    mut visualizer: ResMut<RenetServerVisualizer<DATA_POINTS>>,
+#[derive(Resource, Default, Debug, Clone)]
+pub struct NetworkDiagnostics {
+    since_sample: Duration,
+    pub client: ConnectionMetrics,
+    /// Ticks our clock runs ahead of the latest snapshot.
+    pub tick_offset: VecDeque<f32>,
+    pub clients: HashMap<ClientId, ConnectionMetrics>,
+}
+
+pub fn update_network_diagnostics(
+    time: Res<Time<Real>>,
+    tick: Res<NetworkTick>,
+    latest_snapshot: Res<LatestSnapshotTick>,
+    client: Option<Res<NetworkClient>>,
+    server: Option<Res<NetworkServer>>,
+    mut diagnostics: ResMut<NetworkDiagnostics>,
 ) {
-    for event in events.iter() {
-        match event {
-            ServerEvent::ClientConnected(client_id, _user_data) => {
-                visualizer.add_client(*client_id);
-            }
-            ServerEvent::ClientDisconnected(client_id) => {
-                visualizer.remove_client(*client_id);
+    // A new connection, the old graphs don't belong to it.
+    if client.as_ref().is_some_and(|client| client.is_added()) {
+        diagnostics.client = default();
+        diagnostics.tick_offset.clear();
+    }
+
+    diagnostics.since_sample += time.delta();
+    if diagnostics.since_sample < SAMPLE_INTERVAL {
+        return;
+    }
+    let elapsed = std::mem::take(&mut diagnostics.since_sample);
+
+    if let Some(client) = client.filter(|client| client.is_connected()) {
+        diagnostics.client.sample(client.stats(), elapsed);
+        if let Some(latest) = latest_snapshot.0 {
+            push_sample(&mut diagnostics.tick_offset, tick.0 as f32 - latest as f32);
+        }
+    }
+
+    if let Some(server) = server {
+        let clients = server.clients_id();
+        diagnostics
+            .clients
+            .retain(|client_id, _| clients.contains(client_id));
+        for client_id in clients {
+            if let Some(stats) = server.client_stats(client_id) {
+                diagnostics
+                    .clients
+                    .entry(client_id)
+                    .or_default()
+                    .sample(stats, elapsed);
             }
-            _ => {}
         }
     }
 }
- */
 
-#[derive(SystemParam)]
-pub struct NetworkInfoQuery<'w, 's> {
-    commands: Commands<'w, 's>,
-    entities: &'w Entities,
+fn link_conditions_ui(ui: &mut egui::Ui, label: &str, conditions: &mut LinkConditions) {
+    let mut latency = conditions.latency.as_millis() as u64;
+    let mut jitter = conditions.jitter.as_millis() as u64;
+
+    ui.label(label);
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut latency).suffix(" ms latency"));
+        ui.add(egui::DragValue::new(&mut jitter).suffix(" ms jitter"));
+    });
+    ui.horizontal(|ui| {
+        for (value, suffix) in [
+            (&mut conditions.loss, " loss"),
+            (&mut conditions.duplication, " duplication"),
+            (&mut conditions.reordering, " reordering"),
+        ] {
+            ui.add(egui::DragValue::new(value).speed(0.01).suffix(suffix));
+            *value = value.clamp(0.0, 1.0);
+        }
+    });
 
-    server_entities: ResMut<'w, ServerEntities>,
+    conditions.latency = Duration::from_millis(latency);
+    conditions.jitter = Duration::from_millis(jitter);
+}
 
-    tick: Option<Res<'w, NetworkTick>>,
-    updates: Option<Res<'w, UpdateMessages>>,
+/// Line graph of `samples` scaled to fit.
+pub fn graph(ui: &mut egui::Ui, label: &str, unit: &str, samples: &VecDeque<f32>) {
+    let latest = samples.back().copied().unwrap_or_default();
+    let max = samples.iter().copied().fold(0.0f32, f32::max);
+    let min = samples.iter().copied().fold(0.0f32, f32::min);
+    let range = (max - min).max(f32::EPSILON);
+    ui.label(format!(
+        "{}: {:.1} {} (max {:.1})",
+        label, latest, unit, max
+    ));
 
-    is_client: Option<Res<'w, sabi::Client>>,
-    client: Option<ResMut<'w, RenetClient>>,
-    //client_visualizer: Option<ResMut<'w, RenetClientVisualizer<DATA_POINTS>>>,
-    is_server: Option<Res<'w, sabi::Server>>,
-    server: Option<ResMut<'w, RenetServer>>,
-    //server_visualizer: Option<ResMut<'w, RenetServerVisualizer<DATA_POINTS>>>,
+    let (rect, _) =
+        ui.allocate_exact_size(egui::vec2(ui.available_width(), 40.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, egui::Color32::DARK_GRAY));
+
+    let step = rect.width() / (DATA_POINTS - 1) as f32;
+    let points = samples
+        .iter()
+        .enumerate()
+        .map(|(index, sample)| {
+            egui::pos2(
+                rect.left() + index as f32 * step,
+                rect.bottom() - (sample - min) / range * rect.height(),
+            )
+        })
+        .collect::<Vec<_>>();
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.0, egui::Color32::LIGHT_GREEN),
+    ));
 }
 
-pub fn network_info(ui: &mut egui::Ui, ui_state: &mut NetworkWindow, query: NetworkInfoQuery) {
-    let NetworkInfoQuery {
-        mut commands,
-        entities,
-
-        mut server_entities,
-
-        tick,
-        updates,
-
-        is_client,
-        mut client,
-        //client_visualizer,
-        is_server,
-        mut server,
-        //server_visualizer,
-    } = query;
-
-    egui::ScrollArea::vertical().show(ui, |ui| {
-        if let Some(tick) = tick {
-            ui.label(format!("Client tick: {:?}", tick));
-            if let Some(updates) = updates {
-                ui.label(format!("Server tick: {:?}", updates.latest()));
-                ui.label(format!(
-                    "Diff: {:?}",
-                    updates
-                        .latest()
-                        .and_then(|latest| Some(tick.tick() as i64 - latest.tick() as i64))
-                ));
-            }
-        }
+pub fn network_window(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut window: ResMut<NetworkWindow>,
+    diagnostics: Res<NetworkDiagnostics>,
+    tick: Res<NetworkTick>,
+    latest_snapshot: Res<LatestSnapshotTick>,
+    entities: &Entities,
+    mut lobby: ResMut<Lobby>,
+    mut identity: ResMut<PlayerIdentity>,
+    mut conditioner: ResMut<LinkConditioner>,
+    mut server_entities: ResMut<ServerEntities>,
+    mut client: Option<ResMut<NetworkClient>>,
+    mut server: Option<ResMut<NetworkServer>>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
 
-        if let Some(_) = is_client {
-            ui.heading("Client");
-            ui.horizontal(|ui| {
-                ui.label("IP");
-                ui.add_sized(
-                    [125.0, 16.0],
-                    egui::TextEdit::singleline(&mut ui_state.client_ip)
-                        .hint_text("ip address or domain name (e.g. 127.0.0.1)"),
-                );
-                ui.add(egui::DragValue::new(&mut ui_state.client_port));
-
-                if let Some(ref mut client) = client {
-                    if ui.button("disconnect").clicked() {
-                        client.disconnect();
-                        server_entities.disconnect(entities, &mut commands);
-                        commands.remove_resource::<RenetClient>();
-                    }
-                } else {
-                    if ui.button("connect").clicked() {
-                        match sabi::protocol::new_renet_client(
-                            &ui_state.client_ip,
-                            ui_state.client_port,
-                        ) {
+    egui::Window::new("Network")
+        .default_open(false)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.label(format!("Tick: {}", tick.0));
+                if let Some(latest) = latest_snapshot.0 {
+                    ui.label(format!("Server tick: {}", latest));
+                }
+
+                ui.heading("Client");
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.add_sized(
+                        [125.0, 16.0],
+                        egui::TextEdit::singleline(&mut identity.profile.name)
+                            .char_limit(super::lobby::MAX_NAME_LEN)
+                            .hint_text("picked by the server if empty"),
+                    );
+                    ui.color_edit_button_rgb(&mut identity.profile.color);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("IP");
+                    ui.add_sized(
+                        [125.0, 16.0],
+                        egui::TextEdit::singleline(&mut window.client_ip)
+                            .hint_text("ip address (e.g. 127.0.0.1)"),
+                    );
+                    ui.add(egui::DragValue::new(&mut window.client_port));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Password");
+                    ui.add(egui::TextEdit::singleline(&mut window.client_password).password(true));
+
+                    if let Some(ref mut client) = client {
+                        ui.label(format!("{:?}", client.state()));
+                        if ui.button("disconnect").clicked() {
+                            client.disconnect();
+                            lobby.clear();
+                            server_entities.disconnect(&mut commands, entities);
+                            commands.remove_resource::<NetworkClient>();
+                        }
+                    } else if ui.button("connect").clicked() {
+                        let connect = || -> Result<NetworkClient, Box<dyn std::error::Error>> {
+                            let server_addr =
+                                format!("{}:{}", window.client_ip, window.client_port).parse()?;
+                            let mut client =
+                                NetworkClient::new(UdpTransport::bind("0.0.0.0:0")?, server_addr)
+                                    .with_identity(identity.clone());
+                            if !window.client_password.is_empty() {
+                                client = client.with_password(window.client_password.clone());
+                            }
+                            Ok(client)
+                        };
+
+                        match connect() {
                             Ok(new_client) => {
-                                let new_client: RenetClient = new_client;
-                                ui_state.client_error = None;
+                                window.client_error = None;
                                 commands.insert_resource(new_client);
                             }
-                            Err(err) => ui_state.client_error = Some(err.to_string()),
+                            Err(err) => window.client_error = Some(err.to_string()),
                         }
                     }
+                });
+
+                if let Some(ClientState::Disconnected(reason)) =
+                    client.as_ref().map(|client| client.state())
+                {
+                    ui.colored_label(egui::Color32::RED, reason);
+                }
+                if let Some(error) = &window.client_error {
+                    ui.colored_label(egui::Color32::RED, error);
                 }
-            });
 
-            if let Some(error) = &ui_state.client_error {
-                ui.colored_label(egui::Color32::RED, error);
-            }
+                if client.is_some() {
+                    egui::CollapsingHeader::new("Client Stats")
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            graph(ui, "Tick offset", "ticks", &diagnostics.tick_offset);
+                            diagnostics.client.ui(ui);
+                        });
+                }
 
-            /*
-            if let Some(visualizer) = client_visualizer {
-                ui.collapsing("Client Stats", |ui| visualizer.draw_all(ui));
-            }
-            */
-        }
+                ui.heading("Lobby");
+                let local_id = client.as_ref().and_then(|client| client.client_id());
+                for (id, member) in lobby.list() {
+                    ui.horizontal(|ui| {
+                        let [r, g, b] = member.profile.color;
+                        let color = egui::Rgba::from_rgb(r, g, b);
+                        ui.colored_label(egui::Color32::from(color), "⏺");
+                        ui.label(format!("{} ({})", member.profile.name, id));
 
-        if let Some(_) = is_server {
-            ui.heading("Server");
-            ui.horizontal(|ui| {
-                ui.label("IP");
-                ui.add_sized(
-                    [125.0, 16.0],
-                    egui::TextEdit::singleline(&mut ui_state.server_ip)
-                        .hint_text("ip address or domain name (e.g. 127.0.0.1)"),
-                );
-                ui.add(egui::DragValue::new(&mut ui_state.server_port));
-
-                if let Some(ref mut _server) = server {
-                    if ui.button("stop").clicked() {
-                        commands.remove_resource::<RenetServer>();
-                    }
-                } else {
-                    if ui.button("host").clicked() {
-                        match sabi::protocol::new_renet_server(
-                            &ui_state.server_ip,
-                            None,
-                            ui_state.server_port,
-                        ) {
-                            Ok(new_server) => {
-                                let new_server: RenetServer = new_server;
-                                ui_state.server_error = None;
-                                commands.insert_resource(new_server);
+                        if Some(id) == local_id {
+                            let mut ready = member.ready;
+                            if ui.checkbox(&mut ready, "ready").changed() {
+                                commands.insert_resource(RequestReady(ready));
                             }
-                            Err(err) => {
-                                ui_state.server_error = Some(err.to_string());
+                        } else if member.ready {
+                            ui.label("ready");
+                        }
+                    });
+                }
+                if !lobby.away.is_empty() {
+                    ui.label(format!("{} player(s) reconnecting", lobby.away.len()));
+                }
+
+                ui.heading("Server");
+                ui.horizontal(|ui| {
+                    ui.label("IP");
+                    ui.add_sized(
+                        [125.0, 16.0],
+                        egui::TextEdit::singleline(&mut window.server_ip)
+                            .hint_text("ip address to bind (e.g. 0.0.0.0)"),
+                    );
+                    ui.add(egui::DragValue::new(&mut window.server_port));
+
+                    if let Some(ref mut server) = server {
+                        if ui.button("stop").clicked() {
+                            server.disconnect_all();
+                            commands.remove_resource::<NetworkServer>();
+                        }
+                    } else if ui.button("host").clicked() {
+                        match UdpTransport::bind((window.server_ip.as_str(), window.server_port)) {
+                            Ok(transport) => {
+                                window.server_error = None;
+                                commands.insert_resource(NetworkServer::new(transport));
                             }
+                            Err(err) => window.server_error = Some(err.to_string()),
                         }
                     }
-                }
-            });
+                });
 
-            if let Some(error) = &ui_state.server_error {
-                ui.colored_label(egui::Color32::RED, error);
-            }
+                if let Some(error) = &window.server_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
 
-            /*
-            if let Some(visualizer) = server_visualizer {
-                egui::CollapsingHeader::new("Server Stats")
-                    .default_open(true)
-                    .show(ui, |ui| {
-                        if let Some(server) = server {
+                if let Some(server) = &server {
+                    egui::CollapsingHeader::new("Server Stats")
+                        .default_open(true)
+                        .show(ui, |ui| {
                             for client_id in server.clients_id() {
+                                let Some(metrics) = diagnostics.clients.get(&client_id) else {
+                                    continue;
+                                };
+
                                 ui.collapsing(format!("Client {}", client_id), |ui| {
-                                    visualizer.draw_client_metrics(client_id, ui);
+                                    if let Some(addr) = server.client_addr(client_id) {
+                                        ui.label(format!("Address: {}", addr));
+                                    }
+                                    metrics.ui(ui);
                                 });
                             }
-                        }
+                        });
+                }
+
+                egui::CollapsingHeader::new("Link Conditioner").show(ui, |ui| {
+                    // Only touch the resource on edits, the seed restarts whenever it changes.
+                    let mut edited = conditioner.clone();
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut edited.enabled, "enabled");
+                        ui.add(egui::DragValue::new(&mut edited.seed).prefix("seed "));
                     });
-            }
-            */
-        }
-    });
+                    link_conditions_ui(ui, "Outgoing", &mut edited.outgoing);
+                    link_conditions_ui(ui, "Incoming", &mut edited.incoming);
+
+                    if edited != *conditioner {
+                        *conditioner = edited;
+                    }
+                });
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_starting_over_dont_underflow() {
+        let mut busy = ConnectionStats {
+            resent: 5,
+            ..default()
+        };
+        busy.reliable.sent_packets = 100;
+        busy.unreliable.received_bytes = 4096;
+
+        let mut metrics = ConnectionMetrics::default();
+        metrics.sample(&busy, Duration::from_secs(1));
+        metrics.sample(&ConnectionStats::default(), Duration::from_secs(1));
+
+        assert_eq!(metrics.packet_loss.back(), Some(&0.0));
+        assert_eq!(metrics.received[&Channel::Unreliable].back(), Some(&0.0));
+    }
 }
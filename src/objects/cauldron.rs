@@ -1,16 +1,229 @@
+use crate::objects::{
+    heat::{Boiling, Temperature},
+    ingredient::{Brew, BrewQuality, IngredientProperties},
+    potion::spawn_potion,
+    recipe::{load_recipes, RecipeBook, RecipeLoader, Recipes},
+    stirrer::StirProgress,
+    PotionKind,
+};
 use crate::physics::{
-    slot::{Slot, SlotDeposit},
+    slot::{insert_slot, slot_joints, Slot, SlotDeposit},
     ColliderBundle, RigidBodyBundle,
 };
 use crate::prelude::*;
 
+pub struct CauldronPlugin;
+
+impl Plugin for CauldronPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Cauldron>();
+        app.add_event::<Brewed>();
+
+        app.init_asset::<RecipeBook>()
+            .register_asset_loader(RecipeLoader);
+        app.add_systems(Startup, load_recipes);
+
+        app.add_systems(
+            FixedUpdate,
+            brew.in_set(GameplaySet)
+                .after(insert_slot)
+                .before(slot_joints)
+                // The server brews, clients get the potion replicated.
+                .run_if(not(resource_exists::<NetworkClient>)),
+        );
+    }
+}
+
 #[derive(Default, Debug, Copy, Clone, Component, Reflect)]
 #[reflect(Component)]
 pub struct Cauldron;
 
-#[derive(Default, Debug, Copy, Clone, Component, Reflect)]
+#[derive(Default, Debug, Clone, Component, Reflect)]
 #[reflect(Component)]
-pub struct Ingredient;
+pub struct Ingredient {
+    /// What recipes refer to this ingredient as, see [`crate::objects::recipe`].
+    pub kind: String,
+}
+
+impl Ingredient {
+    pub fn new(kind: impl Into<String>) -> Self {
+        Self { kind: kind.into() }
+    }
+}
+
+/// A cauldron turned its ingredients into a potion.
+#[derive(Event, Debug, Clone)]
+pub struct Brewed {
+    pub cauldron: Entity,
+    /// `None` if the brew was improvised.
+    pub recipe: Option<String>,
+    /// `None` if the brew failed and nothing came out of it.
+    pub potion: Option<Entity>,
+    pub brew: Brew,
+}
+
+/// Model for potions that don't come from a recipe.
+pub const IMPROVISED_POTION_MODEL: &str = "models/potion_normal.glb";
+
+/// Brew the first recipe matching what is slotted in a cauldron.
+///
+/// Ingredients are matched in the order they went in. Recipes that need
+/// stirring or heat wait for the cauldron's [`StirProgress`] and [`Boiling`].
+/// Without a matching recipe, a full cauldron mixes whatever is in it, see
+/// [`Brew::mix`]. The ingredients are used up and the potion shows up at the
+/// lowest slot.
+pub fn brew(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    recipes: Res<Recipes>,
+    books: Res<Assets<RecipeBook>>,
+    mut cauldrons: Query<
+        (
+            Entity,
+            &SlotDeposit,
+            Option<&mut StirProgress>,
+            Option<&Temperature>,
+            Option<&mut Boiling>,
+        ),
+        With<Cauldron>,
+    >,
+    mut slots: Query<(&mut Slot, &GlobalTransform)>,
+    ingredients: Query<(&Ingredient, Option<&IngredientProperties>)>,
+    mut brewed: EventWriter<Brewed>,
+) {
+    let Some(book) = books.get(&recipes.0) else {
+        return;
+    };
+
+    for (cauldron, deposit, mut stirred, temperature, mut boiling) in &mut cauldrons {
+        let mut contents = Vec::new();
+        for slot_entity in &deposit.slots {
+            let Ok((slot, _)) = slots.get(*slot_entity) else {
+                continue;
+            };
+            let Some(item) = slot.containing else {
+                continue;
+            };
+
+            // Anything that isn't an ingredient spoils the brew.
+            let (kind, properties) = ingredients
+                .get(item)
+                .map(|(ingredient, properties)| (ingredient.kind.as_str(), properties))
+                .unwrap_or_default();
+            contents.push((*slot_entity, item, kind, properties));
+        }
+
+        if contents.is_empty() {
+            continue;
+        }
+        contents.sort_by_key(|(_, item, _, _)| deposit.insertion_index(*item));
+
+        let kinds = contents
+            .iter()
+            .map(|(_, _, kind, _)| *kind)
+            .collect::<Vec<_>>();
+        let recipe = book.find(&kinds);
+        let heat = recipe.and_then(|recipe| recipe.heat);
+        let boiled_enough = match (heat, temperature, boiling.as_deref_mut()) {
+            (None, _, _) => true,
+            (Some(condition), Some(temperature), Some(boiling)) => boiling.update(
+                temperature.degrees,
+                &condition,
+                crate::TICK_RATE.as_secs_f32(),
+            ),
+            _ => false,
+        };
+        if let (None, Some(boiling)) = (heat, &mut boiling) {
+            // Whatever is in there now doesn't need boiling, start over for the next.
+            boiling.seconds = 0.0;
+        }
+
+        if let Some(recipe) = recipe {
+            let stirred_enough = stirred.as_deref().map_or(recipe.turns == 0.0, |stirred| {
+                stirred.satisfies(recipe.turns)
+            });
+            if !stirred_enough || !boiled_enough {
+                continue;
+            }
+        }
+
+        let properties = contents
+            .iter()
+            .filter_map(|(_, _, _, properties)| *properties)
+            .collect::<Vec<_>>();
+        let mix = Brew::mix(properties.iter().copied());
+
+        if recipe.is_none() {
+            // Only improvise once the cauldron is full and we know what is in it.
+            let full = contents.len() == deposit.slots.len();
+            if !full || properties.len() != contents.len() {
+                continue;
+            }
+        }
+
+        let Some(lowest) = deposit
+            .slots
+            .iter()
+            .filter_map(|slot| slots.get(*slot).ok())
+            .map(|(_, global)| global.translation())
+            .min_by(|a, b| a.y.total_cmp(&b.y))
+        else {
+            continue;
+        };
+
+        let items = contents
+            .iter()
+            .map(|(slot, item, _, _)| (*slot, *item))
+            .collect::<Vec<_>>();
+        for (slot_entity, item) in items {
+            commands.entity(item).despawn_recursive();
+            if let Ok((mut slot, _)) = slots.get_mut(slot_entity) {
+                slot.containing = None;
+            }
+        }
+        if let Some(stirred) = &mut stirred {
+            stirred.reset();
+        }
+        if let Some(boiling) = &mut boiling {
+            boiling.seconds = 0.0;
+        }
+
+        let (name, model) = match recipe {
+            Some(recipe) => (recipe.output.name.clone(), recipe.output.model.as_str()),
+            None if mix.quality == BrewQuality::Failed => {
+                info!("brew in {:?} failed", cauldron);
+                brewed.send(Brewed {
+                    cauldron,
+                    recipe: None,
+                    potion: None,
+                    brew: mix,
+                });
+                continue;
+            }
+            None => (mix.name(), IMPROVISED_POTION_MODEL),
+        };
+
+        info!("brewed {} in {:?}", name, cauldron);
+        let potion = spawn_potion(
+            &mut commands,
+            &asset_server,
+            Transform::from_translation(lowest).with_scale(Vec3::splat(0.5)),
+            model,
+            &name,
+        );
+        let kind = recipe
+            .and_then(|recipe| recipe.output.effect.clone())
+            .map(PotionKind::new)
+            .unwrap_or_else(|| PotionKind::from_brew(&mix));
+        commands.entity(potion).insert((mix, kind, Replicate));
+        brewed.send(Brewed {
+            cauldron,
+            recipe: recipe.map(|recipe| recipe.name.clone()),
+            potion: Some(potion),
+            brew: mix,
+        });
+    }
+}
 
 pub fn spawn_cauldron(
     commands: &mut Commands,
@@ -61,6 +274,8 @@ pub fn spawn_cauldron(
         .insert(Collider::cylinder(0.2, 0.45))
         .insert(Cauldron)
         .insert(SlotDeposit::new(slots.clone()))
+        .insert(StirProgress::default())
+        .insert((Temperature::with_thermal_mass(2.0), Boiling::default()))
         .insert(Sensor)
         .id();
 
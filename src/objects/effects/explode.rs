@@ -0,0 +1,267 @@
+//! Explosion potion effect
+//!
+//! Pushes rigid bodies within [`ExplodeEffect::radius`] away from where the
+//! potion cracked, less the further away they are. Only bodies the blast can
+//! see are pushed, so walls shield whatever is behind them. Breakable joints
+//! in range snap and [`Destructible`] pieces take damage, coming loose once
+//! they have none left.
+use bevy::utils::HashSet;
+use bevy_mod_wanderlust::GroundCaster;
+
+use super::{PotionEffect, PotionImpact};
+use crate::physics::joint_break::BreakableJoint;
+use crate::prelude::*;
+
+/// How long a knocked back player doesn't try to float on the ground, in seconds.
+pub const KNOCKBACK_AIRTIME: f32 = 0.3;
+
+#[derive(Component, Clone)]
+pub struct ExplodeEffect {
+    pub radius: f32,
+    /// Impulse given to a body right at the center, in N·s.
+    pub impulse: f32,
+    /// Damage dealt to a [`Destructible`] right at the center.
+    pub damage: f32,
+    /// Scales the impulse given to players.
+    pub knockback: f32,
+}
+
+impl Default for ExplodeEffect {
+    fn default() -> Self {
+        Self {
+            radius: 5.0,
+            impulse: 40.0,
+            damage: 10.0,
+            knockback: 0.5,
+        }
+    }
+}
+
+impl ExplodeEffect {
+    /// Share of the full blast that reaches `distance`, falling off quadratically.
+    pub fn falloff(&self, distance: f32) -> f32 {
+        if self.radius <= 0.0 {
+            return 0.0;
+        }
+
+        let falloff = (1.0 - distance / self.radius).clamp(0.0, 1.0);
+        falloff * falloff
+    }
+}
+
+impl PotionEffect for ExplodeEffect {
+    fn spawn(&self, commands: &mut Commands, impact: &PotionImpact) {
+        commands
+            .spawn(SpatialBundle {
+                transform: Transform::from_translation(impact.point),
+                ..default()
+            })
+            .insert(Name::new("Explosion"))
+            .insert(Self {
+                impulse: self.impulse * impact.strength,
+                damage: self.damage * impact.strength,
+                ..self.clone()
+            });
+    }
+}
+
+/// Terrain that can be blown apart.
+///
+/// Stays put until its health runs out, then it becomes a dynamic body.
+#[derive(Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Destructible {
+    pub health: f32,
+}
+
+impl Default for Destructible {
+    fn default() -> Self {
+        Self { health: 5.0 }
+    }
+}
+
+pub fn explode_effect(
+    mut commands: Commands,
+    ctx: Res<RapierContext>,
+    explosions: Query<(Entity, &GlobalTransform, &ExplodeEffect)>,
+    bodies: Query<(&GlobalTransform, &RigidBody)>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    mut destructibles: Query<&mut Destructible>,
+    mut controllers: Query<&mut GroundCaster>,
+    globals: Query<&GlobalTransform>,
+    breakable: Query<
+        (Entity, &ImpulseJoint, Option<&Parent>, Has<RigidBody>),
+        With<BreakableJoint>,
+    >,
+) {
+    for (explosion, explosion_global, effect) in &explosions {
+        commands.entity(explosion).despawn_recursive();
+
+        let center = explosion_global.translation();
+        let filter = QueryFilter::default().exclude_sensors();
+
+        // Whatever the blast hits first on the way is what it reaches.
+        let reaches = |body: Entity, point: Vec3| {
+            let offset = point - center;
+            let Some(direction) = offset.try_normalize() else {
+                return true;
+            };
+            ctx.cast_ray(center, direction, offset.length(), true, filter)
+                .map(|(first, _)| ctx.collider_parent(first).unwrap_or(first))
+                .map_or(true, |first| first == body)
+        };
+
+        let mut hit = HashSet::new();
+        ctx.intersections_with_shape(
+            center,
+            Quat::IDENTITY,
+            &Collider::ball(effect.radius),
+            filter,
+            |collider| {
+                hit.insert(ctx.collider_parent(collider).unwrap_or(collider));
+                true
+            },
+        );
+
+        for body in hit {
+            let Ok((body_global, rigid_body)) = bodies.get(body) else {
+                continue;
+            };
+
+            let offset = body_global.translation() - center;
+            let distance = offset.length();
+            // Anything right at the center gets blown upwards.
+            let direction = offset.try_normalize().unwrap_or(Vec3::Y);
+
+            if !reaches(body, body_global.translation()) {
+                continue;
+            }
+
+            let falloff = effect.falloff(distance);
+            if falloff <= 0.0 {
+                continue;
+            }
+
+            let mut dynamic = matches!(rigid_body, RigidBody::Dynamic);
+            if let Ok(mut destructible) = destructibles.get_mut(body) {
+                destructible.health -= effect.damage * falloff;
+                if destructible.health <= 0.0 {
+                    info!("blew apart {:?}", body);
+                    commands
+                        .entity(body)
+                        .remove::<Destructible>()
+                        .insert(RigidBody::Dynamic);
+                    dynamic = true;
+                }
+            }
+
+            if !dynamic {
+                continue;
+            }
+
+            let mut impulse = direction * effect.impulse * falloff;
+            if let Ok(mut ground_caster) = controllers.get_mut(body) {
+                // Otherwise the float spring pulls them right back down.
+                ground_caster.skip_ground_check_timer = KNOCKBACK_AIRTIME;
+                impulse = (impulse + Vec3::Y * impulse.length() * 0.5) * effect.knockback;
+            }
+
+            match impulses.get_mut(body) {
+                Ok(mut external) => external.impulse += impulse,
+                Err(_) => {
+                    commands.entity(body).try_insert(ExternalImpulse {
+                        impulse,
+                        ..default()
+                    });
+                }
+            }
+        }
+
+        for (joint, impulse_joint, parent, body) in &breakable {
+            // Joints without a body of their own hold on to their parent's.
+            let attached = if body {
+                joint
+            } else {
+                parent.map_or(joint, Parent::get)
+            };
+            let in_blast = [impulse_joint.parent, attached].into_iter().any(|body| {
+                globals.get(body).is_ok_and(|global| {
+                    let point = global.translation();
+                    point.distance(center) <= effect.radius && reaches(body, point)
+                })
+            });
+            if !in_blast {
+                continue;
+            }
+
+            // Don't leave joint entities like vine joints behind empty.
+            if body {
+                commands.entity(joint).remove::<ImpulseJoint>();
+            } else {
+                commands.entity(joint).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_off_with_distance() {
+        let effect = ExplodeEffect {
+            radius: 4.0,
+            ..default()
+        };
+        assert_eq!(effect.falloff(0.0), 1.0);
+        assert_eq!(effect.falloff(4.5), 0.0);
+        assert!(effect.falloff(1.0) > effect.falloff(3.0));
+    }
+
+    #[test]
+    fn breaks_joints_in_range() {
+        use crate::headless::HeadlessApp;
+
+        let mut app = HeadlessApp::new();
+        let world = app.world_mut();
+        let mut tie = |x: f32| {
+            let body = |world: &mut World, x: f32| {
+                world
+                    .spawn((
+                        TransformBundle::from_transform(Transform::from_xyz(x, 0.0, 0.0)),
+                        RigidBody::Dynamic,
+                        Collider::ball(0.2),
+                        GravityScale(0.0),
+                    ))
+                    .id()
+            };
+            let anchor = body(world, x + 0.5);
+            let tied = body(world, x);
+            world
+                .spawn((
+                    TransformBundle::default(),
+                    ImpulseJoint::new(anchor, FixedJointBuilder::new()),
+                    BreakableJoint {
+                        impulse: Vec3::splat(f32::INFINITY),
+                        torque: Vec3::splat(f32::INFINITY),
+                    },
+                ))
+                .set_parent(tied)
+                .id()
+        };
+        let near = tie(1.0);
+        let far = tie(20.0);
+        app.step(2);
+
+        app.world_mut()
+            .spawn((SpatialBundle::default(), ExplodeEffect::default()));
+        app.step(2);
+
+        assert!(
+            app.world().get_entity(near).is_none(),
+            "joint in range should break"
+        );
+        assert!(app.world().get::<ImpulseJoint>(far).is_some());
+    }
+}
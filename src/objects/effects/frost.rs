@@ -0,0 +1,348 @@
+//! Frost potion effect
+//!
+//! Dynamic bodies within [`FrostEffect::radius`] freeze in place: they become
+//! kinematic and get an ice shell around them. Surfaces the frost reaches get
+//! slippery ice patches laid over them, the surfaces themselves are left
+//! alone. Both thaw after a while, faster near a [`HeatSource`].
+use bevy::utils::{HashMap, HashSet};
+use bevy_rapier3d::parry::bounding_volume::BoundingVolume;
+
+use super::{
+    spiral_sphere,
+    surface::{cluster_surfaces, ClusterSettings},
+    PotionEffect, PotionImpact,
+};
+use crate::objects::heat::HeatSource;
+use crate::prelude::*;
+
+/// Friction of frozen surfaces.
+pub const ICE_FRICTION: f32 = 0.02;
+/// Smallest ice patch, for surfaces only a ray or two hit.
+pub const ICE_PATCH_RADIUS: f32 = 0.4;
+/// How far ice patches stick out of the surface they're on.
+pub const ICE_PATCH_THICKNESS: f32 = 0.02;
+/// How much faster ice thaws per unit of heat it gets.
+pub const HEAT_THAW_RATE: f32 = 0.5;
+
+#[derive(Component, Clone)]
+pub struct FrostEffect {
+    pub radius: f32,
+    /// Seconds until frozen things thaw without any heat around.
+    pub duration: f32,
+    /// How far the ice shell reaches past a frozen body.
+    pub shell_thickness: f32,
+    /// Rays cast to find surfaces to ice over.
+    pub samples: usize,
+}
+
+impl Default for FrostEffect {
+    fn default() -> Self {
+        Self {
+            radius: 3.0,
+            duration: 10.0,
+            shell_thickness: 0.1,
+            samples: 32,
+        }
+    }
+}
+
+impl PotionEffect for FrostEffect {
+    fn spawn(&self, commands: &mut Commands, impact: &PotionImpact) {
+        commands
+            .spawn(SpatialBundle {
+                transform: Transform::from_translation(impact.point),
+                ..default()
+            })
+            .insert(Name::new("Frost"))
+            .insert(Self {
+                duration: self.duration * impact.strength,
+                ..self.clone()
+            });
+    }
+}
+
+/// Time left until something thaws.
+#[derive(Debug, Default, Clone, Copy, Reflect)]
+pub struct Thaw {
+    pub seconds: f32,
+}
+
+impl Thaw {
+    /// Count down faster the more `heat` there is, returns true once thawed.
+    pub fn update(&mut self, heat: f32, dt: f32) -> bool {
+        self.seconds -= dt * (1.0 + heat.max(0.0) * HEAT_THAW_RATE);
+        self.seconds <= 0.0
+    }
+}
+
+/// A body frozen in its ice shell.
+#[derive(Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Frozen {
+    /// What the body was before it froze.
+    pub previous: RigidBody,
+    pub shell: Entity,
+    pub thaw: Thaw,
+}
+
+/// A collider with ice patches on it.
+#[derive(Debug, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct Iced {
+    pub patches: Vec<Entity>,
+    pub thaw: Thaw,
+}
+
+pub fn frost_effect(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    ctx: Res<RapierContext>,
+    frosts: Query<(Entity, &GlobalTransform, &FrostEffect)>,
+    mut bodies: Query<(&GlobalTransform, &RigidBody, Option<&mut Velocity>)>,
+    mut frozen: Query<&mut Frozen>,
+    mut iced: Query<&mut Iced>,
+    players: Query<Entity, With<Player>>,
+    parents: Query<&Parent>,
+    joints: Query<&ImpulseJoint>,
+) {
+    let ice = materials.add(StandardMaterial {
+        base_color: Color::srgba(0.7, 0.9, 1.0, 0.5),
+        alpha_mode: AlphaMode::Blend,
+        perceptual_roughness: 0.1,
+        ..default()
+    });
+
+    for (frost, frost_global, effect) in &frosts {
+        commands.entity(frost).despawn_recursive();
+
+        let center = frost_global.translation();
+        let filter = QueryFilter::default().exclude_sensors();
+        let thaw = Thaw {
+            seconds: effect.duration,
+        };
+
+        let mut hit = HashSet::new();
+        ctx.intersections_with_shape(
+            center,
+            Quat::IDENTITY,
+            &Collider::ball(effect.radius),
+            filter,
+            |collider| {
+                hit.insert(ctx.collider_parent(collider).unwrap_or(collider));
+                true
+            },
+        );
+
+        for body in hit {
+            if let Ok(mut frozen) = frozen.get_mut(body) {
+                frozen.thaw.seconds = frozen.thaw.seconds.max(thaw.seconds);
+                continue;
+            }
+
+            let Ok((body_global, rigid_body, velocity)) = bodies.get_mut(body) else {
+                continue;
+            };
+            if !matches!(rigid_body, RigidBody::Dynamic) {
+                continue;
+            }
+            // Players would be stuck for good without their controller.
+            if find_parent_with(&players, &parents, &joints, body).is_some() {
+                continue;
+            }
+
+            // Bounding sphere of the body's colliders, relative to the body.
+            let physics_scale = ctx.integration_parameters.length_unit;
+            let Some(aabb) = ctx
+                .entity2body()
+                .get(&body)
+                .and_then(|handle| ctx.bodies.get(*handle))
+                .and_then(|rapier_body| {
+                    rapier_body
+                        .colliders()
+                        .iter()
+                        .filter_map(|handle| ctx.colliders.get(*handle))
+                        .map(|collider| collider.compute_aabb())
+                        .reduce(|a, b| a.merged(&b))
+                })
+            else {
+                continue;
+            };
+            let (scale, rotation, translation) = body_global.to_scale_rotation_translation();
+            let shell_center = rotation.inverse()
+                * (Vec3::from(aabb.center()) * physics_scale - translation)
+                / scale;
+            let shell_radius = aabb.half_extents().norm() * physics_scale / scale.max_element()
+                + effect.shell_thickness;
+
+            if let Some(mut velocity) = velocity {
+                *velocity = Velocity::zero();
+            }
+
+            let shell = commands
+                .spawn(PbrBundle {
+                    mesh: meshes.add(Sphere::new(shell_radius)),
+                    material: ice.clone(),
+                    transform: Transform::from_translation(shell_center),
+                    ..default()
+                })
+                .insert(ColliderBundle {
+                    collider: Collider::ball(shell_radius),
+                    collision_groups: crate::physics::TERRAIN_GROUPING,
+                    ..default()
+                })
+                .insert((
+                    Name::new("Ice shell"),
+                    Friction {
+                        coefficient: ICE_FRICTION,
+                        combine_rule: CoefficientCombineRule::Min,
+                    },
+                ))
+                .id();
+
+            commands
+                .entity(body)
+                .add_child(shell)
+                .insert(RigidBody::KinematicPositionBased)
+                .insert(Frozen {
+                    previous: *rigid_body,
+                    shell,
+                    thaw,
+                });
+        }
+
+        // Ice over whatever isn't moving around the frost.
+        let hits = spiral_sphere(effect.samples)
+            .into_iter()
+            .filter_map(|direction| {
+                ctx.cast_ray_and_get_normal(center, direction, effect.radius, true, filter)
+            })
+            .filter(|(collider, _)| {
+                let body = ctx.collider_parent(*collider).unwrap_or(*collider);
+                let moving = bodies.get(body).map_or(false, |(_, rigid_body, _)| {
+                    matches!(rigid_body, RigidBody::Dynamic)
+                });
+                !moving && !frozen.contains(body)
+            })
+            .collect::<Vec<_>>();
+
+        let settings = ClusterSettings {
+            max_gap: effect.radius,
+            ..default()
+        };
+        let mut patches = HashMap::<Entity, Vec<Entity>>::new();
+        for surface in cluster_surfaces(hits, &settings) {
+            let radius = surface.radius().max(ICE_PATCH_RADIUS);
+            let transform = Transform::from_translation(surface.centroid)
+                .with_rotation(Quat::from_rotation_arc(Vec3::Y, surface.normal));
+            // Hits that don't span an area get a disc like the mesh instead.
+            let collider = surface
+                .collider(ICE_PATCH_THICKNESS, &transform)
+                .unwrap_or_else(|| Collider::cylinder(ICE_PATCH_THICKNESS / 2.0, radius));
+            let patch = commands
+                .spawn(PbrBundle {
+                    mesh: meshes.add(Cylinder::new(radius, ICE_PATCH_THICKNESS)),
+                    material: ice.clone(),
+                    transform,
+                    ..default()
+                })
+                .insert(ColliderBundle {
+                    collider,
+                    collision_groups: crate::physics::TERRAIN_GROUPING,
+                    ..default()
+                })
+                .insert((
+                    Name::new("Ice patch"),
+                    Friction {
+                        coefficient: ICE_FRICTION,
+                        combine_rule: CoefficientCombineRule::Min,
+                    },
+                ))
+                .id();
+
+            let mut colliders = surface.entities;
+            colliders.sort();
+            colliders.dedup();
+            for collider in colliders {
+                patches.entry(collider).or_default().push(patch);
+            }
+        }
+
+        for (collider, patches) in patches {
+            if let Ok(mut iced) = iced.get_mut(collider) {
+                iced.patches.extend(patches);
+                iced.thaw.seconds = iced.thaw.seconds.max(thaw.seconds);
+                continue;
+            }
+
+            commands.entity(collider).insert(Iced { patches, thaw });
+        }
+    }
+}
+
+/// Heat per second at `point` from all heat sources.
+fn heat_at(sources: &Query<(&GlobalTransform, &HeatSource)>, point: Vec3) -> f32 {
+    sources
+        .iter()
+        .map(|(global, source)| source.heat_at(global.translation().distance(point)))
+        .sum()
+}
+
+/// Put frozen bodies back the way they were and melt ice patches once they thaw.
+pub fn thaw(
+    mut commands: Commands,
+    sources: Query<(&GlobalTransform, &HeatSource)>,
+    mut frozen: Query<(Entity, &GlobalTransform, &mut Frozen)>,
+    mut iced: Query<(Entity, &GlobalTransform, &mut Iced)>,
+) {
+    let dt = crate::TICK_RATE.as_secs_f32();
+    for (entity, global, mut frozen) in &mut frozen {
+        if !frozen
+            .thaw
+            .update(heat_at(&sources, global.translation()), dt)
+        {
+            continue;
+        }
+
+        if let Some(shell) = commands.get_entity(frozen.shell) {
+            shell.despawn_recursive();
+        }
+        commands
+            .entity(entity)
+            .remove::<Frozen>()
+            .insert(frozen.previous);
+    }
+
+    for (entity, global, mut iced) in &mut iced {
+        if !iced
+            .thaw
+            .update(heat_at(&sources, global.translation()), dt)
+        {
+            continue;
+        }
+
+        for patch in &iced.patches {
+            if let Some(patch) = commands.get_entity(*patch) {
+                patch.despawn_recursive();
+            }
+        }
+        commands.entity(entity).remove::<Iced>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heat_thaws_faster() {
+        let mut cold = Thaw { seconds: 1.0 };
+        let mut warm = Thaw { seconds: 1.0 };
+        for _ in 0..5 {
+            assert!(!cold.update(0.0, 0.1));
+            warm.update(10.0, 0.1);
+        }
+        assert!(warm.seconds <= 0.0);
+        assert!(cold.update(0.0, 0.6));
+    }
+}
@@ -1,9 +1,15 @@
-use std::cmp::Ordering;
-
 use crate::prelude::*;
 
+pub mod explode;
+pub mod frost;
+pub mod registry;
+pub mod sampling;
+pub mod surface;
 pub mod vine;
 
+pub use registry::*;
+pub use sampling::*;
+
 #[derive(Component)]
 pub struct EffectVelocity {
     pub linear: Vec3,
@@ -12,13 +18,34 @@ pub struct EffectVelocity {
 pub struct EffectPlugin;
 impl Plugin for EffectPlugin {
     fn build(&self, app: &mut App) {
+        app.register_type::<PotionKind>()
+            .register_type::<explode::Destructible>()
+            .register_type::<frost::Frozen>()
+            .register_type::<frost::Iced>();
+        app.init_resource::<PotionEffects>()
+            .init_resource::<Sampling>()
+            .init_resource::<SampleBudget>()
+            .add_event::<SamplesReady>();
+        app.register_potion_effect(PotionKind::VINE, vine::VineEffect::default())
+            .register_potion_effect(PotionKind::EXPLODE, explode::ExplodeEffect::default())
+            .register_potion_effect(PotionKind::FROST, frost::FrostEffect::default());
+
         app.add_systems(Last, crate::previous::previous::<Velocity>);
 
         app.add_systems(
             FixedUpdate,
-            (vine::vine_effect, vine::vine_growth, vine::vine_despawn),
+            (
+                (vine::vine_effect, process_samples, vine::vine_seed).chain(),
+                vine::vine_growth,
+                vine::vine_despawn,
+                (
+                    explode::explode_effect,
+                    (frost::frost_effect, frost::thaw).chain(),
+                )
+                    .run_if(not(resource_exists::<NetworkClient>)),
+            )
+                .in_set(GameplaySet),
         );
-        app.add_systems(Update, vine::sunflower_effect);
     }
 }
 
@@ -104,58 +131,6 @@ pub fn shape_closest_point(
     point_projection.point.into()
 }
 
-#[derive(Debug, Clone)]
-pub struct Group {
-    pub center_entity: Entity,
-    pub center: RayIntersection,
-    pub entities: Vec<Entity>,
-    pub points: Vec<RayIntersection>,
-}
-
-impl Group {
-    pub fn average_normal(&self) -> Vec3 {
-        let sum = self.points.iter().map(|ray| ray.normal).sum::<Vec3>();
-        sum / self.points.len() as f32
-    }
-}
-
-/// Group points based on a given "shape intersection".
-pub fn group_points(
-    mut points: Vec<(Entity, RayIntersection)>,
-    intersects: impl Fn(RayIntersection, RayIntersection, &[Entity], &[RayIntersection]) -> bool,
-) -> Vec<Group> {
-    let mut groups = Vec::new();
-    while let Some((center_entity, center)) = points.pop() {
-        let mut group_entities = Vec::new();
-        let mut group_points = Vec::new();
-        group_entities.push(center_entity);
-        group_points.push(center);
-
-        let mut to_remove = Vec::new();
-        for (index, (other_entity, other_ray)) in points.iter().enumerate() {
-            if intersects(center, *other_ray, &group_entities, &group_points) {
-                group_entities.push(*other_entity);
-                group_points.push(*other_ray);
-                to_remove.push(index);
-            }
-        }
-
-        to_remove.sort_by(|a, b| b.cmp(a)); // descending
-        for index in to_remove {
-            points.swap_remove(index);
-        }
-
-        groups.push(Group {
-            center_entity,
-            center,
-            entities: group_entities,
-            points: group_points,
-        });
-    }
-
-    groups
-}
-
 pub fn debug_colors(n: usize) -> Vec<Color> {
     let colors = [
         css::RED,
@@ -180,166 +155,81 @@ pub struct Scatter {
     pub angle: f32,
 }
 
-pub fn scatter_sampling(
-    ctx: &RapierContext,
-    from: Vec3,
-    samples: usize,
-    radius: f32,
-    _gizmos: &mut RetainedGizmos,
-) -> Vec<(Entity, RayIntersection)> {
-    let mut results = Vec::new();
-
-    let scatter_dirs = |scatter: &Scatter| {
-        let outwards = Quat::from_axis_angle(Vec3::Y, scatter.angle)
-            * Quat::from_axis_angle(Vec3::X, scatter.angle);
-        [
-            scatter.dir,
-            scatter.dir
-                * Quat::from_axis_angle(Vec3::Y, scatter.angle)
-                * Quat::from_axis_angle(Vec3::X, scatter.angle),
-            scatter.dir
-                * Quat::from_axis_angle(Vec3::Y, -scatter.angle)
-                * Quat::from_axis_angle(Vec3::X, scatter.angle),
-            scatter.dir
-                * Quat::from_axis_angle(Vec3::Y, -scatter.angle)
-                * Quat::from_axis_angle(Vec3::X, -scatter.angle),
-            scatter.dir
-                * Quat::from_axis_angle(Vec3::Y, scatter.angle)
-                * Quat::from_axis_angle(Vec3::X, -scatter.angle),
-            scatter.dir * Quat::from_axis_angle(Vec3::Y, scatter.angle),
-            scatter.dir * Quat::from_axis_angle(Vec3::Y, -scatter.angle),
-            scatter.dir * Quat::from_axis_angle(Vec3::X, scatter.angle),
-            scatter.dir * Quat::from_axis_angle(Vec3::X, -scatter.angle),
-            //scatter.dir * Quat::from_axis_angle(Vec3::X, scatter.angle * 1.5),
-            //scatter.dir * Quat::from_axis_angle(Vec3::Y, 90f32.to_radians()) * outwards,
-            //scatter.dir * Quat::from_axis_angle(Vec3::X, scatter.angle * 2.5),
-            //scatter.dir * Quat::from_axis_angle(Vec3::Y, 180f32.to_radians()) * outwards,
-            //scatter.dir * Quat::from_axis_angle(Vec3::X, scatter.angle * 3.5),
-            //scatter.dir * Quat::from_axis_angle(Vec3::Y, 270f32.to_radians()) * outwards,
-        ]
-    };
-
-    let mut scatters = Vec::new();
+impl Scatter {
+    /// Scatters going out from `from` in every direction.
+    pub fn around(from: Vec3) -> Vec<Scatter> {
+        let dirs = [
+            Quat::from_axis_angle(Vec3::Y, 0f32.to_radians()),
+            Quat::from_axis_angle(Vec3::Y, 90f32.to_radians()),
+            Quat::from_axis_angle(Vec3::Y, 180f32.to_radians()),
+            Quat::from_axis_angle(Vec3::Y, 270f32.to_radians()),
+            Quat::from_axis_angle(Vec3::X, 90f32.to_radians()),
+            Quat::from_axis_angle(Vec3::X, 270f32.to_radians()),
+        ];
 
-    let dirs = [
-        Quat::from_axis_angle(Vec3::Y, 0f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, 90f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, 180f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, 270f32.to_radians()),
-        Quat::from_axis_angle(Vec3::X, 90f32.to_radians()),
-        Quat::from_axis_angle(Vec3::X, 270f32.to_radians()),
-        //Quat::from_axis_angle(Vec3::Y, 180f32.to_radians()),
-        //Quat::from_axis_angle(Vec3::Y, 270f32.to_radians()),
-        //Quat::from_axis_angle(Vec3::Z, 0f32.to_radians()),
-        /*
-        Quat::from_axis_angle(Vec3::Z, 90f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Z, 180f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Z, 270f32.to_radians()),
-        */
-
-        /*
-        Quat::from_axis_angle(Vec3::Y, 45f32.to_radians()) * Quat::from_axis_angle(Vec3::X, 45f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, 90f32.to_radians()) * Quat::from_axis_angle(Vec3::Y, 45f32.to_radians()) * Quat::from_axis_angle(Vec3::X, 45f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, 180f32.to_radians()) * Quat::from_axis_angle(Vec3::Y, 45f32.to_radians()) * Quat::from_axis_angle(Vec3::X, 45f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, 270f32.to_radians()) * Quat::from_axis_angle(Vec3::Y, 45f32.to_radians()) * Quat::from_axis_angle(Vec3::X, 45f32.to_radians()),
-        */
-        /*Quat::from_axis_angle(Vec3::Y, -45f32.to_radians()) * Quat::from_axis_angle(Vec3::X, 45f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, 45f32.to_radians()) * Quat::from_axis_angle(Vec3::X, -45f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, -45f32.to_radians()) * Quat::from_axis_angle(Vec3::X, -45f32.to_radians()),
-
-        Quat::from_axis_angle(Vec3::Y, 135f32.to_radians()) * Quat::from_axis_angle(Vec3::X, 45f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, -135f32.to_radians()) * Quat::from_axis_angle(Vec3::X, 45f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, 135f32.to_radians()) * Quat::from_axis_angle(Vec3::X, -45f32.to_radians()),
-        Quat::from_axis_angle(Vec3::Y, -135f32.to_radians()) * Quat::from_axis_angle(Vec3::X, -45f32.to_radians()),
-        */
-    ];
-    scatters.extend(
         dirs.iter()
             .map(|dir| Scatter {
                 from,
                 dir: *dir,
                 angle: 45f32.to_radians(),
             })
-            .map(|scatter| {
-                scatter_dirs(&scatter)
-                    .iter()
-                    .map(|dir| Scatter {
-                        from: scatter.from,
-                        dir: *dir,
-                        angle: scatter.angle / 2.0,
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .flatten(),
-    );
-
-    let step = radius / 4.0;
-    while let Some(scatter) = scatters.pop() {
-        if scatter.angle < 1f32.to_radians() || scatter.from.distance(from) + 0.25 >= radius {
-            continue;
-        }
-
-        //let color = colors[(step as usize % colors.len()) - 1];
-
-        let mut toi = step;
-        if let Some((entity, ray)) = ctx.cast_ray_and_get_normal(
-            scatter.from,
-            scatter.dir * Vec3::NEG_Z,
-            step,
-            true,
-            QueryFilter::default().exclude_sensors(),
-        ) {
-            toi = ray.time_of_impact;
-            results.push((entity, ray));
-        } else {
-            let traveled = scatter.from + scatter.dir * Vec3::NEG_Z * step;
-
-            let new = scatter_dirs(&scatter);
-
-            for new_dir in new {
-                scatters.push(Scatter {
-                    from: traveled,
-                    dir: new_dir.normalize(),
-                    angle: scatter.angle / 2.0,
-                });
-            }
-        }
+            .flat_map(|scatter| scatter.spread(scatter.from))
+            .collect()
+    }
 
-        /*
-        gizmos.ray(
-            1000.0,
-            scatter.from,
-            scatter.dir * Vec3::NEG_Z * toi,
-            Color::CRIMSON,
-        );
-        */
+    /// This direction and the ones `angle` around it, starting at `from` with half the angle.
+    pub fn spread(&self, from: Vec3) -> [Scatter; 9] {
+        [
+            self.dir,
+            self.dir
+                * Quat::from_axis_angle(Vec3::Y, self.angle)
+                * Quat::from_axis_angle(Vec3::X, self.angle),
+            self.dir
+                * Quat::from_axis_angle(Vec3::Y, -self.angle)
+                * Quat::from_axis_angle(Vec3::X, self.angle),
+            self.dir
+                * Quat::from_axis_angle(Vec3::Y, -self.angle)
+                * Quat::from_axis_angle(Vec3::X, -self.angle),
+            self.dir
+                * Quat::from_axis_angle(Vec3::Y, self.angle)
+                * Quat::from_axis_angle(Vec3::X, -self.angle),
+            self.dir * Quat::from_axis_angle(Vec3::Y, self.angle),
+            self.dir * Quat::from_axis_angle(Vec3::Y, -self.angle),
+            self.dir * Quat::from_axis_angle(Vec3::X, self.angle),
+            self.dir * Quat::from_axis_angle(Vec3::X, -self.angle),
+        ]
+        .map(|dir| Scatter {
+            from,
+            dir: dir.normalize(),
+            angle: self.angle / 2.0,
+        })
     }
+}
 
-    results
+/// Cast all the rays of a [`SamplePattern::Scatter`] right away.
+///
+/// Prefer [`Sampling::request`] during gameplay, this hitches for large radii.
+pub fn scatter_sampling(
+    ctx: &RapierContext,
+    from: Vec3,
+    samples: usize,
+    radius: f32,
+    _gizmos: &mut RetainedGizmos,
+) -> Vec<(Entity, RayIntersection)> {
+    SampleRays::new(from, SamplePattern::Scatter, radius, samples)
+        .cast_all(ctx, QueryFilter::default().exclude_sensors())
 }
 
+/// Cast all the rays of a [`SamplePattern::Sunflower`] right away.
+///
+/// Prefer [`Sampling::request`] during gameplay, this hitches for many samples.
 pub fn sunflower_sampling(
     ctx: &RapierContext,
     from: Vec3,
     samples: usize,
     radius: f32,
-    gizmos: &mut RetainedGizmos,
+    _gizmos: &mut RetainedGizmos,
 ) -> Vec<(Entity, RayIntersection)> {
-    let mut results = Vec::new();
-
-    for point in crate::objects::sunflower_circle(samples, 0.0) {
-        let point = Vec3::new(point.x, 0.0, point.y) * radius;
-
-        if let Some((entity, ray)) = ctx.cast_ray_and_get_normal(
-            from + point,
-            -Vec3::Y,
-            radius,
-            true,
-            QueryFilter::default().exclude_sensors(),
-        ) {
-            results.push((entity, ray));
-        }
-    }
-
-    results
+    SampleRays::new(from, SamplePattern::Sunflower, radius, samples)
+        .cast_all(ctx, QueryFilter::default().exclude_sensors())
 }
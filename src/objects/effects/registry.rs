@@ -0,0 +1,184 @@
+//! What potions do when they crack.
+//!
+//! Effects are registered by [`PotionKind`] with
+//! [`RegisterPotionEffect::register_potion_effect`], and
+//! [`crate::objects::potion::potion_contact_explode`] looks them up when a
+//! potion breaks.
+use bevy::utils::HashMap;
+
+use crate::objects::ingredient::{Brew, Element};
+use crate::prelude::*;
+
+/// Selects which registered [`PotionEffect`] a potion has.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Component, Reflect)]
+#[reflect(Component)]
+pub struct PotionKind(pub String);
+
+impl PotionKind {
+    pub const VINE: &'static str = "vine";
+    pub const EXPLODE: &'static str = "explode";
+    pub const FROST: &'static str = "frost";
+    pub const FIRE: &'static str = "fire";
+
+    pub fn new(kind: impl Into<String>) -> Self {
+        Self(kind.into())
+    }
+
+    /// Kind for a potion that didn't come from a recipe.
+    pub fn from_brew(brew: &Brew) -> Self {
+        if brew.volatile {
+            return Self::new(Self::EXPLODE);
+        }
+
+        Self::new(match brew.element {
+            Some(Element::Fire) => Self::FIRE,
+            Some(Element::Frost) => Self::FROST,
+            Some(Element::Earth) => Self::EXPLODE,
+            Some(Element::Life) | None => Self::VINE,
+        })
+    }
+}
+
+impl Default for PotionKind {
+    fn default() -> Self {
+        Self::new(Self::VINE)
+    }
+}
+
+/// A potion cracking.
+#[derive(Debug, Clone, Copy)]
+pub struct PotionImpact {
+    pub potion: Entity,
+    /// Where the potion was when it cracked.
+    pub point: Vec3,
+    /// Force of the hit on the potion.
+    pub force: Vec3,
+    pub velocity: Velocity,
+    /// [`Brew::strength`] of the potion, 1 if it has none.
+    pub strength: f32,
+}
+
+pub trait PotionEffect: Send + Sync + 'static {
+    /// Spawn whatever the effect does for a cracked potion.
+    fn spawn(&self, commands: &mut Commands, impact: &PotionImpact);
+
+    /// Whether clients spawn the effect too when the server cracks a potion.
+    ///
+    /// Only for effects whose systems run everywhere, the others are left to
+    /// the server and clients get the results replicated.
+    fn on_clients(&self) -> bool {
+        false
+    }
+}
+
+/// Plain functions work as effects without parameters.
+impl<F> PotionEffect for F
+where
+    F: Fn(&mut Commands, &PotionImpact) + Send + Sync + 'static,
+{
+    fn spawn(&self, commands: &mut Commands, impact: &PotionImpact) {
+        self(commands, impact)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PotionEffects {
+    effects: HashMap<String, Box<dyn PotionEffect>>,
+}
+
+impl PotionEffects {
+    /// Replaces whatever was registered for `kind` before.
+    pub fn register(&mut self, kind: impl Into<String>, effect: impl PotionEffect) {
+        self.effects.insert(kind.into(), Box::new(effect));
+    }
+
+    pub fn get(&self, kind: &PotionKind) -> Option<&dyn PotionEffect> {
+        self.effects.get(&kind.0).map(|effect| &**effect)
+    }
+
+    /// Spawn the effect for `kind`, returns false if there is none.
+    pub fn spawn(&self, kind: &PotionKind, commands: &mut Commands, impact: &PotionImpact) -> bool {
+        let Some(effect) = self.get(kind) else {
+            return false;
+        };
+
+        effect.spawn(commands, impact);
+        true
+    }
+
+    /// Spawn the effect for a potion the server cracked, if it is one clients
+    /// spawn themselves, see [`PotionEffect::on_clients`].
+    pub fn spawn_on_client(
+        &self,
+        kind: &PotionKind,
+        commands: &mut Commands,
+        impact: &PotionImpact,
+    ) {
+        if let Some(effect) = self.get(kind).filter(|effect| effect.on_clients()) {
+            effect.spawn(commands, impact);
+        }
+    }
+}
+
+pub trait RegisterPotionEffect {
+    fn register_potion_effect(
+        &mut self,
+        kind: impl Into<String>,
+        effect: impl PotionEffect,
+    ) -> &mut Self;
+}
+
+impl RegisterPotionEffect for App {
+    fn register_potion_effect(
+        &mut self,
+        kind: impl Into<String>,
+        effect: impl PotionEffect,
+    ) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(PotionEffects::default)
+            .register(kind, effect);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::world::CommandQueue;
+
+    use super::*;
+
+    #[derive(Component)]
+    struct Spawned(f32);
+
+    #[test]
+    fn spawns_registered_kind() {
+        let mut effects = PotionEffects::default();
+        effects.register("test", |commands: &mut Commands, impact: &PotionImpact| {
+            commands.spawn(Spawned(impact.strength));
+        });
+
+        let mut world = World::new();
+        let potion = world.spawn_empty().id();
+        let impact = PotionImpact {
+            potion,
+            point: Vec3::ZERO,
+            force: Vec3::Y,
+            velocity: Velocity::default(),
+            strength: 2.0,
+        };
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        assert!(effects.spawn(&PotionKind::new("test"), &mut commands, &impact));
+        assert!(!effects.spawn(&PotionKind::new("missing"), &mut commands, &impact));
+        // Plain functions are left to the server.
+        effects.spawn_on_client(&PotionKind::new("test"), &mut commands, &impact);
+        queue.apply(&mut world);
+
+        let mut spawned = world.query::<&Spawned>();
+        assert_eq!(
+            spawned.iter(&world).map(|spawned| spawned.0).sum::<f32>(),
+            2.0
+        );
+    }
+}
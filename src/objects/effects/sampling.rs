@@ -0,0 +1,318 @@
+//! Raycast sampling for effects, spread out over ticks.
+//!
+//! Effects hand a [`SampleRequest`] to [`Sampling::request`] and get the hits
+//! back in a [`SamplesReady`] event once [`process_samples`] got through all of
+//! its rays. No more than [`SampleBudget::rays_per_tick`] rays are cast per
+//! tick across all requests, so a cracking potion doesn't hitch the game.
+use std::collections::VecDeque;
+
+use super::{spiral_sphere, sunflower_circle, Scatter};
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplePattern {
+    /// Rays from the origin outwards in every direction.
+    SpiralSphere,
+    /// Rays straight down from a disc around the origin.
+    Sunflower,
+    /// Rays that keep splitting up while they don't hit anything, so open
+    /// space gets explored further. Doesn't use the sample count.
+    Scatter,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SampleRequest {
+    /// Who the hits are for, usually the effect entity.
+    pub requester: Entity,
+    pub origin: Vec3,
+    pub pattern: SamplePattern,
+    pub radius: f32,
+    pub samples: usize,
+    pub flags: QueryFilterFlags,
+}
+
+impl SampleRequest {
+    pub fn new(requester: Entity, origin: Vec3, pattern: SamplePattern) -> Self {
+        Self {
+            requester,
+            origin,
+            pattern,
+            radius: 1.0,
+            samples: 100,
+            flags: QueryFilterFlags::EXCLUDE_SENSORS,
+        }
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn with_flags(mut self, flags: QueryFilterFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SampleId(u64);
+
+/// All rays of a [`SampleRequest`] have been cast.
+#[derive(Event, Debug, Clone)]
+pub struct SamplesReady {
+    pub id: SampleId,
+    pub requester: Entity,
+    pub hits: Vec<(Entity, RayIntersection)>,
+}
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct SampleBudget {
+    pub rays_per_tick: usize,
+}
+
+impl Default for SampleBudget {
+    fn default() -> Self {
+        Self {
+            rays_per_tick: 2_000,
+        }
+    }
+}
+
+/// Rays left to cast for a pattern.
+#[derive(Debug, Clone)]
+pub enum SampleRays {
+    /// Origin, direction and length of each ray.
+    Fixed(Vec<(Vec3, Vec3, f32)>),
+    Scatter {
+        origin: Vec3,
+        radius: f32,
+        scatters: Vec<Scatter>,
+    },
+}
+
+impl SampleRays {
+    pub fn new(origin: Vec3, pattern: SamplePattern, radius: f32, samples: usize) -> Self {
+        match pattern {
+            SamplePattern::SpiralSphere => Self::Fixed(
+                spiral_sphere(samples)
+                    .into_iter()
+                    .map(|dir| (origin, dir, radius))
+                    .collect(),
+            ),
+            SamplePattern::Sunflower => Self::Fixed(
+                sunflower_circle(samples, 0.0)
+                    .into_iter()
+                    .map(|point| {
+                        (
+                            origin + Vec3::new(point.x, 0.0, point.y) * radius,
+                            -Vec3::Y,
+                            radius,
+                        )
+                    })
+                    .collect(),
+            ),
+            SamplePattern::Scatter => Self::Scatter {
+                origin,
+                radius,
+                scatters: Scatter::around(origin),
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Fixed(rays) => rays.is_empty(),
+            Self::Scatter { scatters, .. } => scatters.is_empty(),
+        }
+    }
+
+    /// Cast the next ray, `None` once there are no rays left.
+    pub fn cast_next(
+        &mut self,
+        ctx: &RapierContext,
+        filter: QueryFilter,
+    ) -> Option<Option<(Entity, RayIntersection)>> {
+        match self {
+            Self::Fixed(rays) => {
+                let (origin, dir, length) = rays.pop()?;
+                Some(ctx.cast_ray_and_get_normal(origin, dir, length, true, filter))
+            }
+            Self::Scatter {
+                origin,
+                radius,
+                scatters,
+            } => loop {
+                let scatter = scatters.pop()?;
+                if scatter.angle < 1f32.to_radians()
+                    || scatter.from.distance(*origin) + 0.25 >= *radius
+                {
+                    continue;
+                }
+
+                let step = *radius / 4.0;
+                let dir = scatter.dir * Vec3::NEG_Z;
+                let hit = ctx.cast_ray_and_get_normal(scatter.from, dir, step, true, filter);
+                if hit.is_none() {
+                    scatters.extend(scatter.spread(scatter.from + dir * step));
+                }
+                break Some(hit);
+            },
+        }
+    }
+
+    /// Cast every ray right away.
+    pub fn cast_all(
+        mut self,
+        ctx: &RapierContext,
+        filter: QueryFilter,
+    ) -> Vec<(Entity, RayIntersection)> {
+        let mut hits = Vec::new();
+        while let Some(hit) = self.cast_next(ctx, filter) {
+            hits.extend(hit);
+        }
+        hits
+    }
+}
+
+struct PendingSamples {
+    id: SampleId,
+    request: SampleRequest,
+    rays: SampleRays,
+    hits: Vec<(Entity, RayIntersection)>,
+}
+
+/// Sample requests waiting for their rays to be cast, oldest first.
+#[derive(Resource, Default)]
+pub struct Sampling {
+    next_id: u64,
+    pending: VecDeque<PendingSamples>,
+}
+
+impl Sampling {
+    pub fn request(&mut self, request: SampleRequest) -> SampleId {
+        let id = SampleId(self.next_id);
+        self.next_id += 1;
+        self.pending.push_back(PendingSamples {
+            id,
+            request,
+            rays: SampleRays::new(
+                request.origin,
+                request.pattern,
+                request.radius,
+                request.samples,
+            ),
+            hits: Vec::new(),
+        });
+        id
+    }
+
+    /// Requests still waiting on rays.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Cast rays for pending requests until the budget for this tick runs out.
+pub fn process_samples(
+    ctx: Res<RapierContext>,
+    budget: Res<SampleBudget>,
+    mut sampling: ResMut<Sampling>,
+    mut ready: EventWriter<SamplesReady>,
+) {
+    let mut rays = budget.rays_per_tick;
+    while let Some(pending) = sampling.pending.front_mut() {
+        if !pending.rays.is_empty() {
+            if rays == 0 {
+                break;
+            }
+
+            if let Some(hit) = pending.rays.cast_next(&ctx, pending.request.flags.into()) {
+                pending.hits.extend(hit);
+                rays -= 1;
+            }
+            continue;
+        }
+
+        let Some(done) = sampling.pending.pop_front() else {
+            break;
+        };
+        ready.send(SamplesReady {
+            id: done.id,
+            requester: done.request.requester,
+            hits: done.hits,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::HeadlessApp;
+
+    #[derive(Resource, Default)]
+    struct Ready(Vec<SampleId>);
+
+    fn collect_ready(mut ready: ResMut<Ready>, mut events: EventReader<SamplesReady>) {
+        ready.0.extend(events.read().map(|event| event.id));
+    }
+
+    /// Rays left for each pending request.
+    fn rays_left(app: &HeadlessApp) -> Vec<usize> {
+        app.world()
+            .resource::<Sampling>()
+            .pending
+            .iter()
+            .map(|pending| match &pending.rays {
+                SampleRays::Fixed(rays) => rays.len(),
+                SampleRays::Scatter { .. } => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fixed_patterns_cast_every_sample() {
+        for pattern in [SamplePattern::SpiralSphere, SamplePattern::Sunflower] {
+            let SampleRays::Fixed(rays) = SampleRays::new(Vec3::ZERO, pattern, 2.0, 50) else {
+                panic!("{:?} should have a fixed set of rays", pattern);
+            };
+            assert_eq!(rays.len(), 50);
+            assert!(rays.iter().all(|(_, _, length)| *length == 2.0));
+        }
+    }
+
+    #[test]
+    fn requests_are_spread_over_ticks() {
+        let mut app = HeadlessApp::new();
+        app.app_mut()
+            .init_resource::<Ready>()
+            .insert_resource(SampleBudget { rays_per_tick: 10 });
+        app.add_systems(FixedUpdate, collect_ready.after(process_samples));
+
+        let requester = app.world_mut().spawn_empty().id();
+        let mut sampling = app.world_mut().resource_mut::<Sampling>();
+        let first = sampling.request(
+            SampleRequest::new(requester, Vec3::ZERO, SamplePattern::SpiralSphere).with_samples(25),
+        );
+        let second = sampling.request(
+            SampleRequest::new(requester, Vec3::ZERO, SamplePattern::Sunflower).with_samples(5),
+        );
+
+        app.tick();
+        assert_eq!(rays_left(&app), [15, 5]);
+        app.tick();
+        assert_eq!(rays_left(&app), [5, 5]);
+        assert!(app.world().resource::<Ready>().0.is_empty());
+
+        app.tick();
+        assert!(rays_left(&app).is_empty());
+        assert_eq!(app.world().resource::<Ready>().0, [first, second]);
+
+        app.step(3);
+        assert_eq!(app.world().resource::<Ready>().0, [first, second]);
+    }
+}
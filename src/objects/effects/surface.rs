@@ -0,0 +1,309 @@
+//! Grouping sampled ray hits into surface patches.
+//!
+//! Hits join a patch when they are close to a hit already in it, facing about
+//! the same way and not too far off its plane. Comparing against neighbouring
+//! hits instead of the whole patch lets gently curved surfaces end up in a
+//! single patch; [`SurfacePatch::planar`] tells them apart from flat ones.
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterSettings {
+    /// Least dot product between the normals of neighbouring hits.
+    pub normal_threshold: f32,
+    /// Furthest a hit can be off the plane of its neighbour.
+    pub plane_distance: f32,
+    /// Furthest a hit can be from its closest neighbour.
+    pub max_gap: f32,
+    /// Least dot product between every normal and the average one for a patch
+    /// to count as planar.
+    pub planar_threshold: f32,
+    /// Patches with fewer hits than this are dropped.
+    pub min_points: usize,
+}
+
+impl Default for ClusterSettings {
+    fn default() -> Self {
+        Self {
+            normal_threshold: 0.9,
+            plane_distance: 0.05,
+            max_gap: 0.5,
+            planar_threshold: 0.98,
+            min_points: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SurfacePatch {
+    /// Entity each hit was on, lined up with `points` and `normals`.
+    pub entities: Vec<Entity>,
+    pub points: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub centroid: Vec3,
+    pub normal: Vec3,
+    /// Area of the hits' convex hull on the patch's plane.
+    pub area: f32,
+    pub planar: bool,
+}
+
+impl SurfacePatch {
+    fn new(entity: Entity, point: Vec3, normal: Vec3) -> Self {
+        Self {
+            entities: vec![entity],
+            points: vec![point],
+            normals: vec![normal],
+            centroid: point,
+            normal,
+            area: 0.0,
+            planar: true,
+        }
+    }
+
+    /// Whether a hit at `point` facing `normal` continues this patch.
+    pub fn accepts(&self, point: Vec3, normal: Vec3, settings: &ClusterSettings) -> bool {
+        self.points
+            .iter()
+            .zip(&self.normals)
+            .any(|(neighbour, neighbour_normal)| {
+                neighbour.distance(point) <= settings.max_gap
+                    && neighbour_normal.dot(normal) >= settings.normal_threshold
+                    && (point - *neighbour).dot(*neighbour_normal).abs() <= settings.plane_distance
+            })
+    }
+
+    fn merge(&mut self, other: SurfacePatch) {
+        self.entities.extend(other.entities);
+        self.points.extend(other.points);
+        self.normals.extend(other.normals);
+    }
+
+    /// Recompute centroid, normal, area and planarity from the hits.
+    fn finish(&mut self, settings: &ClusterSettings) {
+        let count = self.points.len() as f32;
+        self.centroid = self.points.iter().sum::<Vec3>() / count;
+        self.normal = self
+            .normals
+            .iter()
+            .sum::<Vec3>()
+            .try_normalize()
+            .unwrap_or(self.normals[0]);
+        self.planar = self
+            .normals
+            .iter()
+            .all(|normal| normal.dot(self.normal) >= settings.planar_threshold);
+
+        let (x, y) = self.normal.any_orthonormal_pair();
+        let projected = self
+            .points
+            .iter()
+            .map(|point| {
+                let offset = *point - self.centroid;
+                Vec2::new(offset.dot(x), offset.dot(y))
+            })
+            .collect::<Vec<_>>();
+        self.area = polygon_area(&convex_hull_2d(projected));
+    }
+
+    /// Convex hull of the hits pushed `thickness` out of the surface, relative
+    /// to `frame`. `None` if the hits don't span an area.
+    pub fn collider(&self, thickness: f32, frame: &Transform) -> Option<Collider> {
+        let to_local = frame.compute_affine().inverse();
+        let points = self
+            .points
+            .iter()
+            .zip(&self.normals)
+            .flat_map(|(point, normal)| [*point, *point + *normal * thickness])
+            .map(|point| to_local.transform_point3(point))
+            .collect::<Vec<_>>();
+        Collider::convex_hull(&points)
+    }
+
+    /// Radius of a disc with the same area.
+    pub fn radius(&self) -> f32 {
+        (self.area / std::f32::consts::PI).sqrt()
+    }
+}
+
+/// Group `hits` into surface patches.
+pub fn cluster_surfaces(
+    hits: impl IntoIterator<Item = (Entity, RayIntersection)>,
+    settings: &ClusterSettings,
+) -> Vec<SurfacePatch> {
+    let mut patches: Vec<SurfacePatch> = Vec::new();
+    for (entity, hit) in hits {
+        let accepting = patches
+            .iter()
+            .enumerate()
+            .filter(|(_, patch)| patch.accepts(hit.point, hit.normal, settings))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        let Some((&first, rest)) = accepting.split_first() else {
+            patches.push(SurfacePatch::new(entity, hit.point, hit.normal));
+            continue;
+        };
+
+        // The hit can bridge patches that didn't touch before.
+        for &index in rest.iter().rev() {
+            let other = patches.swap_remove(index);
+            patches[first].merge(other);
+        }
+
+        let patch = &mut patches[first];
+        patch.entities.push(entity);
+        patch.points.push(hit.point);
+        patch.normals.push(hit.normal);
+    }
+
+    patches.retain(|patch| patch.points.len() >= settings.min_points);
+    for patch in &mut patches {
+        patch.finish(settings);
+    }
+    patches
+}
+
+/// Convex hull in counter-clockwise order, see Andrew's monotone chain.
+pub fn convex_hull_2d(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let cross = |o: Vec2, a: Vec2, b: Vec2| (a - o).perp_dot(b - o);
+    let mut hull: Vec<Vec2> = Vec::new();
+    for pass in 0..2 {
+        let start = hull.len();
+        let ordered: Box<dyn Iterator<Item = &Vec2>> = if pass == 0 {
+            Box::new(points.iter())
+        } else {
+            Box::new(points.iter().rev())
+        };
+
+        for &point in ordered {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point is where the other half starts.
+        hull.pop();
+    }
+    hull
+}
+
+/// Area of a simple polygon.
+pub fn polygon_area(polygon: &[Vec2]) -> f32 {
+    if polygon.len() < 3 {
+        return 0.0;
+    }
+
+    let twice = polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b))
+        .sum::<f32>();
+    twice.abs() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_rapier3d::rapier::geometry::FeatureId;
+
+    use super::*;
+
+    fn hit(point: Vec3, normal: Vec3) -> (Entity, RayIntersection) {
+        (
+            Entity::from_raw(0),
+            RayIntersection {
+                time_of_impact: 0.0,
+                point,
+                normal,
+                feature: FeatureId::Unknown,
+            },
+        )
+    }
+
+    /// Hits on a grid `size` wide with `step` between them.
+    fn grid(
+        size: f32,
+        step: f32,
+        at: impl Fn(f32, f32) -> (Vec3, Vec3),
+    ) -> Vec<(Entity, RayIntersection)> {
+        let count = (size / step).round() as usize;
+        let mut hits = Vec::new();
+        for i in 0..=count {
+            for j in 0..=count {
+                let (point, normal) = at(i as f32 * step, j as f32 * step);
+                hits.push(hit(point, normal));
+            }
+        }
+        hits
+    }
+
+    #[test]
+    fn floor_and_wall_are_separate_patches() {
+        let mut hits = grid(1.0, 0.25, |x, z| (Vec3::new(x, 0.0, z), Vec3::Y));
+        hits.extend(grid(1.0, 0.25, |y, z| {
+            (Vec3::new(0.0, y + 0.25, z), Vec3::X)
+        }));
+
+        let patches = cluster_surfaces(hits, &ClusterSettings::default());
+        assert_eq!(patches.len(), 2);
+        for patch in &patches {
+            assert!(patch.planar);
+            assert!((patch.area - 1.0).abs() < 1e-4);
+            assert!(patch.collider(0.05, &Transform::IDENTITY).is_some());
+        }
+        assert!(patches
+            .iter()
+            .any(|patch| patch.normal.distance(Vec3::Y) < 1e-5));
+        assert!(patches
+            .iter()
+            .any(|patch| patch.normal.distance(Vec3::X) < 1e-5));
+    }
+
+    #[test]
+    fn gaps_split_patches() {
+        let mut hits = grid(1.0, 0.25, |x, z| (Vec3::new(x, 0.0, z), Vec3::Y));
+        hits.extend(grid(1.0, 0.25, |x, z| {
+            (Vec3::new(x + 3.0, 0.0, z), Vec3::Y)
+        }));
+
+        let patches = cluster_surfaces(hits, &ClusterSettings::default());
+        assert_eq!(patches.len(), 2);
+        assert!(patches
+            .iter()
+            .any(|patch| patch.centroid.distance(Vec3::new(0.5, 0.0, 0.5)) < 1e-5));
+    }
+
+    #[test]
+    fn curved_surfaces_stay_together() {
+        // Around a quarter of a cylinder lying along Z.
+        let radius = 2.0;
+        let hits = grid(1.5, 0.1, |angle, z| {
+            let normal = Vec3::new(angle.cos(), angle.sin(), 0.0);
+            (normal * radius + Vec3::Z * z, normal)
+        });
+
+        let patches = cluster_surfaces(hits, &ClusterSettings::default());
+        assert_eq!(patches.len(), 1);
+        assert!(!patches[0].planar);
+    }
+
+    #[test]
+    fn hull_area() {
+        let square = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(2.0, 2.0),
+            Vec2::new(0.0, 2.0),
+        ];
+        let hull = convex_hull_2d(square);
+        assert_eq!(hull.len(), 4);
+        assert_eq!(polygon_area(&hull), 4.0);
+    }
+}
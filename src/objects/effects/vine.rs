@@ -5,10 +5,13 @@
 ///   or to kinematic/fixed bodies.
 /// - Travel upwards, away from gravity, if the slope is steep
 ///   enough.
-/// - Burnable
-use super::spiral_sphere;
+/// - Burnable, see [`crate::objects::fire`].
+use super::{
+    shape_closest_point, EffectVelocity, PotionEffect, PotionImpact, SamplePattern, SampleRequest,
+    SamplesReady, Sampling,
+};
 use crate::{prelude::*, previous::Previous};
-use bevy::render::primitives::Aabb;
+use bevy::utils::HashSet;
 
 #[derive(Component, Clone)]
 pub struct VineEffect {
@@ -25,6 +28,24 @@ impl Default for VineEffect {
     }
 }
 
+impl PotionEffect for VineEffect {
+    fn spawn(&self, commands: &mut Commands, impact: &PotionImpact) {
+        commands
+            .spawn(SpatialBundle {
+                transform: Transform::from_translation(impact.point),
+                ..default()
+            })
+            .insert(EffectVelocity {
+                linear: -impact.force,
+            })
+            .insert(self.clone());
+    }
+
+    fn on_clients(&self) -> bool {
+        true
+    }
+}
+
 #[derive(Clone)]
 pub struct VineGrowth {
     pub point: Vec3,
@@ -49,6 +70,11 @@ pub struct Vine {
     pub parent: Option<Entity>,
     /// Root vine this vine comes from.
     pub root: Option<Entity>,
+    /// Normal of the surface this vine grows along, `None` if it is dangling.
+    pub surface: Option<Vec3>,
+
+    /// Impulse it takes to tear a dynamic body off this vine.
+    pub joint_strength: f32,
 }
 
 impl Vine {
@@ -71,6 +97,33 @@ impl Vine {
         ]
     }
 
+    /// [`Self::basic_growth_points`] with a side branch every few links,
+    /// alternating sides.
+    pub fn branching_growth_points(&self) -> Vec<VineGrowth> {
+        let mut points = self.basic_growth_points();
+        if self.growth > 1 && self.growth % VINE_BRANCH_EVERY == 0 {
+            let side = if (self.growth / VINE_BRANCH_EVERY) % 2 == 0 {
+                1.0
+            } else {
+                -1.0
+            };
+            points.push(VineGrowth {
+                point: Vec3::new(0.0, self.half_height(), 0.0),
+                direction: Vec3::new(side, 1.0, 0.0).normalize(),
+            });
+        }
+        points
+    }
+
+    /// Split what is left of the growth budget after this link between `branches`.
+    pub fn split_growth(&self, branches: usize) -> Vec<usize> {
+        let remaining = self.growth.saturating_sub(1);
+        let branches = branches.max(1);
+        (0..branches)
+            .map(|index| remaining / branches + usize::from(index < remaining % branches))
+            .collect()
+    }
+
     pub fn collider(&self) -> Collider {
         Collider::cylinder(self.half_height(), self.radius)
     }
@@ -91,6 +144,8 @@ impl Default for Vine {
             height: 0.15,
             parent: None,
             root: None,
+            surface: None,
+            joint_strength: 8.0,
         }
     }
 }
@@ -141,391 +196,521 @@ pub fn vine_despawn(
 
 const DEBUG_TIME: f32 = 1000.0;
 
-/// Spawn the initial vines given a radius, velocity, etc.
-pub fn vine_effect(
-    mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-
-    ctx: Res<RapierContext>,
-    mut potions: Query<(
-        Entity,
-        &VineEffect,
-        &GlobalTransform,
-        //Option<&EffectVelocity>,
-        Option<&Previous<Velocity>>,
-    )>,
-    globals: Query<&GlobalTransform>,
-    colliders: Query<&Collider>,
-    bodies: Query<&RigidBody>,
-    mut gizmos: ResMut<RetainedGizmos>,
-    names: Query<DebugName>,
-) {
-    let material = materials.add(StandardMaterial {
-        base_color: css::DARK_GREEN.into(),
-        perceptual_roughness: 0.2,
-        ..default()
-    });
-
-    let colors = crate::objects::debug_colors(5);
-
-    let dt = ctx.integration_parameters.dt;
-    for (effect_entity, mut vine_effect, global, velocity) in &mut potions {
-        let mut effect_origin = global.translation();
-
-        let mut vine = vine_effect.vine.clone();
-        vine.growth_points = vine.basic_growth_points();
-        let filter = QueryFilter::default().exclude_sensors();
-
-        commands.entity(effect_entity).remove::<VineEffect>();
-        if vine.growth == 0 {
-            continue;
+/// Rays cast to find a surface for a new vine to start on.
+pub const VINE_SEED_SAMPLES: usize = 256;
+/// Most links grown in a single tick across all vines, the rest wait for the next tick.
+pub const VINE_LINKS_PER_TICK: usize = 16;
+/// Surfaces steeper than this, in radians, get climbed upwards.
+pub const VINE_CLIMB_SLOPE: f32 = std::f32::consts::FRAC_PI_4;
+/// How much vines on steep surfaces turn upwards, 1 is straight up.
+pub const VINE_CLIMB_BIAS: f32 = 0.5;
+/// Links between side branches.
+pub const VINE_BRANCH_EVERY: usize = 6;
+
+/// Joint tying a dynamic body to a vine.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct VineJoint;
+
+/// Point `direction` along the surface with `normal`, turning upwards if the
+/// surface is steep enough to climb.
+pub fn along_surface(direction: Vec3, normal: Vec3) -> Vec3 {
+    let up = Vec3::Y.reject_from_normalized(normal).normalize_or_zero();
+    let along = direction.reject_from_normalized(normal).normalize_or_zero();
+
+    let along = if normal.angle_between(Vec3::Y) > VINE_CLIMB_SLOPE {
+        along.lerp(up, VINE_CLIMB_BIAS).normalize_or_zero()
+    } else {
+        along
+    };
+
+    // Growing straight into the surface, pick any way along it.
+    if along == Vec3::ZERO {
+        if up != Vec3::ZERO {
+            up
+        } else {
+            normal.any_orthonormal_vector()
         }
+    } else {
+        along
+    }
+}
 
-        let velocity = velocity
-            .map(|velocity| velocity.0.linvel)
-            .unwrap_or(Vec3::ZERO);
+/// Rotation of a link growing in `direction`, with its local Z along the
+/// surface normal so side branches stay on the surface.
+pub fn link_rotation(direction: Vec3, surface: Option<Vec3>) -> Quat {
+    let Some(normal) = surface else {
+        return Quat::from_rotation_arc(Vec3::Y, direction);
+    };
 
-        //let effect_radius = 3.0;
+    let side = direction.cross(normal).normalize_or_zero();
+    if side == Vec3::ZERO {
+        return Quat::from_rotation_arc(Vec3::Y, direction);
+    }
 
-        gizmos.sphere(
-            DEBUG_TIME,
-            effect_origin,
-            Quat::IDENTITY,
-            //vine_range,
-            0.1,
-            Color::from(css::PURPLE),
-        );
+    Quat::from_mat3(&Mat3::from_cols(side, direction, side.cross(direction)))
+}
 
-        // (CenterRay, Hull Points)
-        let mut hulls: Vec<(RayIntersection, Vec<Vec3>)> = Vec::new();
+/// Where the next link starts, which way it grows and what surface it grows
+/// along, `None` if there is nowhere to grow.
+pub fn next_link(
+    ctx: &RapierContext,
+    filter: QueryFilter,
+    vine: &Vine,
+    start: Vec3,
+    direction: Vec3,
+    surface: Option<Vec3>,
+) -> Option<(Vec3, Vec3, Option<Vec3>)> {
+    // Something in the way, climb onto it.
+    if let Some((_, hit)) = ctx.cast_ray_and_get_normal(start, direction, vine.height, true, filter)
+    {
+        let start = hit.point + hit.normal * vine.radius;
+        return Some((
+            start,
+            along_surface(direction, hit.normal),
+            Some(hit.normal),
+        ));
+    }
 
-        let mut hull_size = |points: &Vec<Vec3>| -> f32 {
-            if points.is_empty() {
-                return 0.0;
-            }
+    let tip = start + direction * vine.height;
+    let down = surface.map_or(-Vec3::Y, |normal| -normal);
+    let reach = vine.radius * 4.0;
+
+    // Keep following the surface.
+    if let Some((_, hit)) = ctx.cast_ray_and_get_normal(tip, down, reach, true, filter) {
+        return Some((
+            start,
+            along_surface(direction, hit.normal),
+            Some(hit.normal),
+        ));
+    }
 
-            let mut min = points[0];
-            let mut max = points[0];
+    // Went past an edge, wrap around it.
+    if surface.is_some() {
+        let below = tip + down * reach;
+        if let Some((_, hit)) =
+            ctx.cast_ray_and_get_normal(below, -direction, vine.height, true, filter)
+        {
+            let start = hit.point + hit.normal * vine.radius;
+            return Some((start, along_surface(down, hit.normal), Some(hit.normal)));
+        }
+    }
 
-            for point in points {
-                min = min.min(*point);
-                max = max.max(*point);
-            }
+    // Nothing to hold on to, only dangling vines can keep going.
+    if surface.is_some() && vine.parent.is_some() {
+        return None;
+    }
+    let direction = (direction - Vec3::Y).normalize_or(-Vec3::Y);
+    Some((start, direction, None))
+}
 
-            let aabb = Aabb::from_min_max(min, max);
-            let transform = Transform {
-                translation: aabb.center.into(),
-                scale: Vec3::from(aabb.half_extents) * 2.0,
+/// Spawn a vine link starting at `start` and growing in `direction`.
+pub fn spawn_link(
+    commands: &mut Commands,
+    mut vine: Vine,
+    start: Vec3,
+    direction: Vec3,
+    surface: Option<Vec3>,
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+) -> Entity {
+    vine.surface = surface;
+    vine.growth_points = vine.branching_growth_points();
+    commands
+        .spawn(PbrBundle {
+            mesh,
+            material,
+            transform: Transform {
+                translation: start + direction * vine.half_height(),
+                rotation: link_rotation(direction, surface),
                 ..default()
-            };
-            //gizmos.cuboid(DEBUG_TIME, transform, Color::GREEN);
+            },
+            ..default()
+        })
+        .insert((
+            Name::new("Vine"),
+            crate::objects::fire::Flammable::vine(),
+            RigidBody::Fixed,
+            ColliderBundle::collider(vine.collider()),
+            vine,
+        ))
+        .id()
+}
 
-            let dist = max.distance(min);
-            dist
-        };
+/// Where a vine effect looks for a surface to start on, waiting for its
+/// [`SamplesReady`].
+#[derive(Component, Debug, Clone, Copy)]
+pub struct VineSeed {
+    pub origin: Vec3,
+    pub velocity: Vec3,
+}
 
-        let mut add_ray = |ray: RayIntersection| {
-            let mut new_points = Vec::new();
-            let pushed_point = ray.point + ray.normal * vine.radius;
-            new_points.push(ray.point);
-            new_points.push(pushed_point);
-
-            let mut accounted = false;
-            for (center_ray, ref mut points) in &mut hulls {
-                let plane_normal = center_ray.normal;
-                let center_dot = center_ray.normal.dot(center_ray.point);
-
-                // check if it is inside the plane + radius
-                let ray_dot = ray.point.dot(plane_normal);
-                let diff = ray_dot - center_dot;
-                let plane_fudge = vine.radius;
-                if ray.normal.dot(plane_normal) < 0.9 {
-                    continue;
-                    let close = points.iter().any(|point| point.distance(ray.point) < 0.25);
-                    if !close {
-                        continue;
-                    }
-                }
-
-                if diff > -plane_fudge && diff < plane_fudge {
-                    points.extend(new_points.clone());
-                    let center_push = pushed_point + plane_normal * vine.radius;
-                    points.push(center_push);
-
-                    accounted = true;
-                }
-            }
+/// Sample the surroundings of new vine effects, [`vine_seed`] starts the vine
+/// once the hits are in.
+pub fn vine_effect(
+    mut commands: Commands,
+    ctx: Res<RapierContext>,
+    mut sampling: ResMut<Sampling>,
+    potions: Query<
+        (
+            Entity,
+            &VineEffect,
+            &GlobalTransform,
+            //Option<&EffectVelocity>,
+            Option<&Previous<Velocity>>,
+        ),
+        Without<VineSeed>,
+    >,
+    vines: Query<Entity, With<Vine>>,
+    mut gizmos: ResMut<RetainedGizmos>,
+) {
+    for (effect_entity, vine_effect, global, velocity) in &potions {
+        if vine_effect.vine.growth == 0 {
+            commands.entity(effect_entity).remove::<VineEffect>();
+            continue;
+        }
 
-            if !accounted {
-                hulls.push((ray, new_points.clone()));
-            }
-        };
+        let predicate = |entity: Entity| !vines.contains(entity);
+        let filter = QueryFilter::default()
+            .exclude_sensors()
+            .exclude_dynamic()
+            .predicate(&predicate);
 
         let push_radius = vine_effect.explode_radius / 2.0;
-        gizmos.sphere(
-            DEBUG_TIME,
-            effect_origin,
-            Quat::IDENTITY,
-            push_radius,
-            Color::from(css::LIGHT_BLUE),
-        );
-        effect_origin = ctx.correct_penetration(
-            effect_origin,
+        let origin = ctx.correct_penetration(
+            global.translation(),
             Quat::IDENTITY,
             &Collider::ball(push_radius),
             &filter,
         );
-
-        let sphere_bottom = effect_origin - Vec3::new(0.0, vine_effect.explode_radius, 0.0);
-        //gizmos.ray(DEBUG_TIME, global.translation(), -average_normal, Color::BLUE);
-
         gizmos.sphere(
             DEBUG_TIME,
-            effect_origin,
+            origin,
             Quat::IDENTITY,
-            0.08,
-            Color::from(css::GREEN),
+            0.1,
+            Color::from(css::PURPLE),
         );
-        gizmos.sphere(
-            DEBUG_TIME,
-            effect_origin,
-            Quat::IDENTITY,
-            push_radius,
-            css::GREEN.into(),
+
+        sampling.request(
+            SampleRequest::new(effect_entity, origin, SamplePattern::SpiralSphere)
+                .with_radius(vine_effect.explode_radius)
+                .with_samples(VINE_SEED_SAMPLES)
+                .with_flags(QueryFilterFlags::EXCLUDE_SENSORS | QueryFilterFlags::EXCLUDE_DYNAMIC),
         );
+        commands.entity(effect_entity).insert(VineSeed {
+            origin,
+            velocity: velocity
+                .map(|velocity| velocity.0.linvel)
+                .unwrap_or(Vec3::ZERO),
+        });
+    }
+}
 
-        for sample in spiral_sphere(50_000) {
-            //let sample = sample.extend(0.0);
-            //let sample = sample * vine_effect.explode_radius;
-            //let origin = global.translation() + sample * vine_effect.explode_radius;
-            //gizmos.sphere(DEBUG_TIME, origin, Quat::IDENTITY, 0.1, Color::RED);
+/// Start a vine on the closest surface the effect's samples hit, the vine grows
+/// from there in [`vine_growth`].
+pub fn vine_seed(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    ctx: Res<RapierContext>,
+    mut samples: EventReader<SamplesReady>,
+    seeds: Query<(&VineEffect, &VineSeed)>,
+    globals: Query<&GlobalTransform>,
+    colliders: Query<&Collider>,
+    bodies: Query<&RigidBody>,
+    vines: Query<Entity, With<Vine>>,
+) {
+    for samples in samples.read() {
+        let Ok((vine_effect, seed)) = seeds.get(samples.requester) else {
+            continue;
+        };
+        commands
+            .entity(samples.requester)
+            .remove::<(VineEffect, VineSeed)>();
+
+        let vine = vine_effect.vine.clone();
+        let closest = samples
+            .hits
+            .iter()
+            .filter(|(entity, _)| !vines.contains(*entity))
+            .min_by(|(_, a), (_, b)| a.time_of_impact.total_cmp(&b.time_of_impact));
+
+        let Some((_, surface)) = closest else {
+            // Nothing to grow on, tie whatever is around together instead.
+            let ties = tie_bodies(
+                &ctx,
+                seed.origin,
+                vine_effect.explode_radius,
+                &[],
+                &bodies,
+                &globals,
+                &colliders,
+            );
+            for (body, joint) in ties {
+                spawn_vine_joint(&mut commands, &vine, body, joint);
+            }
+            continue;
+        };
 
-            let color = colors[effect_entity.index() as usize % (colors.len() - 1)];
+        // Keep going the way the potion was thrown.
+        let start = surface.point + surface.normal * vine.radius;
+        spawn_link(
+            &mut commands,
+            vine.clone(),
+            start,
+            along_surface(seed.velocity, surface.normal),
+            Some(surface.normal),
+            meshes.add(Cylinder::new(vine.radius, vine.height)),
+            materials.add(StandardMaterial {
+                base_color: css::DARK_GREEN.into(),
+                perceptual_roughness: 0.2,
+                ..default()
+            }),
+        );
+    }
+}
 
-            if let Some((entity, ray)) = ctx.cast_ray_and_get_normal(
-                effect_origin,
-                sample,
-                vine_effect.explode_radius,
-                true,
-                filter,
-            ) {
-                if ray.time_of_impact == 0.0 {
-                    continue;
-                }
+fn spawn_vine_joint(commands: &mut Commands, vine: &Vine, body: Entity, joint: ImpulseJoint) {
+    commands.entity(body).with_children(|children| {
+        // Placed on the body, so effects can find the joint in space.
+        children
+            .spawn((joint, TransformBundle::default()))
+            .insert(BreakableJoint {
+                impulse: Vec3::splat(vine.joint_strength),
+                torque: Vec3::splat(f32::INFINITY),
+            })
+            .insert((VineJoint, Name::new("Vine Joint")));
+    });
+}
 
-                let body = bodies.get(entity).copied().unwrap_or(RigidBody::Fixed);
-                if body == RigidBody::Dynamic {
+/// Joint dynamic bodies within `radius` of `origin` to the closest of the
+/// `anchors` in reach, or to the closest other dynamic body if there are none.
+///
+/// `anchors` are world space points on vines. Returns the joints to add to
+/// each body.
+pub fn tie_bodies(
+    ctx: &RapierContext,
+    origin: Vec3,
+    radius: f32,
+    anchors: &[(Entity, Vec3)],
+    bodies: &Query<&RigidBody>,
+    globals: &Query<&GlobalTransform>,
+    colliders: &Query<&Collider>,
+) -> Vec<(Entity, ImpulseJoint)> {
+    let mut dynamic = Vec::new();
+    ctx.intersections_with_shape(
+        origin,
+        Quat::IDENTITY,
+        &Collider::ball(radius),
+        QueryFilter::default().exclude_sensors(),
+        |collider| {
+            let body = ctx.collider_parent(collider).unwrap_or(collider);
+            if matches!(bodies.get(body), Ok(RigidBody::Dynamic)) && !dynamic.contains(&body) {
+                dynamic.push(body);
+            }
+            true
+        },
+    );
+
+    let translation = |entity: Entity| {
+        globals
+            .get(entity)
+            .map(|global| global.translation())
+            .unwrap_or_default()
+    };
+
+    // Closest point on any of the body's colliders.
+    let closest_point = |body: Entity, point: Vec3| {
+        ctx.colliders(body)
+            .into_iter()
+            .filter_map(|collider| {
+                Some((globals.get(collider).ok()?, colliders.get(collider).ok()?))
+            })
+            .map(|(global, collider)| shape_closest_point(global, collider, point))
+            .min_by(|a, b| {
+                a.distance_squared(point)
+                    .total_cmp(&b.distance_squared(point))
+            })
+            .unwrap_or(point)
+    };
+
+    let mut tied = Vec::new();
+    let mut ties = Vec::new();
+    for &body in &dynamic {
+        let center = translation(body);
+        let anchor = anchors
+            .iter()
+            .map(|(anchor, point)| {
+                (
+                    *anchor,
+                    *point,
+                    closest_point(body, *point).distance(*point),
+                )
+            })
+            .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+            .filter(|(_, _, distance)| *distance <= radius);
+
+        let (other, target) = match anchor {
+            Some((anchor, point, _)) => (anchor, point),
+            None if anchors.is_empty() => {
+                let Some(other) = dynamic
+                    .iter()
+                    .copied()
+                    .filter(|other| *other != body && !tied.contains(&(*other, body)))
+                    .min_by(|a, b| {
+                        let a = translation(*a).distance_squared(center);
+                        let b = translation(*b).distance_squared(center);
+                        a.total_cmp(&b)
+                    })
+                else {
                     continue;
-                }
-
-                //gizmos.sphere(DEBUG_TIME, ray.point, Quat::IDENTITY, 0.05, Color::CYAN);
-                //gizmos.sphere(DEBUG_TIME, ray.point, Quat::IDENTITY, 0.05, color);
-                add_ray(ray);
-            } else {
-                let traveled_point = effect_origin + sample * vine_effect.explode_radius;
-                let y_diff = traveled_point.y - sphere_bottom.y;
-
-                if let Some((entity, ray)) = ctx.cast_ray_and_get_normal(
-                    traveled_point.into(),
-                    (-Vec3::Y).into(),
-                    y_diff.into(),
-                    true,
-                    QueryFilter::default().exclude_sensors(),
-                ) {
-                    if ray.time_of_impact == 0.0 {
-                        continue;
-                    }
-
-                    //gizmos.sphere(DEBUG_TIME, ray.point, Quat::IDENTITY, 0.05, Color::RED);
-                    //add_ray(ray);
-                }
+                };
+                (other, translation(other))
             }
-        }
+            None => continue,
+        };
+        tied.push((body, other));
+
+        // Tie them together where they are now, instead of pulling them in.
+        let point = closest_point(body, target);
+        let local_anchor = |entity: Entity| {
+            globals
+                .get(entity)
+                .map(|global| {
+                    let (_, rotation, translation) = global.to_scale_rotation_translation();
+                    rotation.inverse() * (point - translation)
+                })
+                .unwrap_or(point)
+        };
+        let joint = SphericalJointBuilder::new()
+            .local_anchor1(local_anchor(other))
+            .local_anchor2(local_anchor(body));
 
-        // cull hulls that are too small
-        hulls.retain(|(center_ray, points)| hull_size(points) >= 1.5);
-
-        info!("hulls: {:?}", hulls.len());
-        for (center_ray, points) in hulls {
-            if points.len() >= 2 {
-                if let Some(collider) = Collider::convex_hull(&points) {
-                    commands.spawn((
-                        SpatialBundle {
-                            transform: Transform {
-                                translation: Vec3::ZERO, //ray.point + vine_offset + ray.normal * vine.radius,
-                                //rotation: rotation,
-                                ..default()
-                            },
-                            ..default()
-                        },
-                        Name::new("Vine"),
-                        vine.clone(),
-                        material.clone(),
-                        RigidBody::Fixed,
-                        //Sensor,
-                        //ColliderDebugColor(color),
-                        ColliderBundle::collider(collider),
-                    ));
-                }
-            }
-        }
+        ties.push((body, ImpulseJoint::new(other, joint)));
     }
+
+    ties
 }
 
+/// Grow a link from every growth point of the vines, a few links per tick,
+/// and tie dynamic bodies the vines reach.
 pub fn vine_growth(
     mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-
     ctx: Res<RapierContext>,
-    vines: Query<(Entity, &mut Vine, &GlobalTransform)>,
+    mut vines: Query<(
+        Entity,
+        &mut Vine,
+        &Transform,
+        &Handle<Mesh>,
+        &Handle<StandardMaterial>,
+    )>,
+    links: Query<Entity, With<Vine>>,
+    // Broken joints lose their `ImpulseJoint`, those bodies can be tied again.
+    vine_joints: Query<&Parent, (With<VineJoint>, With<ImpulseJoint>)>,
     globals: Query<&GlobalTransform>,
     colliders: Query<&Collider>,
-    mut gizmos: ResMut<RetainedGizmos>,
+    bodies: Query<&RigidBody>,
 ) {
-    // cylinder raycast + angular velocity method:
-    /*
-    for (entity, mut vine, global) in &mut vines {
-        for growth in vine.growth_points.clone() {
-            if vine.growth == 0 {
-                break;
+    let predicate = |entity: Entity| !links.contains(entity);
+    let filter = QueryFilter::default()
+        .exclude_sensors()
+        .exclude_dynamic()
+        .predicate(&predicate);
+
+    let mut tied = vine_joints
+        .iter()
+        .map(|parent| parent.get())
+        .collect::<HashSet<_>>();
+
+    let mut budget = VINE_LINKS_PER_TICK;
+    for (link, mut vine, transform, mesh, material) in &mut vines {
+        if vine.growth_points.is_empty() {
+            continue;
+        }
+        if budget < vine.growth_points.len() {
+            break;
+        }
+
+        // Links aren't parented, and the global transform of one spawned last
+        // tick isn't propagated yet.
+        let (rotation, translation) = (transform.rotation, transform.translation);
+        let growth_points = std::mem::take(&mut vine.growth_points);
+        let budgets = vine.split_growth(growth_points.len());
+        for (growth, budget_left) in growth_points.iter().zip(budgets) {
+            if budget_left == 0 {
+                continue;
             }
 
-            let vine = vine.grow();
-            let transform = global.compute_transform();
-            let global_direction = transform.rotation * growth.direction;
-            let global_growth_point = global.transform_point(growth.point);
-            let collider = vine.collider();
-
-            gizmos.ray(
-                DEBUG_TIME,
-                global_growth_point,
-                global_direction * vine.height,
-                Color::PURPLE,
-            );
-            // Cast ray in direction to check for obstacles
-            if let Some((entity, ray)) = ctx.cast_ray_and_get_normal(
-                global_growth_point,
-                global_direction,
-                vine.height,
-                true,
-                QueryFilter::default().exclude_sensors(),
-            ) {
-                let normal = ray.normal;
-                let (x, z) = normal.any_orthonormal_pair();
-                let current = transform.rotation * Vec3::Y;
-                let current_x = current.project_onto_normalized(x);
-                let current_z = current.project_onto_normalized(z);
-                let projected = (current_x + current_z).normalize_or_zero();
-
-                let axis = current.cross(projected);
-                let angle = current.angle_between(projected);
-                let rot_delta = Quat::from_axis_angle(axis, angle);
-                let rotation = transform.rotation * rot_delta;
-
-                let vine_offset = rotation * (Vec3::Y * vine.half_height());
-
-                //let color = colors[vine_effect.vine.growth % colors.len()];
-                /*
-                commands.spawn((
-                    SpatialBundle {
-                        transform: Transform {
-                            translation: ray.point + vine_offset + ray.normal * vine.radius,
-                            rotation: rotation,
-                            ..default()
-                        },
-                        ..default()
-                    },
-                    Name::new("Vine"),
-                    vine.clone(),
-                    //RigidBody::Fixed,
-                    //Sensor,
-                    //ColliderDebugColor(color),
-                    //ColliderBundle::collider(vine.collider()),
-                ));
-                */
-            } else {
-                // Cast shape with angular velocity towards gravity
-                let adjusted_global = Transform {
-                    translation: global_growth_point + global_direction * vine.half_height(),
-                    rotation: transform.rotation,
-                    ..default()
-                };
-                let local_center = Vec3::new(0.0, -vine.half_height(), 0.0);
-                let global_center = adjusted_global.transform_point(local_center);
-                gizmos.sphere(DEBUG_TIME, global_center, Quat::IDENTITY, 0.02, Color::CYAN);
-
-                let current = transform.rotation * Vec3::Y;
-                let dangling = -Vec3::Y;
-                let axis = current.cross(dangling).normalize_or_zero();
-                let angle = current.angle_between(dangling);
-
-                gizmos.ray(DEBUG_TIME, global_center, axis * 0.5, Color::GREEN);
-                gizmos.ray(DEBUG_TIME, global_center, -axis * 0.5, Color::GREEN);
-
-                let time = if let Some((collider, toi)) = ctx.query_pipeline.nonlinear_cast_shape(
-                    &ctx.bodies,
-                    &ctx.colliders,
-                    &NonlinearRigidMotion {
-                        start: Isometry {
-                            translation: adjusted_global.translation.into(),
-                            rotation: adjusted_global.rotation.into(),
-                        },
-                        local_center: local_center.into(),
-                        linvel: Vec3::ZERO.into(),
-                        angvel: axis.into(),
-                    },
-                    collider.raw.as_ref(),
-                    0.0,
-                    angle,
-                    true,
-                    bevy_rapier3d::rapier::pipeline::QueryFilter::default().exclude_sensors(),
-                ) {
-                    toi.toi
-                } else {
-                    angle
-                };
+            let start = translation + rotation * growth.point;
+            let direction = (rotation * growth.direction).normalize_or_zero();
+            let Some((start, direction, surface)) =
+                next_link(&ctx, filter, &vine, start, direction, vine.surface)
+            else {
+                continue;
+            };
 
-                let local_translation = adjusted_global.translation - global_center;
-                let rotation = Quat::from_axis_angle(axis, time);
-                let rotated_translation = global_center + rotation * local_translation;
-                let rotated_rotation = rotation * adjusted_global.rotation;
-                //let rotated_translation = adjusted_global.translation + axis.cross()
-                //gizmos.ray(DEBUG_TIME, rotated_translation, rotated_rotation * Vec3::Y * vine.half_height(), Color::PURPLE);
-                commands.spawn((
-                    SpatialBundle {
-                        transform: Transform {
-                            translation: rotated_translation,
-                            rotation: rotated_rotation,
-                            ..default()
-                        },
-                        ..default()
-                    },
-                    Name::new("Vine"),
-                    ColliderBundle::collider(collider),
-                    //RigidBody::Fixed,
-                    Sensor,
-                    //ColliderDebugColor(color)),
-                    vine,
-                ));
+            let next = Vine {
+                growth: budget_left,
+                parent: Some(link),
+                root: vine.root.or(Some(link)),
+                ..vine.clone()
             };
+            spawn_link(
+                &mut commands,
+                next,
+                start,
+                direction,
+                surface,
+                mesh.clone(),
+                material.clone(),
+            );
+            budget -= 1;
+        }
+
+        let ties = tie_bodies(
+            &ctx,
+            translation,
+            vine.height,
+            &[(link, translation)],
+            &bodies,
+            &globals,
+            &colliders,
+        );
+        for (body, joint) in ties {
+            if tied.insert(body) {
+                spawn_vine_joint(&mut commands, &vine, body, joint);
+            }
+        }
+    }
+}
 
-            // Cast around the end of the
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            /*
-            let minimum = global_growth_point - Vec3::splat(vine.height);
-            let maximum = global_growth_point + Vec3::splat(vine.height);
-            let aabb = Aabb::from_min_max(minimum, maximum);
+    #[test]
+    fn climbs_steep_surfaces() {
+        // Flat ground keeps the direction.
+        let along = along_surface(Vec3::X, Vec3::Y);
+        assert!(along.distance(Vec3::X) < 1e-5);
 
-            ctx.move_shape();
-            */
-            /*crate::physics::contact_manifolds(&*ctx, global_growth_point)
-            ctx.colliders_with_aabb_intersecting_aabb(aabb, |entity| -> bool {
-                let collider = colliders.get(entity).unwrap();
-                true
-            });*/
-        }
+        // Walls get climbed.
+        let along = along_surface(Vec3::Z, Vec3::X);
+        assert!(along.y > 0.0);
+        assert!(along.x.abs() < 1e-5);
+
+        // Head on into a wall goes straight up.
+        let along = along_surface(-Vec3::X, Vec3::X);
+        assert!(along.distance(Vec3::Y) < 1e-5);
+    }
 
-        vine.growth_points = Vec::new();
+    #[test]
+    fn splits_growth_between_branches() {
+        let vine = Vine {
+            growth: 8,
+            ..default()
+        };
+        assert_eq!(vine.split_growth(1), vec![7]);
+        assert_eq!(vine.split_growth(2), vec![4, 3]);
+        assert_eq!(vine.split_growth(0), vec![7]);
     }
-    */
 }
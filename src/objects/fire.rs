@@ -0,0 +1,305 @@
+//! Fire, see `design/brewing.md`.
+//!
+//! A [`Flammable`] that gets hotter than its ignition temperature starts
+//! [`Burning`]: it gives off heat and light, heats up flammables it touches
+//! and shrinks away until nothing is left of it. Anything jointed to it comes
+//! loose once it's gone.
+use crate::objects::{
+    effects::{PotionEffect, PotionImpact, PotionKind, RegisterPotionEffect},
+    heat::{radiate_heat, HeatSource, Temperature},
+};
+use crate::prelude::*;
+
+/// Heat per second given to flammables touching something burning.
+pub const CONTACT_HEAT: f32 = 60.0;
+/// Heat per second given off by something burning.
+pub const BURN_POWER: f32 = 10.0;
+/// How far the heat of something burning reaches.
+pub const BURN_RADIUS: f32 = 1.5;
+
+pub struct FirePlugin;
+
+impl Plugin for FirePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Flammable>().register_type::<Burning>();
+        app.register_potion_effect(PotionKind::FIRE, FireEffect::default());
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                (insert_flammable_temperature, fire_effect),
+                ignite,
+                spread_fire,
+                burn,
+            )
+                .chain()
+                .in_set(GameplaySet)
+                .after(radiate_heat)
+                .run_if(not(resource_exists::<NetworkClient>)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Flammable {
+    /// Degrees it catches fire at.
+    pub ignition: f32,
+    /// Seconds it takes to burn away.
+    pub burn_time: f32,
+}
+
+impl Default for Flammable {
+    fn default() -> Self {
+        Self {
+            ignition: 150.0,
+            burn_time: 6.0,
+        }
+    }
+}
+
+impl Flammable {
+    /// Vines are dry and thin, they go up quickly.
+    pub fn vine() -> Self {
+        Self {
+            ignition: 100.0,
+            burn_time: 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Burning {
+    /// Seconds left until it burnt away.
+    pub seconds: f32,
+    pub burn_time: f32,
+    /// Scale it had when it caught fire, it shrinks from there.
+    pub scale: Vec3,
+    /// Local point it shrinks towards.
+    pub pivot: Vec3,
+}
+
+impl Burning {
+    /// How much of it is left, 1 when it just caught fire.
+    pub fn left(&self) -> f32 {
+        if self.burn_time <= 0.0 {
+            return 0.0;
+        }
+
+        (self.seconds / self.burn_time).clamp(0.0, 1.0)
+    }
+
+    /// `transform` shrunk down to [`Self::left`] of its size, keeping the pivot in place.
+    pub fn shrink(&self, transform: &Transform) -> Transform {
+        let scale = self.scale * self.left().max(0.01);
+        let pivot = transform.transform_point(self.pivot);
+        Transform {
+            translation: pivot - transform.rotation * (scale * self.pivot),
+            scale,
+            ..*transform
+        }
+    }
+}
+
+/// Sets whatever flammable is around the potion on fire.
+#[derive(Component, Clone)]
+pub struct FireEffect {
+    pub radius: f32,
+    /// Degrees flammables right at the center are heated by.
+    pub heat: f32,
+}
+
+impl Default for FireEffect {
+    fn default() -> Self {
+        Self {
+            radius: 2.0,
+            heat: 200.0,
+        }
+    }
+}
+
+impl PotionEffect for FireEffect {
+    fn spawn(&self, commands: &mut Commands, impact: &PotionImpact) {
+        commands
+            .spawn(SpatialBundle {
+                transform: Transform::from_translation(impact.point),
+                ..default()
+            })
+            .insert(Name::new("Fire"))
+            .insert(Self {
+                heat: self.heat * impact.strength,
+                ..self.clone()
+            });
+    }
+}
+
+pub fn insert_flammable_temperature(
+    mut commands: Commands,
+    flammables: Query<Entity, (With<Flammable>, Without<Temperature>)>,
+) {
+    for entity in &flammables {
+        commands
+            .entity(entity)
+            .insert(Temperature::with_thermal_mass(0.5));
+    }
+}
+
+pub fn fire_effect(
+    mut commands: Commands,
+    effects: Query<(Entity, &GlobalTransform, &FireEffect)>,
+    mut flammables: Query<(&GlobalTransform, &mut Temperature), With<Flammable>>,
+) {
+    for (effect_entity, effect_global, effect) in &effects {
+        commands.entity(effect_entity).despawn_recursive();
+
+        let center = effect_global.translation();
+        for (global, mut temperature) in &mut flammables {
+            let distance = global.translation().distance(center);
+            if distance > effect.radius {
+                continue;
+            }
+
+            temperature.degrees += effect.heat * (1.0 - distance / effect.radius);
+        }
+    }
+}
+
+pub fn ignite(
+    mut commands: Commands,
+    flammables: Query<
+        (
+            Entity,
+            &Flammable,
+            &Temperature,
+            &Transform,
+            Option<&Collider>,
+        ),
+        Without<Burning>,
+    >,
+) {
+    for (entity, flammable, temperature, transform, collider) in &flammables {
+        if temperature.degrees < flammable.ignition {
+            continue;
+        }
+
+        // Shrink towards the middle of the collider, which isn't always the origin.
+        let pivot = collider
+            .map(|collider| Vec3::from(collider.raw.compute_local_aabb().center()))
+            .unwrap_or(Vec3::ZERO);
+
+        info!("{:?} caught fire", entity);
+        commands
+            .entity(entity)
+            .insert((
+                Burning {
+                    seconds: flammable.burn_time,
+                    burn_time: flammable.burn_time,
+                    scale: transform.scale,
+                    pivot,
+                },
+                HeatSource {
+                    power: BURN_POWER,
+                    radius: BURN_RADIUS,
+                },
+            ))
+            .with_children(|builder| {
+                builder.spawn(PointLightBundle {
+                    point_light: PointLight {
+                        color: css::ORANGE.into(),
+                        intensity: 20_000.0,
+                        range: 3.0,
+                        ..default()
+                    },
+                    transform: Transform::from_translation(pivot),
+                    ..default()
+                });
+            });
+    }
+}
+
+/// Heat up flammables touching something burning.
+pub fn spread_fire(
+    ctx: Res<RapierContext>,
+    burning: Query<(Entity, &GlobalTransform, &Collider), With<Burning>>,
+    flammables: Query<Entity, (With<Flammable>, Without<Burning>)>,
+    mut temperatures: Query<&mut Temperature>,
+    parents: Query<&Parent>,
+    joints: Query<&ImpulseJoint>,
+) {
+    let dt = crate::TICK_RATE.as_secs_f32();
+    let filter = QueryFilter::default().exclude_sensors();
+    for (entity, global, collider) in &burning {
+        let (_, rotation, translation) = global.to_scale_rotation_translation();
+        for (contact, manifold) in ctx.contact_manifolds(translation, rotation, collider, &filter) {
+            if contact == entity || manifold.points.is_empty() {
+                continue;
+            }
+
+            let Some(flammable) = find_parent_with(&flammables, &parents, &joints, contact) else {
+                continue;
+            };
+            let Ok(mut temperature) = temperatures.get_mut(flammable) else {
+                continue;
+            };
+
+            temperature.degrees += CONTACT_HEAT * dt / temperature.thermal_mass.max(f32::EPSILON);
+        }
+    }
+}
+
+/// Shrink burning things and get rid of them once they burnt away.
+pub fn burn(
+    mut commands: Commands,
+    mut burning: Query<(Entity, &mut Burning, &mut Transform)>,
+    jointed: Query<(Entity, &ImpulseJoint, Has<RigidBody>)>,
+) {
+    let dt = crate::TICK_RATE.as_secs_f32();
+    for (entity, mut fire, mut transform) in &mut burning {
+        fire.seconds -= dt;
+        if fire.seconds > 0.0 {
+            *transform = fire.shrink(&transform);
+            continue;
+        }
+
+        for (other, joint, body) in &jointed {
+            if joint.parent != entity {
+                continue;
+            }
+
+            // Joints like vine and grab joints get an entity of their own,
+            // don't leave it behind empty.
+            if body {
+                commands.entity(other).remove::<ImpulseJoint>();
+            } else {
+                commands.entity(other).despawn_recursive();
+            }
+        }
+
+        info!("{:?} burnt away", entity);
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrinks_towards_pivot() {
+        let mut burning = Burning {
+            seconds: 2.0,
+            burn_time: 2.0,
+            scale: Vec3::ONE,
+            pivot: Vec3::new(0.0, 2.0, 0.0),
+        };
+        let transform = Transform::from_xyz(1.0, 0.0, 0.0);
+        let pivot = transform.transform_point(burning.pivot);
+        assert_eq!(burning.shrink(&transform), transform);
+
+        burning.seconds = 1.0;
+        let shrunk = burning.shrink(&transform);
+        assert_eq!(shrunk.scale, Vec3::splat(0.5));
+        assert!(shrunk.transform_point(burning.pivot).distance(pivot) < 1e-5);
+    }
+}
@@ -0,0 +1,292 @@
+//! Heat and temperature, see `design/brewing.md`.
+//!
+//! [`HeatSource`]s warm up anything with a [`Temperature`] within their radius,
+//! which cools back down towards [`AMBIENT_TEMPERATURE`] on its own. How fast
+//! either happens depends on [`Temperature::thermal_mass`].
+use serde::{Deserialize, Serialize};
+
+use crate::objects::{
+    cauldron::Ingredient,
+    ingredient::{Brew, Element, IngredientProperties},
+    potion::Potion,
+};
+use crate::physics::{slot::Slot, ColliderBundle, RigidBodyBundle};
+use crate::prelude::*;
+
+/// Degrees everything cools down to.
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+/// Heat lost per second per degree above ambient.
+pub const COOLING_RATE: f32 = 0.1;
+/// How fast slotted items take on the temperature of their deposit, per second.
+pub const CONDUCTION_RATE: f32 = 0.5;
+/// Ingredients start to spoil above this.
+pub const SPOIL_TEMPERATURE: f32 = 40.0;
+/// Freshness lost per second per degree above [`SPOIL_TEMPERATURE`].
+pub const SPOIL_RATE: f32 = 0.0005;
+/// Heat a full strength fire potion gives off.
+pub const FIRE_POTION_POWER: f32 = 4.0;
+
+pub struct HeatPlugin;
+
+impl Plugin for HeatPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<HeatSource>()
+            .register_type::<Temperature>()
+            .register_type::<Boiling>();
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                (insert_ingredient_temperature, fire_potion_heat),
+                radiate_heat,
+                conduct_to_slotted,
+                spoil_ingredients,
+            )
+                .chain()
+                .in_set(GameplaySet)
+                .run_if(not(resource_exists::<NetworkClient>)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct HeatSource {
+    /// Heat per second given to something right at the source.
+    pub power: f32,
+    /// Nothing further away than this is warmed up.
+    pub radius: f32,
+}
+
+impl HeatSource {
+    /// Heat per second at `distance`, falling off quadratically.
+    pub fn heat_at(&self, distance: f32) -> f32 {
+        if self.radius <= 0.0 {
+            return 0.0;
+        }
+
+        let falloff = (1.0 - distance / self.radius).clamp(0.0, 1.0);
+        self.power * falloff * falloff
+    }
+}
+
+#[derive(Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Temperature {
+    /// Degrees celsius.
+    pub degrees: f32,
+    /// Heat it takes to warm up by a degree.
+    pub thermal_mass: f32,
+}
+
+impl Default for Temperature {
+    fn default() -> Self {
+        Self {
+            degrees: AMBIENT_TEMPERATURE,
+            thermal_mass: 1.0,
+        }
+    }
+}
+
+impl Temperature {
+    pub fn with_thermal_mass(thermal_mass: f32) -> Self {
+        Self {
+            thermal_mass,
+            ..default()
+        }
+    }
+
+    /// Take in `heat` per second for `dt` seconds while losing some to the surroundings.
+    pub fn update(&mut self, heat: f32, dt: f32) {
+        let loss = (self.degrees - AMBIENT_TEMPERATURE) * COOLING_RATE;
+        self.degrees += (heat - loss) * dt / self.thermal_mass.max(f32::EPSILON);
+    }
+}
+
+/// "Boil for `seconds` above `above` degrees", as recipes ask for it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeatCondition {
+    pub above: f32,
+    pub seconds: f32,
+}
+
+/// How long a cauldron has been kept hot enough for the recipe it is brewing.
+#[derive(Default, Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Boiling {
+    pub seconds: f32,
+}
+
+impl Boiling {
+    /// Count up while `temperature` meets `condition`, starting over when it doesn't.
+    pub fn update(&mut self, temperature: f32, condition: &HeatCondition, dt: f32) -> bool {
+        if temperature >= condition.above {
+            self.seconds += dt;
+        } else {
+            self.seconds = 0.0;
+        }
+
+        self.seconds >= condition.seconds
+    }
+}
+
+pub fn insert_ingredient_temperature(
+    mut commands: Commands,
+    ingredients: Query<Entity, (With<Ingredient>, Without<Temperature>)>,
+) {
+    for entity in &ingredients {
+        commands
+            .entity(entity)
+            .insert(Temperature::with_thermal_mass(0.5));
+    }
+}
+
+/// Fire potions keep their surroundings warm.
+pub fn fire_potion_heat(
+    mut commands: Commands,
+    potions: Query<(Entity, &Brew), (With<Potion>, Without<HeatSource>)>,
+) {
+    for (entity, brew) in &potions {
+        if brew.element != Some(Element::Fire) {
+            continue;
+        }
+
+        commands.entity(entity).insert(HeatSource {
+            power: FIRE_POTION_POWER * brew.strength,
+            radius: 1.5,
+        });
+    }
+}
+
+pub fn radiate_heat(
+    sources: Query<(Entity, &GlobalTransform, &HeatSource)>,
+    mut heated: Query<(Entity, &GlobalTransform, &mut Temperature)>,
+) {
+    let dt = crate::TICK_RATE.as_secs_f32();
+    for (entity, global, mut temperature) in &mut heated {
+        let heat = sources
+            .iter()
+            .filter(|(source_entity, _, _)| *source_entity != entity)
+            .map(|(_, source_global, source)| {
+                source.heat_at(source_global.translation().distance(global.translation()))
+            })
+            .sum::<f32>();
+        temperature.update(heat, dt);
+    }
+}
+
+/// Slotted items take on the temperature of whatever they are slotted in.
+pub fn conduct_to_slotted(
+    deposits: Query<(&SlotDeposit, &Temperature)>,
+    slots: Query<&Slot>,
+    mut temperatures: Query<&mut Temperature, Without<SlotDeposit>>,
+) {
+    let dt = crate::TICK_RATE.as_secs_f32();
+    for (deposit, deposit_temperature) in &deposits {
+        for slot in &deposit.slots {
+            let Some(item) = slots.get(*slot).ok().and_then(|slot| slot.containing) else {
+                continue;
+            };
+            let Ok(mut temperature) = temperatures.get_mut(item) else {
+                continue;
+            };
+
+            let difference = deposit_temperature.degrees - temperature.degrees;
+            temperature.degrees += difference * (CONDUCTION_RATE * dt).min(1.0);
+        }
+    }
+}
+
+/// Warm ingredients lose freshness.
+pub fn spoil_ingredients(mut ingredients: Query<(&Temperature, &mut IngredientProperties)>) {
+    let dt = crate::TICK_RATE.as_secs_f32();
+    for (temperature, mut properties) in &mut ingredients {
+        let excess = temperature.degrees - SPOIL_TEMPERATURE;
+        if excess <= 0.0 || properties.freshness <= 0.0 {
+            continue;
+        }
+
+        properties.freshness = (properties.freshness - excess * SPOIL_RATE * dt).max(0.0);
+    }
+}
+
+pub fn spawn_campfire(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    position: Transform,
+) -> Entity {
+    commands
+        .spawn(PbrBundle {
+            mesh: meshes.add(Cylinder::new(0.5, 0.2)),
+            material: materials.add(StandardMaterial {
+                base_color: css::ORANGE_RED.into(),
+                emissive: LinearRgba::rgb(4.0, 1.2, 0.2),
+                ..default()
+            }),
+            transform: position,
+            ..default()
+        })
+        .insert(RigidBodyBundle::fixed())
+        .insert(ColliderBundle {
+            collider: Collider::cylinder(0.1, 0.5),
+            collision_groups: crate::physics::TERRAIN_GROUPING,
+            ..default()
+        })
+        .insert((
+            Name::new("Campfire"),
+            HeatSource {
+                power: 20.0,
+                radius: 6.0,
+            },
+        ))
+        .with_children(|builder| {
+            builder.spawn(PointLightBundle {
+                point_light: PointLight {
+                    color: css::ORANGE.into(),
+                    intensity: 50_000.0,
+                    range: 5.0,
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.5, 0.0),
+                ..default()
+            });
+        })
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heats_up_and_cools_down() {
+        let source = HeatSource {
+            power: 10.0,
+            radius: 2.0,
+        };
+        assert_eq!(source.heat_at(2.5), 0.0);
+        assert!(source.heat_at(0.5) > source.heat_at(1.5));
+
+        let mut light = Temperature::with_thermal_mass(1.0);
+        let mut heavy = Temperature::with_thermal_mass(4.0);
+        for _ in 0..100 {
+            light.update(source.heat_at(0.0), 0.1);
+            heavy.update(source.heat_at(0.0), 0.1);
+        }
+        assert!(light.degrees > heavy.degrees);
+        assert!(heavy.degrees > AMBIENT_TEMPERATURE);
+
+        // Settles where heat coming in matches heat lost.
+        for _ in 0..10_000 {
+            light.update(source.heat_at(0.0), 0.1);
+        }
+        let settled = AMBIENT_TEMPERATURE + source.power / COOLING_RATE;
+        assert!((light.degrees - settled).abs() < 0.1);
+
+        for _ in 0..10_000 {
+            light.update(0.0, 0.1);
+        }
+        assert!((light.degrees - AMBIENT_TEMPERATURE).abs() < 0.1);
+    }
+}
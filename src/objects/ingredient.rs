@@ -0,0 +1,377 @@
+//! What ingredients are made of and what happens when they are mixed.
+//!
+//! Every [`Ingredient`] kind has a `assets/ingredients/<kind>.ingredient.ron`
+//! file with its [`IngredientProperties`]. Mixing sums up the element
+//! affinities, opposing elements cancel out, and how much of the mix is the
+//! strongest element decides whether the [`Brew`] comes out well, partially or
+//! not at all.
+use std::{fmt, ops};
+
+use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadState};
+use serde::{Deserialize, Serialize};
+
+use crate::{objects::cauldron::Ingredient, prelude::*};
+
+/// Share of the mix the strongest element needs for a proper brew.
+pub const PURE_THRESHOLD: f32 = 0.75;
+/// Share of the mix the strongest element needs for a weaker, partial brew.
+pub const PARTIAL_THRESHOLD: f32 = 0.4;
+/// Brews with less strength than this don't do anything.
+pub const MIN_STRENGTH: f32 = 0.1;
+/// Total volatility at which a brew turns explosive.
+pub const VOLATILE_THRESHOLD: f32 = 1.5;
+
+pub struct IngredientPlugin;
+
+impl Plugin for IngredientPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<IngredientProperties>()
+            .register_type::<Brew>();
+
+        app.init_asset::<IngredientProperties>()
+            .register_asset_loader(IngredientLoader);
+
+        app.add_systems(
+            FixedUpdate,
+            (load_ingredient_properties, apply_ingredient_properties).chain(),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum Element {
+    Fire,
+    Frost,
+    Earth,
+    Life,
+}
+
+impl Element {
+    pub const ALL: [Element; 4] = [Element::Fire, Element::Frost, Element::Earth, Element::Life];
+
+    /// Mixing this with its opposite cancels both out.
+    pub fn opposite(self) -> Element {
+        match self {
+            Element::Fire => Element::Frost,
+            Element::Frost => Element::Fire,
+            Element::Earth => Element::Life,
+            Element::Life => Element::Earth,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Affinities {
+    pub fire: f32,
+    pub frost: f32,
+    pub earth: f32,
+    pub life: f32,
+}
+
+impl Affinities {
+    pub fn get(&self, element: Element) -> f32 {
+        match element {
+            Element::Fire => self.fire,
+            Element::Frost => self.frost,
+            Element::Earth => self.earth,
+            Element::Life => self.life,
+        }
+    }
+
+    pub fn total(&self) -> f32 {
+        Element::ALL.iter().map(|element| self.get(*element)).sum()
+    }
+
+    /// Opposing elements take away from each other.
+    pub fn cancelled(&self) -> Self {
+        Self {
+            fire: (self.fire - self.frost).max(0.0),
+            frost: (self.frost - self.fire).max(0.0),
+            earth: (self.earth - self.life).max(0.0),
+            life: (self.life - self.earth).max(0.0),
+        }
+    }
+
+    /// Strongest element and its affinity.
+    pub fn dominant(&self) -> Option<(Element, f32)> {
+        Element::ALL
+            .iter()
+            .map(|element| (*element, self.get(*element)))
+            .filter(|(_, affinity)| *affinity > 0.0)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
+
+impl ops::Add for Affinities {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self {
+            fire: self.fire + other.fire,
+            frost: self.frost + other.frost,
+            earth: self.earth + other.earth,
+            life: self.life + other.life,
+        }
+    }
+}
+
+impl ops::Mul<f32> for Affinities {
+    type Output = Self;
+    fn mul(self, scale: f32) -> Self {
+        Self {
+            fire: self.fire * scale,
+            frost: self.frost * scale,
+            earth: self.earth * scale,
+            life: self.life * scale,
+        }
+    }
+}
+
+#[derive(Asset, Component, Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect(Component)]
+#[serde(default)]
+pub struct IngredientProperties {
+    pub affinities: Affinities,
+    /// How much this ingredient adds to a brew.
+    pub potency: f32,
+    /// How unstable it is, brews that are volatile enough explode.
+    pub volatility: f32,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    /// 1 is fresh, 0 is spoiled, scales potency.
+    pub freshness: f32,
+}
+
+impl Default for IngredientProperties {
+    fn default() -> Self {
+        Self {
+            affinities: Affinities::default(),
+            potency: 1.0,
+            volatility: 0.0,
+            color: [0.5, 0.5, 0.5],
+            freshness: 1.0,
+        }
+    }
+}
+
+impl IngredientProperties {
+    pub fn strength(&self) -> f32 {
+        self.potency * self.freshness.clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum BrewQuality {
+    #[default]
+    Failed,
+    Partial,
+    Full,
+}
+
+/// What a potion does, mixed from the properties of its ingredients.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Component, Reflect)]
+#[reflect(Component)]
+pub struct Brew {
+    pub element: Option<Element>,
+    pub volatile: bool,
+    pub strength: f32,
+    pub quality: BrewQuality,
+    /// Linear RGB.
+    pub color: [f32; 3],
+}
+
+impl Brew {
+    pub fn mix<'a>(ingredients: impl IntoIterator<Item = &'a IngredientProperties>) -> Self {
+        let mut affinities = Affinities::default();
+        let mut volatility = 0.0;
+        let mut color = Vec3::ZERO;
+        let mut weight = 0.0;
+
+        for properties in ingredients {
+            let strength = properties.strength();
+            affinities = affinities + properties.affinities * strength;
+            volatility += properties.volatility;
+
+            let color_weight = strength.max(f32::EPSILON);
+            color += Vec3::from_array(properties.color) * color_weight;
+            weight += color_weight;
+        }
+
+        let color = if weight > 0.0 {
+            (color / weight).to_array()
+        } else {
+            [0.0; 3]
+        };
+
+        let total = affinities.total();
+        let dominant = affinities.cancelled().dominant();
+        let (element, strength, quality) = match dominant {
+            Some((element, strength)) if strength >= MIN_STRENGTH => {
+                let purity = strength / total;
+                if purity >= PURE_THRESHOLD {
+                    (Some(element), strength, BrewQuality::Full)
+                } else if purity >= PARTIAL_THRESHOLD {
+                    (Some(element), strength * purity, BrewQuality::Partial)
+                } else {
+                    (None, 0.0, BrewQuality::Failed)
+                }
+            }
+            _ => (None, 0.0, BrewQuality::Failed),
+        };
+
+        Self {
+            element,
+            volatile: volatility >= VOLATILE_THRESHOLD,
+            strength,
+            quality,
+            color,
+        }
+    }
+
+    /// Name for a potion that didn't come from a recipe.
+    pub fn name(&self) -> String {
+        let quality = match self.quality {
+            BrewQuality::Full => "",
+            BrewQuality::Partial => "Weak ",
+            BrewQuality::Failed => "Murky ",
+        };
+        let element = self
+            .element
+            .map(|element| format!("{:?} ", element))
+            .unwrap_or_default();
+        let volatile = if self.volatile { "Volatile " } else { "" };
+        format!("{}{}{}Potion", volatile, quality, element)
+    }
+}
+
+/// Where an ingredient's properties are being loaded from.
+#[derive(Debug, Clone, Component)]
+pub struct IngredientSource(pub Handle<IngredientProperties>);
+
+pub fn load_ingredient_properties(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ingredients: Query<
+        (Entity, &Ingredient),
+        (Without<IngredientProperties>, Without<IngredientSource>),
+    >,
+) {
+    for (entity, ingredient) in &ingredients {
+        if ingredient.kind.is_empty() {
+            continue;
+        }
+
+        let handle = asset_server.load(format!("ingredients/{}.ingredient.ron", ingredient.kind));
+        commands.entity(entity).insert(IngredientSource(handle));
+    }
+}
+
+pub fn apply_ingredient_properties(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    properties: Res<Assets<IngredientProperties>>,
+    ingredients: Query<(Entity, &Ingredient, &IngredientSource), Without<IngredientProperties>>,
+) {
+    for (entity, ingredient, source) in &ingredients {
+        if let Some(properties) = properties.get(&source.0) {
+            commands.entity(entity).insert(properties.clone());
+        } else if let Some(LoadState::Failed(err)) = asset_server.get_load_state(&source.0) {
+            warn!(
+                "no properties for ingredient `{}`, using defaults: {}",
+                ingredient.kind, err
+            );
+            commands
+                .entity(entity)
+                .insert(IngredientProperties::default());
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct IngredientLoader;
+
+#[derive(Debug)]
+pub enum IngredientLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for IngredientLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IngredientLoaderError::Io(err) => write!(f, "failed to read ingredient: {}", err),
+            IngredientLoaderError::Ron(err) => write!(f, "invalid ingredient: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for IngredientLoaderError {}
+
+impl AssetLoader for IngredientLoader {
+    type Asset = IngredientProperties;
+    type Settings = ();
+    type Error = IngredientLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(IngredientLoaderError::Io)?;
+        ron::de::from_bytes(&bytes).map_err(IngredientLoaderError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ingredient.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(element: Element, potency: f32, volatility: f32) -> IngredientProperties {
+        let mut affinities = Affinities::default();
+        match element {
+            Element::Fire => affinities.fire = 1.0,
+            Element::Frost => affinities.frost = 1.0,
+            Element::Earth => affinities.earth = 1.0,
+            Element::Life => affinities.life = 1.0,
+        }
+
+        IngredientProperties {
+            affinities,
+            potency,
+            volatility,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn mixing() {
+        let fire = element(Element::Fire, 1.0, 0.2);
+        let frost = element(Element::Frost, 1.0, 0.0);
+        let earth = element(Element::Earth, 1.0, 0.0);
+
+        let pure = Brew::mix([&fire, &fire]);
+        assert_eq!(pure.element, Some(Element::Fire));
+        assert_eq!(pure.quality, BrewQuality::Full);
+        assert_eq!(pure.strength, 2.0);
+
+        let partial = Brew::mix([&fire, &fire, &earth]);
+        assert_eq!(partial.quality, BrewQuality::Partial);
+        assert!(partial.strength < pure.strength);
+
+        let cancelled = Brew::mix([&fire, &frost]);
+        assert_eq!(cancelled.quality, BrewQuality::Failed);
+        assert_eq!(cancelled.element, None);
+
+        let unstable = element(Element::Fire, 1.0, 1.0);
+        assert!(Brew::mix([&unstable, &unstable]).volatile);
+    }
+}
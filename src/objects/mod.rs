@@ -1,6 +1,12 @@
 pub mod cauldron;
 pub mod effects;
+pub mod fire;
+pub mod heat;
+pub mod ingredient;
+pub mod mortar;
 pub mod potion;
+pub mod recipe;
+pub mod stirrer;
 pub mod store;
 pub mod thrown;
 //pub mod trees;
@@ -0,0 +1,371 @@
+//! Crushing ingredients with a mortar and pestle, see `design/brewing.md`.
+//!
+//! Whatever is slotted in a [`Mortar`] takes the impulses of [`Pestle`] hits,
+//! and a [`Crushable`] ingredient that took enough of them becomes its crushed
+//! kind, with the properties in `assets/ingredients/<crushed>.ingredient.ron`.
+use crate::objects::{
+    cauldron::Ingredient,
+    ingredient::{IngredientProperties, IngredientSource},
+};
+use crate::physics::{
+    slot::{insert_slot, Slot, SlotDeposit, SlotGracePeriod, SlotSettings, Slottable},
+    ColliderBundle, RigidBodyBundle,
+};
+use crate::player::grab::{AimPrimitive, AutoAim};
+use crate::prelude::*;
+
+/// Radius of the pile a crushed ingredient turns into.
+pub const CRUSHED_RADIUS: f32 = 0.15;
+/// Height of the pile a crushed ingredient turns into.
+pub const CRUSHED_HEIGHT: f32 = 0.06;
+/// How long after the last hit the mortar's slot stays locked, in seconds.
+pub const CRUSH_LOCK_TIME: f32 = 1.0;
+
+pub struct MortarPlugin;
+
+impl Plugin for MortarPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Mortar>()
+            .register_type::<Pestle>()
+            .register_type::<Crushable>()
+            .register_type::<CrushProgress>();
+        app.add_event::<Crushed>();
+
+        app.add_systems(
+            FixedUpdate,
+            (
+                crush
+                    .after(insert_slot)
+                    // The server crushes, clients get the result replicated.
+                    .run_if(not(resource_exists::<NetworkClient>)),
+                client_crushed.run_if(resource_exists::<NetworkClient>),
+            )
+                .in_set(GameplaySet),
+        );
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct Mortar;
+
+#[derive(Default, Debug, Copy, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct Pestle;
+
+#[derive(Debug, Clone, Component, Reflect)]
+#[reflect(Component)]
+pub struct Crushable {
+    /// Ingredient kind this turns into.
+    pub crushed: String,
+    /// Total impulse of the hits it takes, in N·s.
+    pub toughness: f32,
+}
+
+impl Crushable {
+    pub fn new(crushed: impl Into<String>) -> Self {
+        Self {
+            crushed: crushed.into(),
+            toughness: 15.0,
+        }
+    }
+}
+
+/// How much the item in a mortar has been crushed so far.
+#[derive(Default, Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct CrushProgress {
+    /// Item being crushed, progress starts over when it changes.
+    pub item: Option<Entity>,
+    pub impulse: f32,
+    /// Seconds since the pestle last hit the item.
+    pub since_hit: f32,
+}
+
+impl CrushProgress {
+    /// Start over if `item` isn't the one being crushed.
+    pub fn track(&mut self, item: Option<Entity>) {
+        if self.item != item {
+            *self = CrushProgress { item, ..default() };
+        }
+    }
+
+    /// Add up the impulse of this tick's hits on the item.
+    pub fn hit(&mut self, impulse: f32) {
+        if impulse > 0.0 {
+            self.impulse += impulse;
+            self.since_hit = 0.0;
+        } else {
+            self.since_hit += crate::TICK_RATE.as_secs_f32();
+        }
+    }
+}
+
+/// A mortar crushed an ingredient into its crushed kind.
+#[derive(Event, Debug, Clone)]
+pub struct Crushed {
+    pub mortar: Entity,
+    pub item: Entity,
+    pub kind: String,
+}
+
+/// Sum up pestle hits on whatever is slotted in a mortar and crush it once it took enough.
+///
+/// The mortar's slot is locked while it is being hit.
+pub fn crush(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut mortars: Query<(Entity, &mut SlotDeposit, &mut CrushProgress), With<Mortar>>,
+    slots: Query<&Slot>,
+    mut items: Query<(&Crushable, &mut Ingredient, Option<&IngredientProperties>)>,
+    pestles: Query<Entity, With<Pestle>>,
+    slottables: Query<Entity, With<Slottable>>,
+    parents: Query<&Parent>,
+    joints: Query<&ImpulseJoint>,
+    mut contact_forces: EventReader<ContactForceEvent>,
+    mut crushed: EventWriter<Crushed>,
+) {
+    let hits = contact_forces
+        .read()
+        .filter_map(|event| {
+            let pestle = |collider| find_parent_with(&pestles, &parents, &joints, collider);
+            let slottable = |collider| find_parent_with(&slottables, &parents, &joints, collider);
+
+            let hit = if pestle(event.collider1).is_some() {
+                slottable(event.collider2)
+            } else if pestle(event.collider2).is_some() {
+                slottable(event.collider1)
+            } else {
+                None
+            }?;
+            Some((
+                hit,
+                event.total_force_magnitude * crate::TICK_RATE.as_secs_f32(),
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    for (mortar, mut deposit, mut progress) in &mut mortars {
+        let item = deposit
+            .slots
+            .first()
+            .and_then(|slot| slots.get(*slot).ok())
+            .and_then(|slot| slot.containing);
+        progress.track(item);
+
+        let Some((item, Ok((crushable, mut ingredient, properties)))) =
+            item.map(|item| (item, items.get_mut(item)))
+        else {
+            if deposit.locked() {
+                deposit.set_locked(false);
+            }
+            continue;
+        };
+
+        let impulse = hits
+            .iter()
+            .filter(|(hit, _)| *hit == item)
+            .map(|(_, impulse)| impulse)
+            .sum::<f32>();
+        progress.hit(impulse);
+
+        if progress.impulse < crushable.toughness {
+            let locked = progress.impulse > 0.0 && progress.since_hit < CRUSH_LOCK_TIME;
+            if deposit.locked() != locked {
+                deposit.set_locked(locked);
+            }
+            continue;
+        }
+
+        info!("crushed {} into {}", ingredient.kind, crushable.crushed);
+        ingredient.kind = crushable.crushed.clone();
+        progress.impulse = 0.0;
+        deposit.set_locked(false);
+
+        into_pile(&mut commands, &mut meshes, &mut materials, item, properties);
+        commands.entity(item).insert(Replicate);
+
+        crushed.send(Crushed {
+            mortar,
+            item,
+            kind: ingredient.kind.clone(),
+        });
+    }
+}
+
+/// Clients only get the new kind, swap the model for the pile once it arrives.
+pub fn client_crushed(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    items: Query<
+        (
+            Entity,
+            &Crushable,
+            &Ingredient,
+            Option<&IngredientProperties>,
+        ),
+        Changed<Ingredient>,
+    >,
+) {
+    for (item, crushable, ingredient, properties) in &items {
+        if ingredient.kind == crushable.crushed {
+            into_pile(&mut commands, &mut meshes, &mut materials, item, properties);
+        }
+    }
+}
+
+/// Replace the model of a crushed `item` with a pile of its color.
+fn into_pile(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    item: Entity,
+    properties: Option<&IngredientProperties>,
+) {
+    let [r, g, b] = properties
+        .map(|properties| properties.color)
+        .unwrap_or([0.5, 0.5, 0.5]);
+    commands
+        .entity(item)
+        .despawn_descendants()
+        .remove::<(
+            Crushable,
+            IngredientProperties,
+            IngredientSource,
+            Handle<Scene>,
+        )>()
+        .insert((
+            meshes.add(Cylinder::new(CRUSHED_RADIUS, CRUSHED_HEIGHT)),
+            materials.add(Color::linear_rgb(r, g, b)),
+            Collider::cylinder(CRUSHED_HEIGHT / 2.0, CRUSHED_RADIUS),
+        ));
+}
+
+pub fn spawn_mortar(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    position: Transform,
+    meshes: &mut ResMut<Assets<Mesh>>,
+) -> Entity {
+    let slot = commands
+        .spawn(PbrBundle {
+            mesh: meshes.add(Mesh::from(Sphere { radius: 0.02 })),
+            transform: Transform::from_xyz(0.0, 0.15, 0.0),
+            ..default()
+        })
+        .insert(Name::new("Mortar slot"))
+        .insert(Velocity::default())
+        .insert(Slot::default())
+        .insert(crate::DebugVisible)
+        .insert(SlotGracePeriod::default())
+        .insert(SlotSettings(springy::Spring {
+            strength: 1.00,
+            damp_ratio: 0.5,
+        }))
+        .id();
+
+    let deposit = commands
+        .spawn(TransformBundle::from_transform(Transform::from_xyz(
+            0.0, 0.2, 0.0,
+        )))
+        .insert((
+            Name::new("Mortar Deposit"),
+            crate::physics::TERRAIN_GROUPING,
+        ))
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(Collider::cylinder(0.1, 0.2))
+        .insert(Mortar)
+        .insert(SlotDeposit::new(vec![slot]))
+        .insert(CrushProgress::default())
+        .insert(Sensor)
+        .id();
+
+    let collision_mesh: Handle<Mesh> = asset_server.load("models/mortar.gltf#Mesh0/Primitive0");
+    commands
+        .spawn((
+            SceneBundle {
+                scene: asset_server.load("models/mortar.gltf#Scene0"),
+                transform: position,
+                ..default()
+            },
+            crate::ColliderLoad,
+            Name::new("Mortar"),
+            collision_mesh,
+        ))
+        .insert(RigidBodyBundle::dynamic())
+        .insert(ColliderBundle {
+            collision_groups: crate::physics::TERRAIN_GROUPING,
+            ..default()
+        })
+        .add_child(deposit)
+        .add_child(slot)
+        .id()
+}
+
+pub fn spawn_pestle(
+    commands: &mut Commands,
+    position: Transform,
+    meshes: &mut ResMut<Assets<Mesh>>,
+) -> Entity {
+    let radius = 0.05;
+    let half_height = 0.2;
+    commands
+        .spawn(PbrBundle {
+            mesh: meshes.add(Capsule3d::new(radius, half_height * 2.0)),
+            transform: position,
+            ..default()
+        })
+        .insert(RigidBodyBundle::dynamic())
+        .insert(ColliderBundle {
+            collider: Collider::capsule_y(half_height, radius),
+            // Has to reach whatever is locked in the mortar.
+            collision_groups: crate::physics::TOOL_GROUPING,
+            ..default()
+        })
+        .insert((
+            Name::new("Pestle"),
+            Pestle,
+            // Resting on something shouldn't grind it down.
+            ContactForceEventThreshold(50.0),
+            ActiveEvents::CONTACT_FORCE_EVENTS,
+            AutoAim(vec![AimPrimitive::Line {
+                start: Vec3::new(0.0, 0.0, 0.0),
+                end: Vec3::new(0.0, half_height, 0.0),
+            }]),
+        ))
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_adds_up_and_starts_over() {
+        let first = Entity::from_raw(1);
+        let second = Entity::from_raw(2);
+
+        let mut progress = CrushProgress::default();
+        progress.track(Some(first));
+        progress.hit(4.0);
+        progress.hit(0.0);
+        progress.hit(6.0);
+        progress.track(Some(first));
+        assert_eq!(progress.impulse, 10.0);
+        assert_eq!(progress.since_hit, 0.0);
+
+        progress.hit(0.0);
+        assert!(progress.since_hit > 0.0);
+
+        progress.track(Some(second));
+        assert_eq!(progress.item, Some(second));
+        assert_eq!(progress.impulse, 0.0);
+        assert_eq!(progress.since_hit, 0.0);
+
+        progress.hit(3.0);
+        progress.track(None);
+        assert_eq!(progress.impulse, 0.0);
+    }
+}
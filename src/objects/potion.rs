@@ -1,4 +1,7 @@
-use crate::objects::Thrown;
+use bevy::utils::HashSet;
+
+use crate::objects::{ingredient::Brew, PotionEffects, PotionImpact, PotionKind, Thrown};
+use crate::physics::{ColliderBundle, RigidBodyBundle};
 use crate::prelude::*;
 
 pub struct PotionPlugin;
@@ -8,7 +11,13 @@ impl Plugin for PotionPlugin {
         app.register_type::<Potion>()
             .register_type::<CrackThreshold>();
 
-        app.add_systems(FixedUpdate, (potion_contact_explode,));
+        app.add_systems(
+            FixedUpdate,
+            potion_contact_explode
+                .in_set(GameplaySet)
+                // Clients get the cracked potion despawned and the effects replicated.
+                .run_if(not(resource_exists::<NetworkClient>)),
+        );
     }
 }
 
@@ -30,6 +39,7 @@ impl Default for CrackThreshold {
 pub struct PotionBundle {
     pub potion: Potion,
     pub crack_threshold: CrackThreshold,
+    pub kind: PotionKind,
 }
 
 impl Default for PotionBundle {
@@ -37,6 +47,7 @@ impl Default for PotionBundle {
         Self {
             potion: Potion::default(),
             crack_threshold: CrackThreshold::default(),
+            kind: PotionKind::default(),
         }
     }
 }
@@ -56,16 +67,53 @@ impl Default for PotionColliderBundle {
     }
 }
 
+/// Spawn a dynamic potion bottle with the scene at `model`.
+pub fn spawn_potion(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    transform: Transform,
+    model: &str,
+    name: &str,
+) -> Entity {
+    commands
+        .spawn((
+            SceneBundle {
+                scene: asset_server.load(format!("{}#Scene0", model)),
+                transform,
+                ..default()
+            },
+            Name::new(name.to_owned()),
+        ))
+        .insert(crate::player::inventory::Storeable)
+        .insert(RigidBodyBundle::dynamic())
+        .insert(PotionBundle::default())
+        .insert(PotionColliderBundle::default())
+        .insert(ColliderBundle {
+            collider: Collider::cuboid(0.5, 0.5, 0.5),
+            collision_groups: crate::physics::TERRAIN_GROUPING,
+            ..default()
+        })
+        .id()
+}
+
+/// Break thrown potions that hit something hard enough, spawning their [`PotionKind`]'s effect.
 pub fn potion_contact_explode(
     mut commands: Commands,
-    potions: Query<&CrackThreshold, (With<Potion>, With<Thrown>)>,
+    effects: Res<PotionEffects>,
+    potions: Query<
+        (&CrackThreshold, Option<&PotionKind>, Option<&Brew>),
+        (With<Potion>, With<Thrown>),
+    >,
     globals: Query<&GlobalTransform>,
     velocities: Query<&Velocity>,
     mut contact_forces: EventReader<ContactForceEvent>,
     rigid_body: Query<Entity, With<RigidBody>>,
     parent: Query<&Parent>,
     mut gizmos: ResMut<RetainedGizmos>,
+    mut server: Option<ResMut<NetworkServer>>,
 ) {
+    // The despawn is deferred, so a potion could crack again on another contact.
+    let mut cracked_potions = HashSet::new();
     let mut check_crack = |mut entity: Entity, other: Entity, event: &ContactForceEvent| -> bool {
         while !rigid_body.contains(entity) {
             if let Ok(parent) = parent.get(entity) {
@@ -75,12 +123,16 @@ pub fn potion_contact_explode(
             }
         }
 
-        let Ok(crack_threshold) = potions.get(entity) else {
+        let Ok((crack_threshold, kind, brew)) = potions.get(entity) else {
             return false;
         };
+        if cracked_potions.contains(&entity) {
+            return false;
+        }
         let hit_force = event.max_force_magnitude.abs();
         let cracked = hit_force > crack_threshold.0;
         if cracked {
+            cracked_potions.insert(entity);
             info!("entity {:?} cracked at force {:?}", entity, hit_force);
             commands.entity(entity).despawn_recursive();
             let global = globals
@@ -92,16 +144,29 @@ pub fn potion_contact_explode(
                 .cloned()
                 .unwrap_or(Velocity::default());
 
-            commands
-                .spawn(SpatialBundle {
-                    transform: global.compute_transform(),
-                    ..default()
-                })
-                .insert(crate::objects::EffectVelocity {
-                    //linear: velocity.linvel,
-                    linear: -event.total_force,
+            let kind = kind.cloned().unwrap_or_default();
+            let impact = PotionImpact {
+                potion: entity,
+                point: global.translation(),
+                force: event.total_force,
+                velocity,
+                strength: brew.map(|brew| brew.strength).unwrap_or(1.0),
+            };
+            if !effects.spawn(&kind, &mut commands, &impact) {
+                warn!("no effect registered for potion kind {:?}", kind.0);
+            }
+            if let Some(server) = &mut server {
+                let message = bincode::serialize(&ServerMessage::PotionCracked {
+                    kind: kind.0.clone(),
+                    point: impact.point.into(),
+                    force: impact.force.into(),
+                    linvel: velocity.linvel.into(),
+                    angvel: velocity.angvel.into(),
+                    strength: impact.strength,
                 })
-                .insert(crate::objects::vine::VineEffect::default());
+                .unwrap();
+                server.broadcast_message(Channel::Reliable, message);
+            }
 
             /*
             gizmos.sphere(
@@ -0,0 +1,179 @@
+//! Cauldron recipes, see `assets/cauldron.recipes.ron` and `design/brewing.md`.
+use std::fmt;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::objects::heat::HeatCondition;
+use crate::prelude::*;
+
+/// The [`RecipeBook`] cauldrons brew from.
+pub const RECIPES_PATH: &str = "cauldron.recipes.ron";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecipeIngredient {
+    /// Matches [`super::cauldron::Ingredient::kind`].
+    pub kind: String,
+    #[serde(default = "one")]
+    pub count: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecipeOutput {
+    pub name: String,
+    /// Scene the potion is spawned with.
+    pub model: String,
+    /// [`super::PotionKind`] of the potion, picked from how the brew came out if not set.
+    #[serde(default)]
+    pub effect: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    pub name: String,
+    pub ingredients: Vec<RecipeIngredient>,
+    /// `(a, b)`: every `a` has to go in before any `b`.
+    #[serde(default)]
+    pub order: Vec<(String, String)>,
+    /// Stirring needed before it is done, clockwise turns or counterclockwise
+    /// if negative, see [`super::stirrer::StirProgress`].
+    #[serde(default)]
+    pub turns: f32,
+    /// How hot the cauldron has to be kept for how long, see [`super::heat`].
+    #[serde(default)]
+    pub heat: Option<HeatCondition>,
+    pub output: RecipeOutput,
+}
+
+impl Recipe {
+    /// Whether `contents`, in the order they went in, is exactly what this recipe takes.
+    pub fn matches(&self, contents: &[&str]) -> bool {
+        let mut counts = HashMap::<&str, usize>::new();
+        for kind in contents {
+            *counts.entry(*kind).or_default() += 1;
+        }
+
+        let mut required = HashMap::<&str, usize>::new();
+        for ingredient in &self.ingredients {
+            *required.entry(ingredient.kind.as_str()).or_default() += ingredient.count;
+        }
+        if counts != required {
+            return false;
+        }
+
+        self.order.iter().all(|(first, then)| {
+            let last_first = contents.iter().rposition(|kind| *kind == first.as_str());
+            let first_then = contents.iter().position(|kind| *kind == then.as_str());
+            match (last_first, first_then) {
+                (Some(last_first), Some(first_then)) => last_first < first_then,
+                _ => true,
+            }
+        })
+    }
+}
+
+#[derive(Asset, TypePath, Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecipeBook {
+    pub recipes: Vec<Recipe>,
+}
+
+impl RecipeBook {
+    pub fn from_ron(source: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(source)
+    }
+
+    /// First recipe made from exactly `contents`, in the order they went in.
+    pub fn find(&self, contents: &[&str]) -> Option<&Recipe> {
+        self.recipes.iter().find(|recipe| recipe.matches(contents))
+    }
+}
+
+/// Where the [`RecipeBook`] is being loaded from.
+#[derive(Resource, Debug, Clone)]
+pub struct Recipes(pub Handle<RecipeBook>);
+
+pub fn load_recipes(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Recipes(asset_server.load(RECIPES_PATH)));
+}
+
+#[derive(Default)]
+pub struct RecipeLoader;
+
+#[derive(Debug)]
+pub enum RecipeLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for RecipeLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecipeLoaderError::Io(err) => write!(f, "failed to read recipes: {}", err),
+            RecipeLoaderError::Ron(err) => write!(f, "invalid recipes: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RecipeLoaderError {}
+
+impl AssetLoader for RecipeLoader {
+    type Asset = RecipeBook;
+    type Settings = ();
+    type Error = RecipeLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(RecipeLoaderError::Io)?;
+        ron::de::from_bytes(&bytes).map_err(RecipeLoaderError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["recipes.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recipes_ron_is_valid() {
+        let path = format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), RECIPES_PATH);
+        RecipeBook::from_ron(&std::fs::read_to_string(path).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn matches_counts_and_order() {
+        let book = RecipeBook::from_ron(
+            r#"(recipes: [(
+                name: "Vine Potion",
+                ingredients: [(kind: "weltberry"), (kind: "thorns", count: 2)],
+                order: [("weltberry", "thorns")],
+                output: (name: "Vine Potion", model: "models/potion_flask.glb"),
+            )])"#,
+        )
+        .unwrap();
+
+        assert!(book.find(&["weltberry", "thorns", "thorns"]).is_some());
+        assert!(book.find(&["thorns", "weltberry", "thorns"]).is_none());
+        assert!(book.find(&["weltberry", "thorns"]).is_none());
+        assert!(book
+            .find(&["weltberry", "thorns", "thorns", "stone"])
+            .is_none());
+    }
+}
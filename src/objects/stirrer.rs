@@ -0,0 +1,183 @@
+//! Stirring a cauldron, see `design/brewing.md`.
+//!
+//! A [`Stirrer`] tip moving around the axis of a cauldron's deposit while
+//! something is slotted winds up that cauldron's [`StirProgress`], which
+//! recipes can ask for with [`crate::objects::recipe::Recipe::turns`].
+use std::f32::consts::{PI, TAU};
+
+use crate::objects::cauldron::{brew, Cauldron};
+use crate::physics::{slot::insert_slot, ColliderBundle, RigidBodyBundle};
+use crate::player::grab::{AimPrimitive, AutoAim};
+use crate::prelude::*;
+
+pub struct StirrerPlugin;
+
+impl Plugin for StirrerPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Stirrer>()
+            .register_type::<StirProgress>();
+
+        app.add_systems(
+            FixedUpdate,
+            stir.in_set(GameplaySet)
+                .after(insert_slot)
+                .before(brew)
+                .run_if(not(resource_exists::<NetworkClient>)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct Stirrer {
+    /// Local point that has to be in the cauldron for stirring to count,
+    /// the end of the paddle past the handle.
+    pub tip: Vec3,
+}
+
+impl Default for Stirrer {
+    fn default() -> Self {
+        Self {
+            tip: Vec3::new(0.0, 1.4, 0.0),
+        }
+    }
+}
+
+/// How far the contents of a cauldron have been stirred.
+#[derive(Default, Debug, Clone, Copy, Component, Reflect)]
+#[reflect(Component)]
+pub struct StirProgress {
+    /// Turns around the cauldron, clockwise seen from above is positive.
+    pub turns: f32,
+    /// Angle of the stirrer tip last tick, `None` if it wasn't stirring.
+    pub last_angle: Option<f32>,
+}
+
+impl StirProgress {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Whether this is at least `turns` in the same direction, 0 is always satisfied.
+    pub fn satisfies(&self, turns: f32) -> bool {
+        if turns >= 0.0 {
+            self.turns >= turns
+        } else {
+            self.turns <= turns
+        }
+    }
+}
+
+/// Shortest signed difference between two angles.
+pub fn angle_delta(from: f32, to: f32) -> f32 {
+    let delta = (to - from).rem_euclid(TAU);
+    if delta > PI {
+        delta - TAU
+    } else {
+        delta
+    }
+}
+
+/// Track the angle of stirrer tips inside a cauldron's deposit around its up axis.
+///
+/// The cauldron's slots are locked while a stirrer is in it.
+pub fn stir(
+    mut cauldrons: Query<
+        (
+            &GlobalTransform,
+            &Collider,
+            &mut SlotDeposit,
+            &mut StirProgress,
+        ),
+        With<Cauldron>,
+    >,
+    slots: Query<&Slot>,
+    stirrers: Query<(&GlobalTransform, &Stirrer)>,
+) {
+    for (deposit_global, collider, mut deposit, mut progress) in &mut cauldrons {
+        let occupied = deposit
+            .slots
+            .iter()
+            .filter_map(|slot| slots.get(*slot).ok())
+            .any(|slot| slot.containing.is_some());
+        if !occupied {
+            if progress.turns != 0.0 || progress.last_angle.is_some() {
+                progress.reset();
+            }
+            if deposit.locked() {
+                deposit.set_locked(false);
+            }
+            continue;
+        }
+
+        let Some(cylinder) = collider.as_cylinder() else {
+            continue;
+        };
+
+        // Colliders are scaled along with the transform, so leave the scale in.
+        let (_, rotation, translation) = deposit_global.to_scale_rotation_translation();
+        let angle = stirrers.iter().find_map(|(stirrer_global, stirrer)| {
+            let tip =
+                rotation.inverse() * (stirrer_global.transform_point(stirrer.tip) - translation);
+            let inside =
+                tip.y.abs() <= cylinder.half_height() && tip.xz().length() <= cylinder.radius();
+            // Right at the axis the angle jumps around.
+            let off_axis = tip.xz().length() > cylinder.radius() * 0.1;
+            (inside && off_axis).then(|| tip.z.atan2(tip.x))
+        });
+
+        // Rotating from +X towards +Z is clockwise looking down.
+        if let (Some(last), Some(angle)) = (progress.last_angle, angle) {
+            progress.turns += angle_delta(last, angle) / TAU;
+        }
+        progress.last_angle = angle;
+
+        if deposit.locked() != angle.is_some() {
+            deposit.set_locked(angle.is_some());
+        }
+    }
+}
+
+pub fn spawn_stirrer(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    transform: Transform,
+) -> Entity {
+    commands
+        .spawn(SceneBundle {
+            scene: asset_server.load("models/cauldron_stirrer.glb#Scene0"),
+            transform,
+            ..default()
+        })
+        .insert(AutoAim(vec![AimPrimitive::Line {
+            start: Vec3::new(0.0, 0.3, 0.0),
+            end: Vec3::new(0.0, 1.2, 0.0),
+        }]))
+        .insert(RigidBodyBundle::dynamic())
+        .insert((Name::new("Stirrer"), Stirrer::default()))
+        .with_children(|builder| {
+            builder
+                .spawn(TransformBundle::from_transform(Transform::from_xyz(
+                    0.0, 1.0, 0.0,
+                )))
+                .insert(ColliderBundle {
+                    collider: Collider::cuboid(0.1, 0.5, 0.1),
+                    // Stirs through locked slots.
+                    collision_groups: crate::physics::TOOL_GROUPING,
+                    ..default()
+                });
+        })
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angle_delta_wraps() {
+        assert!((angle_delta(0.1, 0.3) - 0.2).abs() < 1e-5);
+        assert!((angle_delta(PI - 0.1, -PI + 0.1) - 0.2).abs() < 1e-5);
+        assert!((angle_delta(-PI + 0.1, PI - 0.1) + 0.2).abs() < 1e-5);
+    }
+}
@@ -4,7 +4,7 @@ pub struct ThrowPlugin;
 impl Plugin for ThrowPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Thrown>();
-        app.add_systems(FixedUpdate, (throw_decay,));
+        app.add_systems(FixedUpdate, throw_decay.in_set(GameplaySet));
     }
 }
 
@@ -14,7 +14,7 @@ pub mod prelude {
     pub use super::{
         contact_filter::*, context_ext::*, joint_break::*, joint_interpolation::*, muscle::*,
         slot::*, ColliderBundle, RigidBodyBundle, GRAB_GROUPING, PLAYER_GROUPING, REST_GROUPING,
-        STORED_GROUPING, TERRAIN_GROUPING,
+        SLOT_GROUPING, STORED_GROUPING, TERRAIN_GROUPING, TOOL_GROUPING,
     };
 }
 
@@ -28,8 +28,12 @@ bitflags::bitflags! {
         const TERRAIN = 1 << 1;
         const FLUFF = 1 << 3;
         const STORED = 1 << 5;
+        const SLOTTED = 1 << 6;
+        /// Grabbing hands, and tools that work on slotted items.
+        const HAND = 1 << 7;
 
         const PLAYER_FILTER = Groups::TERRAIN.bits();
+        const GRAB_FILTER = Groups::PLAYER_FILTER.bits() | Groups::SLOTTED.bits();
         const TERRAIN_FILTER = Groups::PLAYER.bits() | Groups::TERRAIN.bits() | Groups::FLUFF.bits();
     }
 }
@@ -49,7 +53,24 @@ pub const REST_GROUPING: CollisionGroups = CollisionGroups::new(
     Group::from_bits_truncate(0),
     //Group::from_bits_truncate(Groups::PLAYER.bits()),
 );
-pub const GRAB_GROUPING: CollisionGroups = PLAYER_GROUPING;
+pub const GRAB_GROUPING: CollisionGroups = CollisionGroups::new(
+    Group::from_bits_truncate(Groups::PLAYER.bits() | Groups::HAND.bits()),
+    Group::from_bits_truncate(Groups::GRAB_FILTER.bits()),
+);
+
+/// Items in locked slots, only [`Groups::HAND`] touches them.
+///
+/// Colliders left in the default groups are in every group, `HAND` included.
+pub const SLOT_GROUPING: CollisionGroups = CollisionGroups::new(
+    Group::from_bits_truncate(Groups::SLOTTED.bits()),
+    Group::from_bits_truncate(Groups::HAND.bits()),
+);
+
+/// Props that work on slotted items, like the stirrer and pestle.
+pub const TOOL_GROUPING: CollisionGroups = CollisionGroups::new(
+    Group::from_bits_truncate(Groups::TERRAIN.bits() | Groups::HAND.bits()),
+    Group::from_bits_truncate(Groups::TERRAIN_FILTER.bits() | Groups::SLOTTED.bits()),
+);
 
 pub const STORED_GROUPING: CollisionGroups = CollisionGroups::new(
     Group::from_bits_truncate(Groups::STORED.bits()),
@@ -234,6 +255,9 @@ pub fn modify_rapier_context(mut context: ResMut<RapierContext>) {
     integration.dt = crate::TICK_RATE.as_secs_f32();
 }
 
+/// Solver substeps rapier takes per fixed tick.
+pub const PHYSICS_SUBSTEPS: usize = 8;
+
 pub const VELOCITY_CAP: f32 = 50.0;
 pub const ANG_VELOCITY_CAP: f32 = 5.0;
 
@@ -290,7 +314,7 @@ impl Plugin for PhysicsPlugin {
         app.insert_resource(RapierConfiguration {
             timestep_mode: TimestepMode::Fixed {
                 dt: crate::TICK_RATE.as_secs_f32() / 1.0,
-                substeps: 8,
+                substeps: PHYSICS_SUBSTEPS,
             },
             ..RapierConfiguration::new(1.0)
         });
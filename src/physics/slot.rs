@@ -1,16 +1,31 @@
 use std::{collections::VecDeque, time::Duration};
 
 use crate::prelude::*;
-use bevy_rapier3d::rapier::dynamics::{JointAxesMask, JointAxis};
+use bevy::{
+    ecs::{
+        entity::{EntityMapper, MapEntities},
+        reflect::ReflectMapEntities,
+    },
+    utils::HashSet,
+};
+use bevy_rapier3d::rapier::dynamics::{GenericJoint as RawGenericJoint, JointAxesMask, JointAxis};
 
 #[derive(Default, Debug, Copy, Clone, Component, Reflect)]
-#[reflect(Component)]
+#[reflect(Component, MapEntities)]
 pub struct Slot {
     /// Entity this slot contains.
     #[reflect(default)]
     pub containing: Option<Entity>,
 }
 
+impl MapEntities for Slot {
+    fn map_entities<M: EntityMapper>(&mut self, entity_mapper: &mut M) {
+        if let Some(containing) = &mut self.containing {
+            *containing = entity_mapper.map_entity(*containing);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Bundle)]
 pub struct SlotBundle {
     pub slot: Slot,
@@ -18,10 +33,21 @@ pub struct SlotBundle {
     pub grace: SlotGracePeriod,
 }
 
-#[derive(Default, Debug, Clone, Component, Reflect)]
+/// Spring holding a slotted item in place, `strength` scales [`SLOT_STIFFNESS`]
+/// and `damp_ratio` scales [`SLOT_DAMPING`].
+#[derive(Debug, Clone, Component, Reflect)]
 #[reflect(Component)]
 pub struct SlotSettings(pub springy::Spring);
 
+impl Default for SlotSettings {
+    fn default() -> Self {
+        Self(springy::Spring {
+            strength: 1.0,
+            damp_ratio: 0.2,
+        })
+    }
+}
+
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Component, Reflect)]
 #[reflect(Component)]
 pub enum Slottable {
@@ -41,10 +67,35 @@ impl Default for SlotGracePeriod {
     }
 }
 
+/// Motor stiffness of a slot spring with a strength of 1.
+pub const SLOT_STIFFNESS: f32 = 5000.0;
+/// Motor damping of a slot spring with a damp ratio of 1.
+pub const SLOT_DAMPING: f32 = 25.0;
+/// Motor force slots hold their items with.
+pub const SLOT_MAX_FORCE: f32 = 300.0;
+/// Share of its max force a slot's motor can put out before the item breaks free.
+pub const SLOT_BREAK_RATIO: f32 = 0.95;
+/// Motor force locked slots hold their items with.
+pub const LOCKED_SLOT_MAX_FORCE: f32 = 3000.0;
+
+/// Whether a deposit is in the middle of processing what is in its slots.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Reflect)]
+pub enum SlotLock {
+    #[default]
+    Unlocked,
+
+    /// Slots hold on harder, nothing new goes in, and slotted items are moved
+    /// to [`SLOT_GROUPING`] so only hands get at them.
+    Locked,
+}
+
 #[derive(Debug, Clone, Component)]
 pub struct SlotDeposit {
     pub slots: Vec<Entity>,
     pub attempting: VecDeque<Entity>,
+    /// Slotted items in the order they went in, whichever slot they ended up in.
+    pub inserted: Vec<Entity>,
+    pub lock: SlotLock,
 }
 
 impl SlotDeposit {
@@ -52,18 +103,40 @@ impl SlotDeposit {
         Self {
             slots,
             attempting: VecDeque::new(),
+            inserted: Vec::new(),
+            lock: SlotLock::Unlocked,
+        }
+    }
+
+    pub fn locked(&self) -> bool {
+        self.lock == SlotLock::Locked
+    }
+
+    /// Lock or unlock, dropping anything waiting to be slotted when locking.
+    pub fn set_locked(&mut self, locked: bool) {
+        if locked == self.locked() {
+            return;
+        }
+
+        if locked {
+            self.lock = SlotLock::Locked;
+            self.attempting.clear();
+        } else {
+            self.lock = SlotLock::Unlocked;
         }
     }
 
-    pub fn contains(&self, _entity: Entity) -> Option<usize> {
+    pub fn contains(&self, entity: Entity) -> Option<usize> {
         self.attempting
             .iter()
-            .enumerate()
-            .find(|(_index, entity)| entity == entity)
-            .map(|(index, _)| index)
+            .position(|attempting| *attempting == entity)
     }
 
     pub fn attempt(&mut self, entity: Entity) {
+        if self.locked() {
+            return;
+        }
+
         if let None = self.contains(entity) {
             self.attempting.push_back(entity);
         }
@@ -79,6 +152,11 @@ impl SlotDeposit {
     pub fn pop_attempt(&mut self) -> Option<Entity> {
         self.attempting.pop_front()
     }
+
+    /// How many items went in before `item`, `None` if it isn't slotted here.
+    pub fn insertion_index(&self, item: Entity) -> Option<usize> {
+        self.inserted.iter().position(|inserted| *inserted == item)
+    }
 }
 
 pub fn pending_slot(
@@ -164,8 +242,19 @@ pub fn insert_slot(
         let SlotDeposit {
             slots: deposit_slots,
             attempting,
+            inserted,
+            ..
         } = deposit.as_mut();
 
+        // Forget items that left their slot since.
+        inserted.retain(|item| {
+            deposit_slots.iter().any(|slot| {
+                slots
+                    .get(*slot)
+                    .is_ok_and(|(slot, _)| slot.containing == Some(*item))
+            })
+        });
+
         if attempting.len() == 0 {
             continue;
         }
@@ -182,6 +271,7 @@ pub fn insert_slot(
                     if *slottable == Slottable::Free {
                         info!("slotting {:?}", names.get(next_item).unwrap());
                         slot.containing = Some(next_item);
+                        inserted.push(next_item);
 
                         grace_period.0 = Timer::new(Duration::from_secs(1), TimerMode::Once);
                         *slottable = Slottable::Slotted;
@@ -193,66 +283,174 @@ pub fn insert_slot(
     }
 }
 
+/// Slot whose joint was built for a locked deposit.
+#[derive(Default, Debug, Copy, Clone, Component)]
+pub struct HardenedSlot;
+
+pub fn slot_joint(item: Entity, settings: &SlotSettings, locked: bool) -> ImpulseJoint {
+    let strength = settings.0.strength * SLOT_STIFFNESS;
+    let damping = settings.0.damp_ratio * SLOT_DAMPING;
+    let max_force = if locked {
+        LOCKED_SLOT_MAX_FORCE
+    } else {
+        SLOT_MAX_FORCE
+    };
+
+    let slot_joint = GenericJointBuilder::new(JointAxesMask::empty())
+        .motor_position(JointAxis::LinX, 0.0, strength, damping)
+        .motor_max_force(JointAxis::LinX, max_force)
+        .motor_position(JointAxis::LinY, 0.0, strength, damping)
+        .motor_max_force(JointAxis::LinY, max_force)
+        .motor_position(JointAxis::LinZ, 0.0, strength, damping)
+        .motor_max_force(JointAxis::LinZ, max_force)
+        .motor_position(JointAxis::AngX, 0.0, strength, damping)
+        .motor_position(JointAxis::AngY, 0.0, strength, damping)
+        .motor_position(JointAxis::AngZ, 0.0, strength, damping)
+        .build();
+    ImpulseJoint::new(item, TypedJoint::GenericJoint(slot_joint))
+}
+
 pub fn slot_joints(
     mut commands: Commands,
-    slots: Query<(Entity, &Slot)>,
-    joints: Query<(&ImpulseJoint, &RapierImpulseJointHandle)>,
-    //rapier: Res<RapierContext>,
-    //names: Query<&Name>,
+    deposits: Query<&SlotDeposit>,
+    slots: Query<(Entity, &Slot, Option<Ref<SlotSettings>>, Has<HardenedSlot>)>,
+    joints: Query<&ImpulseJoint>,
 ) {
-    for (entity, slot) in &slots {
+    let locked = deposits
+        .iter()
+        .filter(|deposit| deposit.locked())
+        .flat_map(|deposit| deposit.slots.iter().copied())
+        .collect::<HashSet<_>>();
+
+    for (entity, slot, settings, hardened) in &slots {
         match slot.containing {
             Some(item) => {
-                if let Ok((_joint, _handle)) = joints.get(entity) {
-                    /*
-                    let name = names
-                        .get(entity)
-                        .map(|name| name.as_str())
-                        .unwrap_or("blah");
-
-                    let joint = rapier.impulse_joints.get(handle.0).unwrap();
-                    info!(
-                        "slot: {:?}, impulse: {:?}, motors: {:?}",
-                        name,
-                        joint.impulses,
-                        joint
-                            .data
-                            .motors
-                            .iter()
-                            .map(|motor| motor.impulse)
-                            .collect::<Vec<_>>()
-                    );
-                    */
+                let locked = locked.contains(&entity);
+                let retuned = settings
+                    .as_ref()
+                    .is_some_and(|settings| settings.is_changed());
+                if joints.contains(entity) && hardened == locked && !retuned {
+                    continue;
+                }
+
+                let mut slot_commands = commands.entity(entity);
+                let settings = settings
+                    .map(|settings| (*settings).clone())
+                    .unwrap_or_default();
+                slot_commands.insert(slot_joint(item, &settings, locked));
+                if locked {
+                    slot_commands.insert(HardenedSlot);
                 } else {
-                    let strength = 5000.0;
-                    let damping = 5.0;
-                    /*
-                                    let mut slot_joint = FixedJointBuilder::new()
-                                        .build();
-                    */
-                    let slot_joint = GenericJointBuilder::new(JointAxesMask::empty())
-                        .motor_position(JointAxis::LinX, 0.0, strength, damping)
-                        .motor_max_force(JointAxis::LinX, 300.0)
-                        .motor_position(JointAxis::LinY, 0.0, strength, damping)
-                        .motor_max_force(JointAxis::LinY, 300.0)
-                        .motor_position(JointAxis::LinZ, 0.0, strength, damping)
-                        .motor_max_force(JointAxis::LinZ, 300.0)
-                        .motor_position(JointAxis::AngX, 0.0, strength, damping)
-                        .motor_position(JointAxis::AngY, 0.0, strength, damping)
-                        .motor_position(JointAxis::AngZ, 0.0, strength, damping)
-                        .build();
-                    commands
-                        .entity(entity)
-                        .insert(ImpulseJoint::new(item, TypedJoint::GenericJoint(slot_joint)));
+                    slot_commands.remove::<HardenedSlot>();
                 }
             }
             None => {
-                commands.entity(entity).remove::<ImpulseJoint>();
+                commands
+                    .entity(entity)
+                    .remove::<(ImpulseJoint, HardenedSlot)>();
             }
         }
     }
 }
 
+/// Let go of items once their slot's spring can't keep up, see [`SLOT_BREAK_RATIO`].
+///
+/// Freshly slotted items get their [`SlotGracePeriod`] to settle in first.
+pub fn break_slots(
+    rapier_ctx: Res<RapierContext>,
+    mut slots: Query<(&mut Slot, &SlotGracePeriod, &RapierImpulseJointHandle)>,
+    mut slottables: Query<&mut Slottable>,
+    names: Query<DebugName>,
+) {
+    // Motor impulses are what the last substep put out, not the whole tick.
+    let dt = crate::TICK_RATE.as_secs_f32() / super::PHYSICS_SUBSTEPS as f32;
+    for (mut slot, grace_period, handle) in &mut slots {
+        let Some(item) = slot.containing else {
+            continue;
+        };
+        if !grace_period.0.finished() {
+            continue;
+        }
+        let Some(joint) = rapier_ctx.impulse_joints.get(handle.0) else {
+            continue;
+        };
+
+        if !slot_strained(&joint.data, dt) {
+            continue;
+        }
+
+        info!("{:?} broke free of its slot", names.get(item).ok());
+        slot.containing = None;
+        if let Ok(mut slottable) = slottables.get_mut(item) {
+            *slottable = Slottable::Free;
+        }
+    }
+}
+
+/// Whether any linear motor of a slot joint put out [`SLOT_BREAK_RATIO`] of its
+/// max force over the last solver step of `dt` seconds.
+pub fn slot_strained(joint: &RawGenericJoint, dt: f32) -> bool {
+    let linear = [JointAxis::LinX, JointAxis::LinY, JointAxis::LinZ];
+    linear.iter().any(|axis| {
+        let motor = &joint.motors[*axis as usize];
+        motor.max_force.is_finite()
+            && motor.impulse.abs() >= motor.max_force * dt * SLOT_BREAK_RATIO
+    })
+}
+
+/// Groups a collider had before the slot its item is in got locked.
+#[derive(Debug, Copy, Clone, Component)]
+pub struct LockedGroups(pub Option<CollisionGroups>);
+
+/// Move the colliders of items in locked slots to [`SLOT_GROUPING`] and back
+/// once they are unlocked or leave.
+///
+/// Child colliders are moved along with the item's own.
+pub fn slot_lock_groups(
+    mut commands: Commands,
+    deposits: Query<&SlotDeposit>,
+    slots: Query<&Slot>,
+    children: Query<&Children>,
+    colliders: Query<Option<&CollisionGroups>, With<Collider>>,
+    locked_colliders: Query<(Entity, &LockedGroups)>,
+) {
+    let locked = deposits
+        .iter()
+        .filter(|deposit| deposit.locked())
+        .flat_map(|deposit| deposit.slots.iter())
+        .filter_map(|slot| slots.get(*slot).ok()?.containing)
+        .flat_map(|item| std::iter::once(item).chain(children.iter_descendants(item)))
+        .filter(|entity| colliders.contains(*entity))
+        .collect::<HashSet<_>>();
+
+    for collider in &locked {
+        if locked_colliders.contains(*collider) {
+            continue;
+        }
+
+        let previous = colliders.get(*collider).ok().flatten().copied();
+        commands
+            .entity(*collider)
+            .try_insert((LockedGroups(previous), SLOT_GROUPING));
+    }
+
+    for (collider, previous) in &locked_colliders {
+        if locked.contains(&collider) {
+            continue;
+        }
+
+        // Might have been used up by whatever processed it.
+        let Some(mut collider_commands) = commands.get_entity(collider) else {
+            continue;
+        };
+        collider_commands.remove::<LockedGroups>();
+        match previous.0 {
+            Some(groups) => collider_commands.try_insert(groups),
+            None => collider_commands.remove::<CollisionGroups>(),
+        };
+    }
+}
+
 pub struct SlotPlugin;
 impl Plugin for SlotPlugin {
     fn build(&self, app: &mut App) {
@@ -260,7 +458,10 @@ impl Plugin for SlotPlugin {
             .register_type::<Option<Entity>>()
             .register_type::<springy::Spring>()
             .register_type::<bevy::time::TimerMode>()
-            .register_type::<SlotSettings>();
+            .register_type::<SlotSettings>()
+            .register_type::<Slottable>()
+            .register_type::<SlotLock>()
+            .register_type::<SlotGracePeriod>();
 
         app.add_systems(
             FixedUpdate,
@@ -268,9 +469,92 @@ impl Plugin for SlotPlugin {
                 pending_slot,
                 insert_slot.after(pending_slot),
                 tick_grace_period.before(insert_slot),
+                break_slots.after(tick_grace_period).before(insert_slot),
                 slot_joints.after(insert_slot),
+                slot_lock_groups.after(insert_slot),
             )
                 .before(PhysicsSet::SyncBackend),
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Slot joint whose motors all pushed with `impulse` last step.
+    fn pushed(locked: bool, impulse: f32) -> RawGenericJoint {
+        let mut joint = slot_joint(Entity::PLACEHOLDER, &SlotSettings::default(), locked)
+            .data
+            .as_ref()
+            .raw;
+        for motor in &mut joint.motors {
+            motor.impulse = impulse;
+        }
+        joint
+    }
+
+    #[test]
+    fn breaks_near_max_force() {
+        let dt = crate::TICK_RATE.as_secs_f32() / crate::physics::PHYSICS_SUBSTEPS as f32;
+        let threshold = SLOT_MAX_FORCE * dt * SLOT_BREAK_RATIO;
+
+        assert!(!slot_strained(&pushed(false, threshold * 0.9), dt));
+        assert!(slot_strained(&pushed(false, threshold), dt));
+        assert!(slot_strained(&pushed(false, -threshold), dt));
+        assert!(
+            !slot_strained(&pushed(true, threshold), dt),
+            "locked slots hold on harder"
+        );
+    }
+
+    #[test]
+    fn pulled_items_break_free() {
+        use crate::{headless::HeadlessApp, physics::RigidBodyBundle};
+
+        let mut app = HeadlessApp::new();
+        let item = app
+            .world_mut()
+            .spawn((
+                TransformBundle::default(),
+                RigidBodyBundle::dynamic(),
+                Collider::ball(0.2),
+                GravityScale(0.0),
+                Slottable::Slotted,
+            ))
+            .id();
+        let slot = app
+            .world_mut()
+            .spawn((
+                TransformBundle::default(),
+                RigidBody::Fixed,
+                SlotBundle {
+                    slot: Slot {
+                        containing: Some(item),
+                    },
+                    settings: SlotSettings::default(),
+                    grace: SlotGracePeriod::default(),
+                },
+            ))
+            .id();
+
+        // Let the grace period run out, nothing is pulling yet.
+        app.step(80);
+        assert_eq!(
+            app.world().get::<Slot>(slot).unwrap().containing,
+            Some(item)
+        );
+
+        app.world_mut()
+            .get_mut::<ExternalForce>(item)
+            .unwrap()
+            .force = Vec3::X * SLOT_MAX_FORCE * 2.0;
+        app.step(30);
+        assert_eq!(app.world().get::<Slot>(slot).unwrap().containing, None);
+        assert_eq!(
+            *app.world().get::<Slottable>(item).unwrap(),
+            Slottable::Free
+        );
+        assert!(app.world().get::<ImpulseJoint>(slot).is_none());
+    }
+}
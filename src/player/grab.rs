@@ -17,8 +17,6 @@ impl Plugin for GrabPlugin {
             .register_type::<Grabbed>()
             .register_type::<Grabbing>();
 
-        app.add_systems(Update, auto_aim_debug_lines);
-
         app.add_systems(
             FixedUpdate,
             (
@@ -72,8 +72,18 @@ pub struct PlayerNeck(pub Entity);
 
 #[derive(Event, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum PlayerEvent {
-    Spawn { id: u64 },
-    SetupLocal { id: u64 },
+    Spawn {
+        id: u64,
+    },
+    SetupLocal {
+        id: u64,
+    },
+    /// Build a player on an entity the server told us about, `local` if it's ours.
+    Replicated {
+        id: u64,
+        entity: Entity,
+        local: bool,
+    },
 }
 
 pub fn setup_player(
@@ -84,219 +94,300 @@ pub fn setup_player(
 
     //asset_server: ResMut<AssetServer>,
     mut player_reader: EventReader<PlayerEvent>,
-    //server: Option<ResMut<RenetServer>>,
 ) {
     for (event, id) in player_reader.read_with_id() {
         info!("player event {:?}: {:?}", id, event);
-        match event {
-            &PlayerEvent::SetupLocal { id: _ } => {
+        match *event {
+            PlayerEvent::SetupLocal { id: _ } => {
                 //let player_entity = *lobby.players.get(&id).expect("Expected a player");
                 //info!("setting up local entity: {:?}", player_entity);
             }
-            &PlayerEvent::Spawn { id } => {
+            PlayerEvent::Spawn { id } => {
                 info!("spawning player {}", id);
-                let global_transform = GlobalTransform::from(Transform::from_xyz(-15.0, 15.0, 0.0));
-
-                let player_height = 1.0;
-                let player_radius = 0.3;
-                // Spawn player cube
-                let player_entity = commands
-                    .spawn(ControllerBundle {
-                        controller: Controller {
-                            movement: Movement {
-                                acceleration: Strength::Scaled(50.0),
-                                max_speed: 7.0,
-                                force_scale: ForceScale::Up,
-                                ..default()
-                            },
-                            gravity: Gravity {
-                                acceleration: -15.0,
-                                up_vector: Vec3::Y,
-                            },
-                            ground_caster: GroundCaster {
-                                unstable_ground_angle: 45.0 * (PI / 180.0),
-                                max_ground_angle: 75.0 * (PI / 180.0),
-                                exclude_from_ground: HashSet::new(),
-                                cast_collider: Some(Collider::ball(player_radius)),
-                                cast_origin: Vec3::new(0., 0., 0.),
-                                cast_length: 2.0,
-                                ..default()
-                            },
-                            float: Float {
-                                min_offset: -0.3,
-                                max_offset: 0.05,
-                                distance: 2.0,
-                                spring: Spring {
-                                    strength: SpringStrength::AngularFrequency(20.0),
-                                    damping: 0.9,
-                                },
-                            },
-                            upright: Upright {
-                                spring: Spring {
-                                    strength: SpringStrength::AngularFrequency(30.0),
-                                    damping: 0.9,
-                                },
-                                forward_vector: None,
-                            },
-                            jump: Jump {
-                                initial_force: 150.0,
-                                ..default()
-                            },
-                            force_settings: ForceSettings {
-                                //opposing_movement_force_scale: 0.01,
-                                opposing_movement_force_scale: 0.0,
-                                opposing_force_scale: 1.0,
-                            },
-                            ..default()
-                        },
-                        rapier_physics: RapierPhysicsBundle {
-                            collider: Collider::capsule(
-                                Vec3::new(0.0, 0.0, 0.0),
-                                Vec3::new(0.0, player_height, 0.0),
-                                player_radius,
-                            ),
-                            ..default()
-                        },
-                        transform: global_transform.compute_transform(),
-                        global_transform: global_transform,
-                        ..default()
-                    })
-                    //.insert(crate::deposit::Value::new(500))
-                    .insert(ColliderMassProperties::Density(0.5))
-                    .insert(PlayerInput::default())
-                    .insert(Inventory::default())
-                    .insert(Player { id: id })
-                    .insert(Name::new(format!("Player {}", id.to_string())))
-                    .insert(ConnectedEntities::default())
-                    .insert(CharacterEntities::default())
-                    .insert(ContactFilter::default())
-                    .insert(ReadMassProperties::default())
-                    //.insert(Loader::<Mesh>::new("scenes/gltfs/boi.glb#Mesh0/Primitive0"))
-                    .insert(crate::physics::PLAYER_GROUPING)
-                    .insert(LookTransform::default())
-                    .insert(GrabSphere::default())
-                    .id();
-
-                let distance_from_body = player_radius + 0.10;
-
-                // for some body horror set this to > 2
-                let arms = 2;
-                let radius = distance_from_body;
-                for i in 0..arms {
-                    let step = (i as f32 / arms as f32) * std::f32::consts::TAU;
-                    let x = step.cos() * radius;
-                    let y = step.sin() * radius;
-
-                    attach_arm(
-                        &mut commands,
-                        &mut meshes,
-                        &mut materials,
-                        player_entity,
-                        global_transform.compute_transform(),
-                        Vec3::new(x, player_height, y),
-                        i,
-                    );
+                let player_entity = commands.spawn_empty().id();
+                build_player(
+                    &mut commands,
+                    &mut meshes,
+                    &mut materials,
+                    player_entity,
+                    id,
+                );
+                // Announcing the player to clients is handled by `network::server_announce_players`.
+            }
+            PlayerEvent::Replicated { id, entity, local } => {
+                info!("building replicated player {} on {:?}", id, entity);
+                if local {
+                    build_player(&mut commands, &mut meshes, &mut materials, entity, id);
+                } else {
+                    build_remote_player(&mut commands, &mut meshes, &mut materials, entity, id);
                 }
+            }
+        }
+    }
+}
 
-                let camera = commands
-                    .spawn(SpatialBundle {
-                        transform: Transform::from_translation(Vec3::new(0., 0., 4.))
-                            .looking_at(Vec3::ZERO, Vec3::Y),
-                        ..default()
-                    })
-                    .insert(Camera3dBundle {
-                        projection: PerspectiveProjection { ..default() }.into(),
-                        camera: Camera {
-                            order: 50,
-                            is_active: true,
-                            ..default()
-                        },
-                        ..default()
-                    })
-                    .insert((DepthPrepass, NormalPrepass))
-                    .insert(Fxaa {
-                        enabled: true,
-                        edge_threshold_min: Sensitivity::Extreme,
-                        edge_threshold: Sensitivity::Extreme,
-                    })
-                    .insert(AvoidIntersecting {
-                        dir: Vec3::Z,
-                        max_toi: 4.0,
-                        buffer: 0.05,
-                    })
-                    .insert(ZoomScroll {
-                        current: 8.0,
-                        scroll_sensitivity: -0.15,
-                        min: 4.0,
-                        max: 24.0,
-                    })
-                    .insert(ZoomScrollForToi)
-                    .insert(Name::new("Player Camera"))
-                    .id();
-
-                let head = commands
-                    .spawn((
-                        TransformBundle::from_transform(Transform::from_xyz(0., 1., 0.)),
-                        Name::new("Head"),
-                    ))
-                    .insert(Velocity::default())
-                    .id();
-
-                commands.entity(player_entity).push_children(&[head]);
-
-                let neck = commands
-                    .spawn(SpatialBundle::default())
-                    .insert(Neck)
-                    .insert(Name::new("Neck"))
-                    .insert(Attach::translation(head))
-                    .insert(Velocity::default())
-                    .id();
-
-                commands.entity(neck).push_children(&[camera]);
-                commands
-                    .entity(player_entity)
-                    .insert(PlayerCamera(camera))
-                    .insert(PlayerNeck(neck));
-
-                // We could send an InitState with all the players id and positions for the client
-                // but this is easier to do.
-
-                // Server-Client code is disabled for now
-                /*
-                lobby.players.insert(id, player_entity);
-                if let Some(ref mut server) = server {
-                    for (existing_id, existing_entity) in lobby.players.iter() {
-                        let message = bincode::serialize(&ServerMessage::PlayerConnected {
-                            id: *existing_id,
-                            entity: (*existing_entity).into(),
-                        })
-                        .unwrap();
-
-                        server.send_message(id, ServerChannel::Message.id(), message);
-                    }
-                }
+/// Build the body, arms and camera rig of a player on `player_entity`.
+pub fn build_player(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    player_entity: Entity,
+    id: u64,
+) {
+    let global_transform = GlobalTransform::from(Transform::from_xyz(-15.0, 15.0, 0.0));
+
+    let player_height = 1.0;
+    let player_radius = 0.3;
+    // Spawn player cube
+    commands
+        .entity(player_entity)
+        .insert(ControllerBundle {
+            controller: Controller {
+                movement: Movement {
+                    acceleration: Strength::Scaled(50.0),
+                    max_speed: 7.0,
+                    force_scale: ForceScale::Up,
+                    ..default()
+                },
+                gravity: Gravity {
+                    acceleration: -15.0,
+                    up_vector: Vec3::Y,
+                },
+                ground_caster: GroundCaster {
+                    unstable_ground_angle: 45.0 * (PI / 180.0),
+                    max_ground_angle: 75.0 * (PI / 180.0),
+                    exclude_from_ground: HashSet::new(),
+                    cast_collider: Some(Collider::ball(player_radius)),
+                    cast_origin: Vec3::new(0., 0., 0.),
+                    cast_length: 2.0,
+                    ..default()
+                },
+                float: Float {
+                    min_offset: -0.3,
+                    max_offset: 0.05,
+                    distance: 2.0,
+                    spring: Spring {
+                        strength: SpringStrength::AngularFrequency(20.0),
+                        damping: 0.9,
+                    },
+                },
+                upright: Upright {
+                    spring: Spring {
+                        strength: SpringStrength::AngularFrequency(30.0),
+                        damping: 0.9,
+                    },
+                    forward_vector: None,
+                },
+                jump: Jump {
+                    initial_force: 150.0,
+                    ..default()
+                },
+                force_settings: ForceSettings {
+                    //opposing_movement_force_scale: 0.01,
+                    opposing_movement_force_scale: 0.0,
+                    opposing_force_scale: 1.0,
+                },
+                ..default()
+            },
+            rapier_physics: RapierPhysicsBundle {
+                collider: Collider::capsule(
+                    Vec3::new(0.0, 0.0, 0.0),
+                    Vec3::new(0.0, player_height, 0.0),
+                    player_radius,
+                ),
+                ..default()
+            },
+            transform: global_transform.compute_transform(),
+            global_transform,
+            ..default()
+        })
+        //.insert(crate::deposit::Value::new(500))
+        .insert(ColliderMassProperties::Density(0.5))
+        .insert(PlayerInput::default())
+        .insert(Inventory::default())
+        .insert(Player { id })
+        .insert(Name::new(format!("Player {}", id)))
+        .insert(ConnectedEntities::default())
+        .insert(CharacterEntities::default())
+        .insert(ContactFilter::default())
+        .insert(ReadMassProperties::default())
+        //.insert(Loader::<Mesh>::new("scenes/gltfs/boi.glb#Mesh0/Primitive0"))
+        .insert(crate::physics::PLAYER_GROUPING)
+        .insert(LookTransform::default())
+        .insert(GrabSphere::default());
+
+    attach_arms(
+        commands,
+        meshes,
+        materials,
+        player_entity,
+        global_transform.compute_transform(),
+        player_radius,
+        player_height,
+    );
+
+    let camera = commands
+        .spawn(SpatialBundle {
+            transform: Transform::from_translation(Vec3::new(0., 0., 4.))
+                .looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        })
+        .insert(Camera3dBundle {
+            projection: PerspectiveProjection { ..default() }.into(),
+            camera: Camera {
+                order: 50,
+                is_active: true,
+                ..default()
+            },
+            ..default()
+        })
+        .insert((DepthPrepass, NormalPrepass))
+        .insert(Fxaa {
+            enabled: true,
+            edge_threshold_min: Sensitivity::Extreme,
+            edge_threshold: Sensitivity::Extreme,
+        })
+        .insert(AvoidIntersecting {
+            dir: Vec3::Z,
+            max_toi: 4.0,
+            buffer: 0.05,
+        })
+        .insert(ZoomScroll {
+            current: 8.0,
+            scroll_sensitivity: -0.15,
+            min: 4.0,
+            max: 24.0,
+        })
+        .insert(ZoomScrollForToi)
+        .insert(Name::new("Player Camera"))
+        .id();
 
-                if let Some(ref mut server) = server {
-                    let message = bincode::serialize(&ServerMessage::PlayerConnected {
-                        id: id,
-                        entity: player_entity.into(),
-                    })
-                    .unwrap();
-                    server.broadcast_message(ServerChannel::Message.id(), message);
-
-                    let message = bincode::serialize(&ServerMessage::AssignOwnership {
-                        entity: player_entity.into(),
-                    })
-                    .unwrap();
-                    server.send_message(id, ServerChannel::Message.id(), message);
-
-                    let message = bincode::serialize(&ServerMessage::SetPlayer { id }).unwrap();
-                    server.send_message(id, ServerChannel::Message.id(), message);
-                }
-                */
-            }
+    let head = commands
+        .spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0., 1., 0.)),
+            Name::new("Head"),
+        ))
+        .insert(Velocity::default())
+        .id();
+
+    commands.entity(player_entity).push_children(&[head]);
+
+    let neck = commands
+        .spawn(SpatialBundle::default())
+        .insert(Neck)
+        .insert(Name::new("Neck"))
+        .insert(Attach::translation(head))
+        .insert(Velocity::default())
+        .id();
+
+    commands.entity(neck).push_children(&[camera]);
+    commands
+        .entity(player_entity)
+        .insert(PlayerCamera(camera))
+        .insert(PlayerNeck(neck));
+}
+
+/// Build another client's player on `player_entity`, moved only by snapshots.
+///
+/// Its owner runs the controller, here the body is kinematic so physics doesn't
+/// fight the interpolation. There is no camera, the arms still hang off it.
+pub fn build_remote_player(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    player_entity: Entity,
+    id: u64,
+) {
+    let transform = Transform::from_xyz(-15.0, 15.0, 0.0);
+
+    let player_height = 1.0;
+    let player_radius = 0.3;
+    commands
+        .entity(player_entity)
+        .insert(SpatialBundle::from_transform(transform))
+        .insert(RigidBodyBundle::kinematic_position())
+        .insert(ColliderBundle {
+            collider: Collider::capsule(
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, player_height, 0.0),
+                player_radius,
+            ),
+            collision_groups: crate::physics::PLAYER_GROUPING,
+            ..default()
+        })
+        .insert(KinematicProxy)
+        .insert(Player { id })
+        .insert(Name::new(format!("Player {}", id)))
+        .insert(ConnectedEntities::default())
+        .insert(CharacterEntities::default())
+        .insert(ContactFilter::default())
+        .insert(LookTransform::default());
+
+    attach_arms(
+        commands,
+        meshes,
+        materials,
+        player_entity,
+        transform,
+        player_radius,
+        player_height,
+    );
+}
+
+/// Attach arms around the shoulders of a `player_radius` wide body.
+fn attach_arms(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    player_entity: Entity,
+    transform: Transform,
+    player_radius: f32,
+    player_height: f32,
+) {
+    let distance_from_body = player_radius + 0.10;
+
+    // for some body horror set this to > 2
+    let arms = 2;
+    let radius = distance_from_body;
+    for i in 0..arms {
+        let step = (i as f32 / arms as f32) * std::f32::consts::TAU;
+        let x = step.cos() * radius;
+        let y = step.sin() * radius;
+
+        attach_arm(
+            commands,
+            meshes,
+            materials,
+            player_entity,
+            transform,
+            Vec3::new(x, player_height, y),
+            i,
+        );
+    }
+}
+
+/// Despawn a player along with its arms and camera rig.
+pub fn despawn_player(
+    commands: &mut Commands,
+    player: Entity,
+    characters: &Query<(&CharacterEntities, Option<&PlayerNeck>)>,
+) {
+    let mut despawn = HashSet::new();
+    despawn.insert(player);
+
+    if let Ok((character, neck)) = characters.get(player) {
+        despawn.extend(character.iter());
+
+        // The neck is only attached, not parented.
+        if let Some(neck) = neck {
+            commands.entity(neck.0).despawn_recursive();
         }
     }
+
+    // `CharacterEntities` already covers the whole hierarchy.
+    for entity in despawn {
+        commands.entity(entity).despawn();
+    }
 }
 
 #[derive(Debug, Clone, Component)]
@@ -746,15 +837,15 @@ pub fn setup_ik(
         };
 
         let mesh_left_hand = if let Ok(found_entity) = find_entity(
-                &vec![
-                    "Pelvis".into(),
-                    "Spine1".into(),
-                    "Spine2".into(),
-                    "Collar.L".into(),
-                    "UpperArm.L".into(),
-                    "ForeArm.L".into(),
-                    "Hand.L".into(),
-                ],
+            &vec![
+                "Pelvis".into(),
+                "Spine1".into(),
+                "Spine2".into(),
+                "Collar.L".into(),
+                "UpperArm.L".into(),
+                "ForeArm.L".into(),
+                "Hand.L".into(),
+            ],
             entity,
             &children,
             &names,
@@ -780,11 +871,7 @@ pub fn setup_ik(
         let pole_target = commands
             .spawn(PbrBundle {
                 transform: Transform::from_xyz(-1.0, 0.4, -0.4),
-                mesh: meshes.add(
-                    Mesh::from(Sphere {
-                        radius: 0.05,
-                    })
-                ),
+                mesh: meshes.add(Mesh::from(Sphere { radius: 0.05 })),
                 material: materials.add(StandardMaterial {
                     base_color: css::GREEN.into(),
                     ..default()
@@ -806,9 +893,7 @@ pub fn setup_ik(
         let pole_target = commands
             .spawn(PbrBundle {
                 transform: Transform::from_xyz(1.0, 0.4, -0.4),
-                mesh: meshes.add(
-                    Mesh::from(Sphere::new(0.05)),
-                ),
+                mesh: meshes.add(Mesh::from(Sphere::new(0.05))),
                 material: materials.add(StandardMaterial {
                     base_color: css::GREEN.into(),
                     ..default()